@@ -0,0 +1,112 @@
+//! Benchmark of the network input encoding.
+//!
+//! Compares the previous per-square encoding (`Bitboard::get` for each square into a fresh tensor) with
+//! `planes_to_tensor`, which unpacks raw words, and `planes_to_tensor_into`, which also reuses the tensor.
+//!
+//! Run with `cargo bench --bench encode`.
+
+use std::hint::black_box;
+use std::slice;
+use std::time::{Duration, Instant};
+
+use cattus::chess::ChessGame;
+use cattus::game::player::PlayerRand;
+use cattus::game::{Bitboard, Game};
+use cattus::hex::HexGame;
+use cattus::net::encoder::EncodedGame;
+use cattus::net::{planes_to_tensor, planes_to_tensor_into};
+use ndarray::Array4;
+
+const BATCH_SIZE: usize = 64;
+const MEASURE_TIME: Duration = Duration::from_secs(2);
+
+fn main() {
+    bench_encode::<ChessGame>("chess");
+    bench_encode::<HexGame<11>>("hex11");
+}
+
+fn bench_encode<G: EncodedGame>(game_name: &str) {
+    let encoder = G::default_encoder();
+    let positions = random_positions::<G>(BATCH_SIZE * 16);
+    let samples = positions
+        .iter()
+        .map(|pos| encoder.encode_to_vec(slice::from_ref(pos)))
+        .collect::<Vec<_>>();
+    let planes_num = samples[0].len();
+    let batches = samples.chunks_exact(BATCH_SIZE).collect::<Vec<_>>();
+
+    let naive = measure(&batches, |batch| {
+        black_box(naive_planes_to_tensor::<G>(batch, BATCH_SIZE));
+    });
+    let unpack = measure(&batches, |batch| {
+        black_box(planes_to_tensor::<G>(batch, BATCH_SIZE));
+    });
+    let mut tensor = Array4::zeros((BATCH_SIZE, planes_num, G::BOARD_SIZE, G::BOARD_SIZE));
+    let reuse = measure(&batches, |batch| {
+        planes_to_tensor_into::<G>(batch, &mut tensor);
+        black_box(&tensor);
+    });
+
+    let per_position = |d: Duration| d.as_nanos() as f64 / BATCH_SIZE as f64;
+    println!("{game_name} (batch size {BATCH_SIZE}, {planes_num} planes), time per position:");
+    println!("  per-square get:        {:>8.1}ns", per_position(naive));
+    println!(
+        "  word unpack:           {:>8.1}ns ({:.2}x)",
+        per_position(unpack),
+        naive.as_secs_f64() / unpack.as_secs_f64()
+    );
+    println!(
+        "  word unpack + reuse:   {:>8.1}ns ({:.2}x)",
+        per_position(reuse),
+        naive.as_secs_f64() / reuse.as_secs_f64()
+    );
+}
+
+/// Run the function on the batches repeatedly and return the mean time per batch
+fn measure<B>(batches: &[B], mut f: impl FnMut(&B)) -> Duration {
+    /* warmup */
+    for batch in batches {
+        f(batch);
+    }
+
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < MEASURE_TIME {
+        for batch in batches {
+            f(batch);
+        }
+        iterations += batches.len() as u32;
+    }
+    start.elapsed() / iterations
+}
+
+fn random_positions<G: Game>(num: usize) -> Vec<G::Position> {
+    let mut positions = Vec::with_capacity(num);
+    let mut seed = 0;
+    while positions.len() < num {
+        let mut player1 = PlayerRand::from_seed(seed);
+        let mut player2 = PlayerRand::from_seed(seed + 1);
+        seed += 2;
+        let mut game = G::new();
+        game.play_until_over(&mut player1, &mut player2);
+        positions.extend(game.pos_history().iter().cloned());
+    }
+    positions.truncate(num);
+    positions
+}
+
+/// The encoding prior to the raw words unpacking, kept as a baseline
+fn naive_planes_to_tensor<G: Game>(samples: &[Vec<G::Bitboard>], batch_size: usize) -> Array4<f32> {
+    let planes_num = samples[0].len();
+    let mut tensor = Array4::<f32>::zeros((batch_size, planes_num, G::BOARD_SIZE, G::BOARD_SIZE));
+    for (b, sample) in samples.iter().enumerate() {
+        for (c, plane) in sample.iter().enumerate() {
+            for h in 0..G::BOARD_SIZE {
+                for w in 0..G::BOARD_SIZE {
+                    tensor[(b, c, h, w)] = if plane.get(h * G::BOARD_SIZE + w) { 1.0 } else { 0.0 };
+                }
+            }
+        }
+    }
+    tensor
+}
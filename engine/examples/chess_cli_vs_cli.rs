@@ -1,4 +1,5 @@
 use cattus::chess::cli::{cli_print_chess_board, ChessPlayerCmd};
+use cattus::chess::pgn::PgnGame;
 use cattus::chess::ChessGame;
 use cattus::game::{Game, GameColor};
 
@@ -20,4 +21,6 @@ fn main() {
     let (final_pos, winner) = game.play_until_over(&mut player1, &mut player2);
     println!("The winner is: {}, details below:", color_to_str(winner));
     cli_print_chess_board(&final_pos);
+    println!();
+    print!("{}", PgnGame::from_game(&game));
 }
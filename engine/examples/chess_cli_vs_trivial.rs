@@ -1,5 +1,6 @@
 use cattus::chess::cli::{cli_print_chess_board, ChessPlayerCmd};
 use cattus::chess::net::trivial::TrivialNet;
+use cattus::chess::pgn::PgnGame;
 use cattus::chess::ChessGame;
 use cattus::game::{Game, GameColor};
 use cattus::mcts::{MctsParams, MctsPlayer};
@@ -24,4 +25,6 @@ fn main() {
     let (final_pos, winner) = game.play_until_over(&mut player2, &mut player1);
     println!("The winner is: {}, details below:", color_to_str(winner));
     cli_print_chess_board(&final_pos);
+    println!();
+    print!("{}", PgnGame::from_game(&game));
 }
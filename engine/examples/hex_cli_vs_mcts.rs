@@ -1,5 +1,6 @@
+use cattus::net::encoder::EncodedGame;
 use cattus::net::model::InferenceConfig;
-use cattus::net::NNetwork;
+use cattus::net::{NNetwork, NNetworkParams};
 use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -8,7 +9,7 @@ use cattus::game::{Game, GameColor};
 use cattus::hex::cli::{cli_print_hex_board, HexPlayerCmd};
 use cattus::hex::HexGame;
 use cattus::mcts::cache::ValueFuncCache;
-use cattus::mcts::{MctsParams, MctsPlayer, TemperaturePolicy};
+use cattus::mcts::{MctsParams, MctsPlayer, MovesLeftParams, TemperaturePolicy};
 
 #[derive(Parser, Debug)]
 #[clap(about, long_about = None)]
@@ -40,7 +41,9 @@ fn run_main<const BOARD_SIZE: usize>(args: Args) {
     let value_func = Arc::new(NNetwork::<HexGame<BOARD_SIZE>>::new(
         &args.model_path,
         InferenceConfig::default(),
-        args.batch_size,
+        HexGame::<BOARD_SIZE>::default_encoder(),
+        HexGame::<BOARD_SIZE>::default_policy_map(),
+        NNetworkParams::new(args.batch_size),
         Some(cache),
     ));
     let mut player2 = MctsPlayer::new(MctsParams {
@@ -49,7 +52,10 @@ fn run_main<const BOARD_SIZE: usize>(args: Args) {
         temperature: TemperaturePolicy::constant(1.0),
         prior_noise_alpha: args.prior_noise_alpha,
         prior_noise_epsilon: args.prior_noise_epsilon,
+        contempt: 0.0,
+        moves_left: MovesLeftParams::default(),
         value_func,
+        tablebase: None,
     });
 
     let mut game = HexGame::<BOARD_SIZE>::new();
@@ -1,8 +1,9 @@
 use cattus::hex::uxi;
 use cattus::hex::HexGameStandard;
 use cattus::mcts::{MctsParams, MctsPlayer};
+use cattus::net::encoder::EncodedGame;
 use cattus::net::model::InferenceConfig;
-use cattus::net::NNetwork;
+use cattus::net::{NNetwork, NNetworkParams};
 use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -26,7 +27,9 @@ fn main() {
     let value_func = Arc::new(NNetwork::<HexGameStandard>::new(
         &args.model_path,
         InferenceConfig::default(),
-        args.batch_size,
+        HexGameStandard::default_encoder(),
+        HexGameStandard::default_policy_map(),
+        NNetworkParams::new(args.batch_size),
         None,
     ));
     let player = Box::new(MctsPlayer::new(MctsParams::new(args.sim_num, value_func)));
@@ -1,7 +1,8 @@
 use cattus::game::Game;
 use cattus::mcts::{MctsParams, MctsPlayer};
+use cattus::net::encoder::EncodedGame;
 use cattus::net::model::InferenceConfig;
-use cattus::net::NNetwork;
+use cattus::net::{NNetwork, NNetworkParams};
 use cattus::ttt::cli::{cli_print_ttt_board, TttPlayerCmd};
 use cattus::ttt::{color_to_str, TttGame};
 use clap::Parser;
@@ -25,7 +26,9 @@ fn main() {
     let value_func = Arc::new(NNetwork::new(
         &args.model_path,
         InferenceConfig::default(),
-        args.batch_size,
+        TttGame::default_encoder(),
+        TttGame::default_policy_map(),
+        NNetworkParams::new(args.batch_size),
         None,
     ));
     let mut player1 = MctsPlayer::new(MctsParams::new(1000, value_func));
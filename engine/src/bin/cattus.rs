@@ -1,6 +1,8 @@
 use cattus::chess::net::stockfish::StockfishNet;
+use cattus::chess::syzygy::Syzygy;
 use cattus::chess::uci::UCI;
-use cattus::mcts::{MctsParams, TemperaturePolicy};
+use cattus::mcts::tablebase::Tablebase;
+use cattus::mcts::{MctsParams, MovesLeftParams, TemperaturePolicy};
 use cattus::net::model::InferenceConfig;
 use clap::Parser;
 use std::path::PathBuf;
@@ -39,6 +41,8 @@ struct MctsConfig {
     prior_noise_epsilon: f32,
     #[allow(unused)]
     cache_size: usize,
+    #[serde(default)]
+    tablebase_path: Option<PathBuf>,
 }
 
 fn main() -> std::io::Result<()> {
@@ -60,7 +64,12 @@ fn main() -> std::io::Result<()> {
         temperature,
         prior_noise_alpha: config.mcts.prior_noise_alpha,
         prior_noise_epsilon: config.mcts.prior_noise_epsilon,
+        contempt: 0.0,
+        moves_left: MovesLeftParams::default(),
         value_func: Arc::new(StockfishNet),
+        tablebase: config.mcts.tablebase_path.map(|path| {
+            Arc::new(Syzygy::new(&path).expect("failed to read tablebase directory")) as Arc<dyn Tablebase<_>>
+        }),
     };
 
     let mut uci = UCI::new(player_params);
@@ -0,0 +1,158 @@
+use cattus::chess::epd::{self, EpdEntry};
+use cattus::chess::net::trivial::TrivialNet;
+use cattus::chess::ChessGame;
+use cattus::game::player::GamePlayer;
+use cattus::mcts::cache::ValueFuncCache;
+use cattus::mcts::value_func::ValueFunction;
+use cattus::mcts::{MctsParams, MctsPlayer, TemperaturePolicy};
+use cattus::net::encoder::EncodedGame;
+use cattus::net::model::InferenceConfig;
+use cattus::net::{NNetwork, NNetworkParams};
+use clap::Parser;
+use itertools::Itertools;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Run a test suite of EPD positions and report the positions solved by the engine
+#[derive(Parser, Debug)]
+#[clap(about, long_about = None)]
+struct Args {
+    /// EPD file with the suite positions, solved by one of their 'bm' moves and none of their 'am' moves
+    #[clap(long)]
+    epd_file: PathBuf,
+    #[clap(long, value_enum, default_value = "trivial")]
+    value_func: ValueFuncArg,
+    /// The model used by the "model" value function
+    #[clap(long)]
+    model_path: Option<PathBuf>,
+    /// The inference config of the model as JSON, for example '{"engine": "onnx-tract"}'.
+    /// Defaults to the first implementation available in this build.
+    #[clap(long)]
+    inference: Option<String>,
+    /// The encoder id of the model, the default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
+    /// The policy map id of the model, the default policy map if not set
+    #[clap(long)]
+    policy_map: Option<String>,
+    #[clap(long, default_value = "1")]
+    batch_size: usize,
+    /// Number of MCTS simulations per position
+    #[clap(long, default_value = "800")]
+    sim_num: u32,
+    /// Exit with a failure code if fewer positions are solved
+    #[clap(long)]
+    min_solved: Option<usize>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ValueFuncArg {
+    Trivial,
+    #[cfg(feature = "stockfish")]
+    Stockfish,
+    Model,
+}
+
+fn main() -> std::io::Result<()> {
+    cattus::util::init_globals();
+
+    let args = Args::parse();
+
+    let entries = epd::parse(&std::fs::read_to_string(&args.epd_file)?).expect("failed to parse EPD file");
+
+    let value_func: Arc<dyn ValueFunction<ChessGame>> = match args.value_func {
+        ValueFuncArg::Trivial => Arc::new(TrivialNet),
+        #[cfg(feature = "stockfish")]
+        ValueFuncArg::Stockfish => Arc::new(cattus::chess::net::stockfish::StockfishNet),
+        ValueFuncArg::Model => {
+            let model_path = args
+                .model_path
+                .as_ref()
+                .expect("model value function requires a model path");
+            let inference_cfg: InferenceConfig = match &args.inference {
+                Some(cfg) => serde_json::from_str(cfg).expect("invalid inference config"),
+                None => InferenceConfig::default(),
+            };
+            Arc::new(NNetwork::new(
+                model_path,
+                inference_cfg,
+                args.encoder
+                    .as_deref()
+                    .map_or_else(ChessGame::default_encoder, ChessGame::encoder),
+                args.policy_map
+                    .as_deref()
+                    .map_or_else(ChessGame::default_policy_map, ChessGame::policy_map),
+                NNetworkParams::new(args.batch_size),
+                Some(Arc::new(ValueFuncCache::new(100_000))),
+            ))
+        }
+    };
+    let mut params = MctsParams::new(args.sim_num, value_func);
+    /* Always play the most visited move */
+    params.temperature = TemperaturePolicy::constant(0.0);
+
+    let mut failures: Vec<(usize, &EpdEntry, String)> = Vec::new();
+    let mut total_time = Duration::ZERO;
+    for (idx, entry) in entries.iter().enumerate() {
+        /* Each position is searched from scratch */
+        let mut player = MctsPlayer::new(params.clone());
+        let search_start_time = Instant::now();
+        let m = player.next_move(&[entry.pos]).expect("EPD position has no legal moves");
+        let time = search_start_time.elapsed();
+        total_time += time;
+
+        let solved = entry.is_solved_by(m);
+        let san = m.to_san(&entry.pos);
+        println!(
+            "{} {}: {} ({} ms)",
+            entry_name(idx, entry),
+            if solved { "solved" } else { "failed" },
+            san,
+            time.as_millis()
+        );
+        if !solved {
+            failures.push((idx, entry, san));
+        }
+    }
+
+    let solved_num = entries.len() - failures.len();
+    println!();
+    println!("Solved: {}/{}", solved_num, entries.len());
+    if !entries.is_empty() {
+        println!("Average time: {} ms", total_time.as_millis() / entries.len() as u128);
+    }
+    if !failures.is_empty() {
+        println!("Failures:");
+        for (idx, entry, san) in failures {
+            let moves_str = |moves: &[cattus::chess::ChessMove]| moves.iter().map(|m| m.to_san(&entry.pos)).join(" ");
+            let mut expected = String::new();
+            if !entry.best_moves.is_empty() {
+                expected += &format!(" bm {}", moves_str(&entry.best_moves));
+            }
+            if !entry.avoid_moves.is_empty() {
+                expected += &format!(" am {}", moves_str(&entry.avoid_moves));
+            }
+            println!(
+                "  {} '{}': played {},{}",
+                entry_name(idx, entry),
+                entry.pos.fen(),
+                san,
+                expected
+            );
+        }
+    }
+
+    if let Some(min_solved) = args.min_solved
+        && solved_num < min_solved
+    {
+        eprintln!("Solved {} positions, less than the required {}", solved_num, min_solved);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The position identifier, or its index in the suite if it has none
+fn entry_name(idx: usize, entry: &EpdEntry) -> String {
+    entry.id.clone().unwrap_or_else(|| format!("#{}", idx + 1))
+}
@@ -0,0 +1,53 @@
+use cattus::net::model::{InferenceConfig, Model};
+use cattus::net::remote::{RemoteAddress, RemoteServer};
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Run a model for many engine processes, which use it with the "remote" inference config
+#[derive(Parser, Debug)]
+#[clap(about, long_about = None)]
+struct Args {
+    #[clap(long)]
+    model_path: PathBuf,
+    /// The inference config of the model as JSON, for example '{"engine": "onnx-tract"}'.
+    /// Defaults to the first implementation available in this build.
+    #[clap(long)]
+    inference: Option<String>,
+    /// Either `unix:<path>` for a Unix domain socket or `<host>:<port>` for TCP
+    #[clap(long)]
+    address: String,
+    /// The shape of a single sample of the model input, without the batch dimension, for example '20,8,8'
+    #[clap(long, value_delimiter = ',', required = true)]
+    input_shape: Vec<usize>,
+    /// The batch size of the model
+    #[clap(long)]
+    batch_size: usize,
+    /// Maximum time a request waits for its batch to fill
+    #[clap(long, default_value = "5")]
+    batch_deadline_ms: u64,
+}
+
+fn main() -> std::io::Result<()> {
+    cattus::util::init_globals();
+
+    let args = Args::parse();
+
+    let inference_cfg: InferenceConfig = match &args.inference {
+        Some(cfg) => serde_json::from_str(cfg).expect("invalid inference config"),
+        None => InferenceConfig::default(),
+    };
+    assert!(
+        !matches!(inference_cfg, InferenceConfig::Remote(_)),
+        "the inference server can't use a remote model"
+    );
+    let model = Model::new(&args.model_path, inference_cfg);
+
+    let server = RemoteServer::new(
+        model,
+        args.input_shape,
+        args.batch_size,
+        Duration::from_millis(args.batch_deadline_ms),
+    );
+    server.serve(&RemoteAddress::parse(&args.address))
+}
@@ -1,6 +1,7 @@
 use crate::chess::{ChessGame, ChessMove, ChessPosition};
 use crate::game::player::GamePlayer;
-use crate::game::Game;
+use crate::game::{Game, Position};
+use itertools::Itertools;
 use std::io;
 
 pub struct ChessPlayerCmd;
@@ -13,13 +14,21 @@ impl GamePlayer<ChessGame> for ChessPlayerCmd {
 
             match ChessMove::from_san(position, line.trim()) {
                 Err(e) => {
-                    println!("invalid number: {}", e);
+                    let legal_moves = position.legal_moves().map(|m| m.to_san(position)).collect_vec();
+                    println!("invalid move: {}, legal moves: {}", e, legal_moves.join(" "));
                     None
                 }
                 Ok(x) => Some(x),
             }
         };
 
+        if let [.., prev_position, _] = pos_history {
+            let last_move = prev_position
+                .legal_moves()
+                .find(|m| prev_position.moved_position(*m) == *position)
+                .unwrap();
+            println!("Last move: {}", last_move.to_san(prev_position));
+        }
         println!("Current position:");
         cli_print_chess_board(position);
 
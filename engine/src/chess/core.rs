@@ -5,7 +5,7 @@ use std::fmt::{self, Display};
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
-use crate::game::{Bitboard, Game, GameColor, GameStatus, Move, Position};
+use crate::game::{unpack_bits, Bitboard, Game, GameColor, GameStatus, Move, Position};
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct ChessMove {
@@ -18,7 +18,20 @@ impl ChessMove {
     }
 
     pub fn from_san(pos: &ChessPosition, move_str: &str) -> Result<Self, chess::Error> {
-        chess::ChessMove::from_san(&pos.board, move_str).map(Self::new)
+        chess::ChessMove::from_san(&pos.board, move_str)
+            /* The library parses castling as a move from the e-file, which is wrong in Chess960 */
+            .and_then(|m| match pos.chess960 && move_str.starts_with(['O', '0']) {
+                true => Err(chess::Error::InvalidSanMove),
+                false => Ok(Self::new(m)),
+            })
+            .or_else(|err| {
+                /* The library does not parse some valid SAN, such as 'e8=Q' or en passant without an 'e.p.' suffix */
+                let normalize = |s: &str| s.replace(['=', '+', '#'], "");
+                let move_str = normalize(move_str);
+                pos.legal_moves()
+                    .find(|m| normalize(&m.to_san(pos)) == move_str)
+                    .ok_or(err)
+            })
     }
 
     pub fn from_lan(move_str: &str) -> Result<Self, String> {
@@ -48,6 +61,75 @@ impl ChessMove {
         Ok(ChessMove::new(chess::ChessMove::new(source, dest, promotion)))
     }
 
+    /// Standard algebraic notation of the move, such as 'e4', 'Nbxd7+', 'e8=Q#' or 'O-O'
+    ///
+    /// The move must be legal on the board of the position, but the game may be over by the draw rules, for example
+    /// when the fifty-move counter ran out.
+    pub fn to_san(&self, pos: &ChessPosition) -> String {
+        let board = &pos.board;
+        let (source, dest) = (self.m.get_source(), self.m.get_dest());
+        let piece = board.piece_on(source).unwrap();
+
+        let castling = pos.castling_side(*self).or_else(|| {
+            let is_castling = !pos.chess960
+                && piece == chess::Piece::King
+                && source.get_file().to_index().abs_diff(dest.get_file().to_index()) == 2;
+            is_castling.then(|| dest.get_file() == chess::File::G)
+        });
+
+        let mut s = String::new();
+        if let Some(kingside) = castling {
+            s.push_str(if kingside { "O-O" } else { "O-O-O" });
+        } else if piece == chess::Piece::Pawn {
+            if source.get_file() != dest.get_file() {
+                s.push(file_char(source.get_file()));
+                s.push('x');
+            }
+            s.push_str(&dest.to_string());
+            if let Some(promotion) = self.m.get_promotion() {
+                s.push('=');
+                s.push(piece_char(promotion));
+            }
+        } else {
+            s.push(piece_char(piece));
+            /* Other pieces of the same type that can move to the destination */
+            let ambiguous = chess::MoveGen::new_legal(board)
+                .filter(|other| {
+                    other.get_dest() == dest
+                        && other.get_source() != source
+                        && board.piece_on(other.get_source()) == Some(piece)
+                })
+                .collect_vec();
+            if !ambiguous.is_empty() {
+                if ambiguous
+                    .iter()
+                    .all(|other| other.get_source().get_file() != source.get_file())
+                {
+                    s.push(file_char(source.get_file()));
+                } else if ambiguous
+                    .iter()
+                    .all(|other| other.get_source().get_rank() != source.get_rank())
+                {
+                    s.push(rank_char(source.get_rank()));
+                } else {
+                    s.push_str(&source.to_string());
+                }
+            }
+            if board.piece_on(dest).is_some() {
+                s.push('x');
+            }
+            s.push_str(&dest.to_string());
+        }
+
+        let next = pos.moved_position_unchecked(*self).board;
+        if next.status() == chess::BoardStatus::Checkmate {
+            s.push('#');
+        } else if next.checkers().popcnt() > 0 {
+            s.push('+');
+        }
+        s
+    }
+
     pub fn get_raw(&self) -> &chess::ChessMove {
         &self.m
     }
@@ -145,6 +227,11 @@ impl Bitboard for ChessBitboard {
             self.b.0 &= !(1u64 << idx);
         }
     }
+
+    fn write_plane(&self, out: &mut [f32]) {
+        assert_eq!(out.len(), ChessGame::BOARD_SIZE * ChessGame::BOARD_SIZE);
+        unpack_bits(self.b.0, out);
+    }
 }
 impl From<chess::BitBoard> for ChessBitboard {
     fn from(b: chess::BitBoard) -> Self {
@@ -152,26 +239,392 @@ impl From<chess::BitBoard> for ChessBitboard {
     }
 }
 
+/// The draw rules by repetitions and by the fifty-move counter
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ChessDrawRules {
+    /// A draw is claimed as soon as possible, on a threefold repetition or after fifty moves
+    #[default]
+    Claimable,
+    /// Only the automatic draws, on a fivefold repetition or after seventy-five moves
+    Automatic,
+}
+impl ChessDrawRules {
+    pub fn repetition_limit(self) -> usize {
+        match self {
+            Self::Claimable => 3,
+            Self::Automatic => 5,
+        }
+    }
+
+    /// The halfmove clock at which the game is drawn
+    pub fn halfmove_limit(self) -> u16 {
+        match self {
+            Self::Claimable => 100,
+            Self::Automatic => 150,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct ChessPosition {
     pub board: chess::Board,
-    pub fifty_rule_count: u8,
+    /// Number of half moves since the last capture or pawn move
+    pub halfmove_clock: u16,
+    /// Number of the full move, starting at 1 and incremented after black's move
+    pub fullmove_number: u16,
+    /// The draw rules of the game, kept by the following positions
+    pub draw_rules: ChessDrawRules,
+    /// Chess960 position, castling rights are kept in `castling_rooks` and castling moves are encoded as the king
+    /// capturing its own rook
+    pub chess960: bool,
+    /// The files of the castling rooks of Chess960 positions, by color and side, kingside first
+    castling_rooks: [[Option<chess::File>; 2]; 2],
 }
 
 impl ChessPosition {
     fn new_from_board(board: chess::Board) -> Self {
         Self {
             board,
-            fifty_rule_count: 0,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            draw_rules: ChessDrawRules::default(),
+            chess960: false,
+            castling_rooks: [[None; 2]; 2],
+        }
+    }
+
+    /// Create a Chess960 start position by its standard number in 0..960, 518 being the standard start position
+    pub fn new_chess960(idx: u16) -> Self {
+        assert!(idx < 960);
+        let idx = idx as usize;
+        let mut back_rank = [None; 8];
+        back_rank[1 + 2 * (idx % 4)] = Some('B');
+        back_rank[2 * (idx / 4 % 4)] = Some('B');
+        let idx = idx / 16;
+        /* The other pieces are placed on the n-th empty square */
+        let mut place = |nth_empty: usize, piece: char| {
+            let file = (0..8).filter(|f| back_rank[*f].is_none()).nth(nth_empty).unwrap();
+            back_rank[file] = Some(piece);
+        };
+        place(idx % 6, 'Q');
+        let (n1, n2) = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ][idx / 6];
+        place(n2, 'N');
+        place(n1, 'N');
+        for piece in ['R', 'K', 'R'] {
+            place(0, piece);
+        }
+        let back_rank = back_rank.map(Option::unwrap);
+
+        let rook_files = (0..8)
+            .filter(|f| back_rank[*f] == 'R')
+            .map(|f| (b'A' + f as u8) as char)
+            .collect_vec();
+        let white = back_rank.iter().collect::<String>();
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{}{}{} - 0 1",
+            white.to_lowercase(),
+            white,
+            rook_files[1],
+            rook_files[0],
+            rook_files[1].to_ascii_lowercase(),
+            rook_files[0].to_ascii_lowercase()
+        );
+        Self::from_fen_chess960(&fen).unwrap()
+    }
+
+    pub fn with_draw_rules(mut self, draw_rules: ChessDrawRules) -> Self {
+        self.draw_rules = draw_rules;
+        self
+    }
+
+    /// Parse a position from a FEN string
+    ///
+    /// The halfmove clock and fullmove number fields are optional, and default to 0 and 1. The en passant square is
+    /// kept only if an en passant capture is possible, as done by the underlying board.
+    ///
+    /// The castling rights may be given in X-FEN or Shredder-FEN notation. The position is a Chess960 position if the
+    /// castling rights are given by rook files or are not of the standard setup, use `from_fen_chess960` otherwise.
+    pub fn from_fen(s: &str) -> Result<Self, String> {
+        Self::parse_fen(s, false)
+    }
+
+    /// Parse a Chess960 position from a FEN string, see `from_fen`
+    pub fn from_fen_chess960(s: &str) -> Result<Self, String> {
+        Self::parse_fen(s, true)
+    }
+
+    fn parse_fen(s: &str, chess960: bool) -> Result<Self, String> {
+        let fields = s.split_whitespace().collect_vec();
+        if !(4..=6).contains(&fields.len()) {
+            return Err(format!("Invalid FEN fields number {}: '{}'", fields.len(), s));
+        }
+
+        let ranks = fields[0].split('/').collect_vec();
+        if ranks.len() != ChessGame::BOARD_SIZE {
+            return Err(format!("Invalid FEN ranks number {}: '{}'", ranks.len(), s));
+        }
+        for rank in ranks {
+            let mut files = 0;
+            for c in rank.chars() {
+                files += match c {
+                    '1'..='8' => c as usize - '0' as usize,
+                    'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => 1,
+                    _ => return Err(format!("Invalid FEN piece char '{}': '{}'", c, s)),
+                };
+            }
+            if files != ChessGame::BOARD_SIZE {
+                return Err(format!("Invalid FEN rank '{}': '{}'", rank, s));
+            }
+        }
+        for king in ['K', 'k'] {
+            if fields[0].matches(king).count() != 1 {
+                return Err(format!("Invalid FEN, expected a single '{}' king: '{}'", king, s));
+            }
+        }
+        if !["w", "b"].contains(&fields[1]) {
+            return Err(format!("Invalid FEN side to move '{}': '{}'", fields[1], s));
+        }
+        if !(fields[2] == "-" || fields[2].chars().all(|c| "KQkqABCDEFGHabcdefgh".contains(c))) {
+            return Err(format!("Invalid FEN castling rights '{}': '{}'", fields[2], s));
         }
+        let ep = fields[3].as_bytes();
+        if !(fields[3] == "-" || (ep.len() == 2 && (b'a'..=b'h').contains(&ep[0]) && [b'3', b'6'].contains(&ep[1]))) {
+            return Err(format!("Invalid FEN en passant square '{}': '{}'", fields[3], s));
+        }
+
+        /* The castling rights are resolved to rook files on the board without castling rights */
+        let parse_board = |castling: &str| {
+            chess::Board::from_str(&[fields[0], fields[1], castling, fields[3]].join(" "))
+                .map_err(|e| format!("{}: '{}'", e, s))
+        };
+        let board = parse_board("-")?;
+        let mut castling_rooks = [[None; 2]; 2];
+        let mut chess960 = chess960;
+        for c in fields[2].chars().filter(|c| *c != '-') {
+            let color = if c.is_ascii_uppercase() {
+                chess::Color::White
+            } else {
+                chess::Color::Black
+            };
+            let king = board.king_square(color);
+            if king.get_rank() != color.to_my_backrank() {
+                return Err(format!("Invalid FEN castling rights, king not on back rank: '{}'", s));
+            }
+            let (kingside, rook_file) = match c.to_ascii_uppercase() {
+                'K' => (true, castling_rook_candidate(&board, color, true)),
+                'Q' => (false, castling_rook_candidate(&board, color, false)),
+                file => {
+                    chess960 = true;
+                    let file = chess::File::from_index((file as u8 - b'A') as usize);
+                    let square = chess::Square::make_square(color.to_my_backrank(), file);
+                    let is_rook =
+                        board.piece_on(square) == Some(chess::Piece::Rook) && board.color_on(square) == Some(color);
+                    (file > king.get_file(), is_rook.then_some(file))
+                }
+            };
+            let Some(rook_file) = rook_file else {
+                return Err(format!(
+                    "Invalid FEN castling rights, no castling rook for '{}': '{}'",
+                    c, s
+                ));
+            };
+            let right = &mut castling_rooks[color.to_index()][if kingside { 0 } else { 1 }];
+            if right.is_some() {
+                return Err(format!("Invalid FEN castling rights '{}': '{}'", fields[2], s));
+            }
+            *right = Some(rook_file);
+            let standard_rook_file = if kingside { chess::File::H } else { chess::File::A };
+            chess960 |= king.get_file() != chess::File::E || rook_file != standard_rook_file;
+        }
+        let board = if chess960 { board } else { parse_board(fields[2])? };
+        if !chess960 {
+            castling_rooks = [[None; 2]; 2];
+        }
+        let halfmove_clock = match fields.get(4) {
+            Some(field) => field
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid FEN halfmove clock '{}': '{}'", field, s))?,
+            None => 0,
+        };
+        let fullmove_number = match fields.get(5) {
+            Some(field) => field
+                .parse::<u16>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("Invalid FEN fullmove number '{}': '{}'", field, s))?,
+            None => 1,
+        };
+        Ok(Self {
+            board,
+            halfmove_clock,
+            fullmove_number,
+            draw_rules: ChessDrawRules::default(),
+            chess960,
+            castling_rooks,
+        })
     }
 
-    pub fn from_fen(s: &str) -> Self {
-        Self::new_from_board(chess::Board::from_str(s).unwrap())
+    /// Whether no sequence of legal moves can lead to a checkmate, with the kings alone, a single minor piece, or
+    /// only bishops all on squares of the same colour
+    pub fn is_insufficient_material(&self) -> bool {
+        let b = &self.board;
+        let heavy = b.pieces(chess::Piece::Pawn) | b.pieces(chess::Piece::Rook) | b.pieces(chess::Piece::Queen);
+        if heavy.popcnt() > 0 {
+            return false;
+        }
+        let knights = b.pieces(chess::Piece::Knight);
+        let bishops = b.pieces(chess::Piece::Bishop);
+        if (knights | bishops).popcnt() <= 1 {
+            return true;
+        }
+        const LIGHT_SQUARES: u64 = 0x55aa55aa55aa55aa;
+        knights.popcnt() == 0 && (bishops.0 & LIGHT_SQUARES == 0 || bishops.0 & !LIGHT_SQUARES == 0)
     }
 
     pub fn is_valid_move(&self, m: ChessMove) -> bool {
-        self.status().is_ongoing() && self.board.legal(m.m)
+        self.status().is_ongoing() && (self.board.legal(m.m) || self.castling_moves().any(|c| c == m))
+    }
+
+    /// The position after a legal move, without checking the move or whether the game is over
+    pub(crate) fn moved_position_unchecked(&self, m: ChessMove) -> Self {
+        let castling = self.castling_side(m);
+        let board = match castling {
+            Some(kingside) => self.castled_board(kingside).unwrap().1,
+            None => self.board.make_move_new(m.m),
+        };
+        let mut next_board = ChessPosition::new_from_board(board).with_draw_rules(self.draw_rules);
+        next_board.chess960 = self.chess960;
+
+        /* A castling right is lost when the king moves, or when the castling rook moves or is captured */
+        next_board.castling_rooks = self.castling_rooks;
+        for color in [chess::Color::White, chess::Color::Black] {
+            let king_moved = m.m.get_source() == self.board.king_square(color);
+            for rook_file in next_board.castling_rooks[color.to_index()].iter_mut() {
+                let rook = rook_file.map(|file| chess::Square::make_square(color.to_my_backrank(), file));
+                if king_moved || rook == Some(m.m.get_source()) || rook == Some(m.m.get_dest()) {
+                    *rook_file = None;
+                }
+            }
+        }
+
+        let piece = self.board.piece_on(m.m.get_source());
+        let is_pawn = piece.is_some() && piece.unwrap() == chess::Piece::Pawn;
+        let is_atk = castling.is_none() && self.board.piece_on(m.m.get_dest()).is_some();
+
+        next_board.halfmove_clock = if is_pawn || is_atk {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+        next_board.fullmove_number = if self.turn() == GameColor::Player2 {
+            self.fullmove_number + 1
+        } else {
+            self.fullmove_number
+        };
+
+        next_board
+    }
+
+    /// Whether a player can still castle to a side, in standard chess or Chess960
+    pub fn has_castling_right(&self, color: chess::Color, kingside: bool) -> bool {
+        if self.chess960 {
+            self.castling_rooks[color.to_index()][if kingside { 0 } else { 1 }].is_some()
+        } else {
+            let rights = self.board.castle_rights(color);
+            if kingside {
+                rights.has_kingside()
+            } else {
+                rights.has_queenside()
+            }
+        }
+    }
+
+    /// If the move is a Chess960 castling move, return whether it is kingside
+    fn castling_side(&self, m: ChessMove) -> Option<bool> {
+        let (source, dest) = (m.m.get_source(), m.m.get_dest());
+        let is_castling = self.chess960
+            && self.board.piece_on(source) == Some(chess::Piece::King)
+            && self.board.color_on(dest) == Some(self.board.side_to_move());
+        is_castling.then(|| dest.get_file() > source.get_file())
+    }
+
+    /// The legal Chess960 castling moves, standard chess castling moves are generated by the underlying board
+    fn castling_moves(&self) -> impl Iterator<Item = ChessMove> + '_ {
+        [true, false].into_iter().filter_map(|kingside| {
+            let king = self.board.king_square(self.board.side_to_move());
+            self.castled_board(kingside)
+                .map(|(rook, _board)| ChessMove::new(chess::ChessMove::new(king, rook, None)))
+        })
+    }
+
+    /// The castling rook square and the board after a Chess960 castling, if it is legal
+    fn castled_board(&self, kingside: bool) -> Option<(chess::Square, chess::Board)> {
+        let b = &self.board;
+        let color = b.side_to_move();
+        let rook_file = self.castling_rooks[color.to_index()][if kingside { 0 } else { 1 }]?;
+        if b.checkers().popcnt() > 0 {
+            return None;
+        }
+        let rank = color.to_my_backrank();
+        let king = b.king_square(color);
+        let rook = chess::Square::make_square(rank, rook_file);
+        let (king_dest, rook_dest) = if kingside {
+            (chess::File::G, chess::File::F)
+        } else {
+            (chess::File::C, chess::File::D)
+        };
+
+        /* All the squares the king and rook move through must be empty, except for the king and the rook */
+        let occupied = *b.combined() & !chess::BitBoard::from_square(king) & !chess::BitBoard::from_square(rook);
+        let files = [king.get_file(), rook_file, king_dest, rook_dest].map(|f| f.to_index());
+        let (min_file, max_file) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+        for file in min_file..=max_file {
+            let square = chess::Square::make_square(rank, chess::File::from_index(file));
+            if occupied & chess::BitBoard::from_square(square) != chess::EMPTY {
+                return None;
+            }
+        }
+
+        /* The king must not pass through an attacked square */
+        let (king_file, king_dest_file) = (king.get_file().to_index(), king_dest.to_index());
+        for file in king_file.min(king_dest_file)..=king_file.max(king_dest_file) {
+            let square = chess::Square::make_square(rank, chess::File::from_index(file));
+            if is_attacked(b, square, !color, occupied | chess::BitBoard::from_square(rook)) {
+                return None;
+            }
+        }
+
+        let pieces = b
+            .combined()
+            .into_iter()
+            .filter(|square| *square != king && *square != rook)
+            .map(|square| (square, b.piece_on(square).unwrap(), b.color_on(square).unwrap()))
+            .chain([
+                (chess::Square::make_square(rank, king_dest), chess::Piece::King, color),
+                (chess::Square::make_square(rank, rook_dest), chess::Piece::Rook, color),
+            ])
+            .collect_vec();
+        /* Fails if the castling reveals an attack on the king */
+        let board = chess::Board::try_from(chess::BoardBuilder::setup(
+            pieces.iter(),
+            !color,
+            chess::CastleRights::NoRights,
+            chess::CastleRights::NoRights,
+            None,
+        ))
+        .ok()?;
+        Some((rook, board))
     }
 
     pub fn get_raw_board(&self) -> &chess::Board {
@@ -198,14 +651,7 @@ impl ChessPosition {
                             s.push(char::from_digit(blanks, 10).unwrap());
                             blanks = 0;
                         }
-                        let mut c = match piece {
-                            chess::Piece::Pawn => 'P',
-                            chess::Piece::Knight => 'N',
-                            chess::Piece::Bishop => 'B',
-                            chess::Piece::Rook => 'R',
-                            chess::Piece::Queen => 'Q',
-                            chess::Piece::King => 'K',
-                        };
+                        let mut c = piece_char(piece);
                         if b.color_on(sq).unwrap() == chess::Color::Black {
                             c = c.to_lowercase().next().unwrap();
                         }
@@ -230,7 +676,32 @@ impl ChessPosition {
         // Castling State
         let cr_w = b.castle_rights(chess::Color::White);
         let cr_b = b.castle_rights(chess::Color::Black);
-        let cr_str = if cr_w == chess::CastleRights::NoRights && cr_b == chess::CastleRights::NoRights {
+        let cr_str = if self.chess960 {
+            /* X-FEN, the rook file is written only if the right is not for the outermost rook */
+            let mut s = String::default();
+            for color in [chess::Color::White, chess::Color::Black] {
+                for (side, kingside) in [('K', true), ('Q', false)] {
+                    let Some(file) = self.castling_rooks[color.to_index()][if kingside { 0 } else { 1 }] else {
+                        continue;
+                    };
+                    let c = if castling_rook_candidate(b, color, kingside) == Some(file) {
+                        side
+                    } else {
+                        file_char(file).to_ascii_uppercase()
+                    };
+                    s.push(if color == chess::Color::White {
+                        c
+                    } else {
+                        c.to_ascii_lowercase()
+                    });
+                }
+            }
+            if s.is_empty() {
+                "-".to_string()
+            } else {
+                s
+            }
+        } else if cr_w == chess::CastleRights::NoRights && cr_b == chess::CastleRights::NoRights {
             "-".to_string()
         } else {
             let mut s = String::default();
@@ -264,20 +735,71 @@ impl ChessPosition {
                         chess::Color::White => 1,
                         chess::Color::Black => -1,
                     }) as usize;
-                s.push(['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'][sq.get_file().to_index()]);
-                s.push(['1', '2', '3', '4', '5', '6', '7', '8'][rank_idx]);
+                s.push(file_char(sq.get_file()));
+                s.push(rank_char(chess::Rank::from_index(rank_idx)));
             }
             None => s.push('-'),
         }
-        // s.push(' ');
-        // s.push_str(&format!("{}", self.rule_50()));
-        // s.push(' ');
-        // s.push_str(&format!("{}", (self.half_moves / 2) + 1));
+        s.push(' ');
+        s.push_str(&self.halfmove_clock.to_string());
+        s.push(' ');
+        s.push_str(&self.fullmove_number.to_string());
 
         s
     }
 }
 
+/// The outermost rook on the back rank on one side of the king, which castles with the 'K' and 'Q' rights of X-FEN
+fn castling_rook_candidate(board: &chess::Board, color: chess::Color, kingside: bool) -> Option<chess::File> {
+    let king_file = board.king_square(color).get_file();
+    let rooks = board.pieces(chess::Piece::Rook) & board.color_combined(color);
+    let files = rooks
+        .into_iter()
+        .filter(|square| square.get_rank() == color.to_my_backrank())
+        .map(|square| square.get_file())
+        .filter(|file| (*file > king_file) == kingside && *file != king_file);
+    if kingside {
+        files.max_by_key(|file| file.to_index())
+    } else {
+        files.min_by_key(|file| file.to_index())
+    }
+}
+
+fn is_attacked(board: &chess::Board, square: chess::Square, attacker: chess::Color, occupied: chess::BitBoard) -> bool {
+    let attackers = *board.color_combined(attacker);
+    let queens = *board.pieces(chess::Piece::Queen);
+    let rooks = (*board.pieces(chess::Piece::Rook) | queens) & attackers;
+    let bishops = (*board.pieces(chess::Piece::Bishop) | queens) & attackers;
+    let knights = *board.pieces(chess::Piece::Knight) & attackers;
+    let kings = *board.pieces(chess::Piece::King) & attackers;
+    let pawns = *board.pieces(chess::Piece::Pawn) & attackers;
+    (chess::get_rook_moves(square, occupied) & rooks
+        | chess::get_bishop_moves(square, occupied) & bishops
+        | chess::get_knight_moves(square) & knights
+        | chess::get_king_moves(square) & kings
+        | chess::get_pawn_attacks(square, !attacker, pawns))
+        != chess::EMPTY
+}
+
+fn piece_char(piece: chess::Piece) -> char {
+    match piece {
+        chess::Piece::Pawn => 'P',
+        chess::Piece::Knight => 'N',
+        chess::Piece::Bishop => 'B',
+        chess::Piece::Rook => 'R',
+        chess::Piece::Queen => 'Q',
+        chess::Piece::King => 'K',
+    }
+}
+
+fn file_char(file: chess::File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+fn rank_char(rank: chess::Rank) -> char {
+    (b'1' + rank.to_index() as u8) as char
+}
+
 fn chess_color_to_game_color(c: chess::Color) -> GameColor {
     match c {
         chess::Color::White => GameColor::Player1,
@@ -301,6 +823,8 @@ impl PartialEq for ChessPosition {
             && b1.castle_rights(chess::Color::Black) == b2.castle_rights(chess::Color::Black)
             && b1.en_passant() == b2.en_passant()
             && b1.side_to_move() == b2.side_to_move()
+            && self.chess960 == other.chess960
+            && self.castling_rooks == other.castling_rooks
     }
 }
 impl Eq for ChessPosition {}
@@ -321,40 +845,31 @@ impl Position for ChessPosition {
     }
 
     fn legal_moves(&self) -> impl Iterator<Item = ChessMove> {
-        chess::MoveGen::new_legal(&self.board).map(ChessMove::new)
+        chess::MoveGen::new_legal(&self.board)
+            .map(ChessMove::new)
+            .chain(self.castling_moves())
     }
 
     fn moved_position(&self, m: ChessMove) -> Self {
         assert!(self.is_valid_move(m));
-
-        let mut next_board = ChessPosition::new_from_board(self.board.make_move_new(m.m));
-
-        let piece = self.board.piece_on(m.m.get_source());
-        let is_pawn = piece.is_some() && piece.unwrap() == chess::Piece::Pawn;
-        let is_atk = self.board.piece_on(m.m.get_dest()).is_some();
-
-        next_board.fifty_rule_count = if is_pawn || is_atk {
-            0
-        } else if self.turn() == GameColor::Player1 {
-            /* The move count is incremented only once per white+black move */
-            self.fifty_rule_count + 1
-        } else {
-            self.fifty_rule_count
-        };
-
-        next_board
+        self.moved_position_unchecked(m)
     }
 
     fn status(&self) -> GameStatus {
-        if !(self.board.status() != chess::BoardStatus::Ongoing || self.fifty_rule_count >= 50) {
-            return GameStatus::Ongoing;
-        }
         GameStatus::Finished(match self.board.status() {
             chess::BoardStatus::Ongoing => {
-                assert!(self.fifty_rule_count >= 50);
+                if self.halfmove_clock < self.draw_rules.halfmove_limit() && !self.is_insufficient_material() {
+                    return GameStatus::Ongoing;
+                }
+                None
+            }
+            chess::BoardStatus::Stalemate => {
+                /* The underlying board is not aware of Chess960 castling moves */
+                if self.castling_moves().next().is_some() {
+                    return GameStatus::Ongoing;
+                }
                 None
             }
-            chess::BoardStatus::Stalemate => None,
             chess::BoardStatus::Checkmate => {
                 // TODO not sure this is correct, need to check.
                 // looks valid according to https://docs.rs/chess/latest/src/chess/game.rs.html#98-105
@@ -394,7 +909,11 @@ impl Position for ChessPosition {
 
         Self {
             board,
-            fifty_rule_count: self.fifty_rule_count,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            draw_rules: self.draw_rules,
+            chess960: self.chess960,
+            castling_rooks: [self.castling_rooks[1], self.castling_rooks[0]],
         }
     }
 }
@@ -404,6 +923,11 @@ pub struct ChessGame {
     seen_positions: HashMap<ChessPosition, u32>,
     repetition_detected: bool,
 }
+impl ChessGame {
+    pub fn new_with_draw_rules(draw_rules: ChessDrawRules) -> Self {
+        Self::from_position(ChessPosition::new().with_draw_rules(draw_rules))
+    }
+}
 
 impl Game for ChessGame {
     type Position = ChessPosition;
@@ -411,12 +935,14 @@ impl Game for ChessGame {
     type Bitboard = ChessBitboard;
     const BOARD_SIZE: usize = 8;
     const MOVES_NUM: usize = 1880;
+    /* The search treats threefold repetitions as draws regardless of the game draw rules */
     const REPETITION_LIMIT: Option<usize> = Some(3);
 
     fn new() -> Self {
         Self::from_position(Self::Position::new())
     }
 
+    /// Create a game from a position, with the draw rules of the position
     fn from_position(pos: Self::Position) -> Self {
         Self {
             pos_history: vec![pos],
@@ -444,7 +970,7 @@ impl Game for ChessGame {
 
         let repeat = self.seen_positions.entry(new_pos).or_insert(0);
         *repeat += 1;
-        if *repeat as usize >= Self::REPETITION_LIMIT.unwrap() {
+        if *repeat as usize >= new_pos.draw_rules.repetition_limit() {
             self.repetition_detected = true;
         }
     }
@@ -610,9 +1136,9 @@ mod tests {
     use rand::{Rng, RngCore, SeedableRng};
     use std::collections::HashSet;
 
-    use crate::chess::{ChessGame, ChessMove, ChessPosition};
+    use crate::chess::{ChessBitboard, ChessDrawRules, ChessGame, ChessMove, ChessPosition};
     use crate::game::player::{GamePlayer, PlayerRand};
-    use crate::game::{Game, GameColor, GameStatus, Move, Position};
+    use crate::game::{Bitboard, Game, GameColor, GameStatus, Move, Position};
 
     #[test]
     fn simple_game_and_mate() {
@@ -660,16 +1186,228 @@ mod tests {
             "Ke2", "Ke7", "Ke1", "Ke8",
             "Ke2", "Ke7", "Ke1", "Ke8",
             "Ke2", "Ke7", "Ke1", "Ke8",
-            "Ke2", "Ke7", "Ke1",
+            "Ke2", "Ke7", "Ke1", "Ke8",
         ];
         for move_str in moves {
             assert!(pos.status().is_ongoing());
             let m = ChessMove::from_san(&pos, move_str).unwrap();
             pos = pos.moved_position(m);
         }
+        assert_eq!(pos.halfmove_clock, 100);
+        assert_eq!(pos.fullmove_number, 52);
+        assert_eq!(pos.status(), GameStatus::Finished(None));
+    }
+
+    #[test]
+    fn to_san() {
+        let pos = ChessPosition::from_fen("1k6/4P3/8/8/1N3N2/8/8/R3K2R w KQ - 0 1").unwrap();
+        let san = |lan: &str| ChessMove::from_lan(lan).unwrap().to_san(&pos);
+        assert_eq!(san("b4d5"), "Nbd5");
+        assert_eq!(san("f4d5"), "Nfd5");
+        assert_eq!(san("a1d1"), "Rd1");
+        assert_eq!(san("e1g1"), "O-O");
+        assert_eq!(san("e1c1"), "O-O-O");
+        assert_eq!(san("e7e8q"), "e8=Q+");
+        assert_eq!(san("e7e8n"), "e8=N");
+        assert_eq!(san("a1a8"), "Ra8+");
+
+        let pos = ChessPosition::from_fen("1k6/8/8/8/R7/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(ChessMove::from_lan("a4a2").unwrap().to_san(&pos), "R4a2");
+
+        let pos = ChessPosition::from_fen("k7/8/8/3pP3/3Q4/8/1Q1Q4/KQ6 w - d6 0 1").unwrap();
+        let san = |lan: &str| ChessMove::from_lan(lan).unwrap().to_san(&pos);
+        assert_eq!(san("d2b4"), "Qd2b4");
+        assert_eq!(san("e5d6"), "exd6");
+        assert_eq!(san("b2b7"), "Qb7#");
+
+        /* The game is drawn by the fifty-move rule, but the moves are still legal on the board */
+        let pos = ChessPosition::from_fen("1k6/8/8/8/8/8/8/R3K3 w - - 100 80").unwrap();
+        assert!(!pos.status().is_ongoing());
+        assert_eq!(ChessMove::from_lan("a1a8").unwrap().to_san(&pos), "Ra8+");
+
+        /* SAN of random games moves are parsed back to the same moves */
+        let mut player = PlayerRand::from_seed(0x1e4eb9c1b07b5e53);
+        for _ in 0..10 {
+            let mut game = ChessGame::new();
+            while game.status().is_ongoing() {
+                let pos = *game.position();
+                for m in pos.legal_moves() {
+                    let san = m.to_san(&pos);
+                    assert_eq!(ChessMove::from_san(&pos, &san).unwrap(), m, "{}", san);
+                }
+                let next_move = <_ as GamePlayer<ChessGame>>::next_move(&mut player, game.pos_history()).unwrap();
+                game.play_single_turn(next_move);
+            }
+        }
+    }
+
+    #[test]
+    fn fen() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 17 42",
+            "8/5k2/8/8/8/8/2K5/8 w - - 99 120",
+            "8/5k2/8/8/8/8/2K5/8 b - - 300 250",
+        ] {
+            assert_eq!(ChessPosition::from_fen(fen).unwrap().fen(), fen);
+        }
+
+        /* The clocks are optional */
+        let pos = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap();
+        assert!(pos == ChessPosition::new());
+        assert_eq!((pos.halfmove_clock, pos.fullmove_number), (0, 1));
+
+        /* The clocks are updated by moves */
+        let mut pos = ChessPosition::new();
+        for (move_str, fen) in [
+            ("e4", "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"),
+            ("Nf6", "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"),
+            ("Nc3", "rnbqkb1r/pppppppp/5n2/8/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 2"),
+            ("Nxe4", "rnbqkb1r/pppppppp/8/8/4n3/2N5/PPPP1PPP/R1BQKBNR w KQkq - 0 3"),
+        ] {
+            pos = pos.moved_position(ChessMove::from_san(&pos, move_str).unwrap());
+            assert_eq!(pos.fen(), fen);
+            assert!(ChessPosition::from_fen(fen).unwrap() == pos);
+        }
+
+        for invalid in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra",
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+        ] {
+            assert!(ChessPosition::from_fen(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for (fen, insufficient) in [
+            ("8/8/3k4/8/8/2K5/8/8 w - - 0 1", true),
+            ("8/8/3k4/8/8/2KB4/8/8 w - - 0 1", true),
+            ("8/8/3k4/8/8/2K5/8/6n1 b - - 0 1", true),
+            /* Bishops on the same colour, of either side */
+            ("8/8/3k4/5b2/8/2KB4/8/8 w - - 0 1", true),
+            ("6B1/8/3k4/8/8/2KB4/8/8 w - - 0 1", true),
+            ("8/8/3k4/4b3/8/2KB4/8/8 w - - 0 1", false),
+            ("8/8/3k4/8/8/2KN4/8/6n1 w - - 0 1", false),
+            ("8/8/3k4/8/8/2KNN3/8/8 w - - 0 1", false),
+            ("8/8/3k4/8/8/2K5/P7/8 w - - 0 1", false),
+            ("8/8/3k4/8/8/2K5/8/r7 w - - 0 1", false),
+        ] {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            assert_eq!(pos.is_insufficient_material(), insufficient, "{}", fen);
+            assert_eq!(pos.status().is_ongoing(), !insufficient, "{}", fen);
+        }
+
+        /* Capturing the last pawn ends the game */
+        let pos = ChessPosition::from_fen("8/8/3k4/8/2P5/8/8/7K b - - 0 1").unwrap();
+        let pos = pos.moved_position(ChessMove::from_san(&pos, "Kc5").unwrap());
+        assert!(pos.status().is_ongoing());
+        let pos = pos.moved_position(ChessMove::from_san(&pos, "Kh2").unwrap());
+        let pos = pos.moved_position(ChessMove::from_san(&pos, "Kxc4").unwrap());
         assert_eq!(pos.status(), GameStatus::Finished(None));
     }
 
+    #[test]
+    fn draw_rules() {
+        let knight_moves = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        let play = |game: &mut ChessGame, moves_num: usize| {
+            for move_str in knight_moves.iter().cycle().take(moves_num) {
+                assert!(game.status().is_ongoing());
+                let m = ChessMove::from_san(game.position(), move_str).unwrap();
+                game.play_single_turn(m);
+            }
+        };
+
+        /* The third occurrence of the start position */
+        let mut game = ChessGame::new();
+        play(&mut game, 8);
+        assert_eq!(game.status(), GameStatus::Finished(None));
+
+        /* The fifth occurrence of the start position */
+        let mut game = ChessGame::new_with_draw_rules(ChessDrawRules::Automatic);
+        play(&mut game, 8);
+        assert!(game.status().is_ongoing());
+        play(&mut game, 8);
+        assert_eq!(game.status(), GameStatus::Finished(None));
+
+        for (draw_rules, halfmove_limit) in [(ChessDrawRules::Claimable, 100), (ChessDrawRules::Automatic, 150)] {
+            let pos = ChessPosition::from_fen(&format!("8/8/3k4/8/8/2K5/P7/8 w - - {} 80", halfmove_limit - 1))
+                .unwrap()
+                .with_draw_rules(draw_rules);
+            assert!(pos.status().is_ongoing());
+            let pos = pos.moved_position(ChessMove::from_san(&pos, "Kc4").unwrap());
+            assert_eq!(pos.status(), GameStatus::Finished(None));
+        }
+    }
+
+    #[test]
+    fn chess960() {
+        let standard = ChessPosition::new_chess960(518);
+        assert!(standard.chess960);
+        assert_eq!(standard.fen(), ChessPosition::new().fen());
+        assert!(ChessPosition::new_chess960(0).fen().starts_with("bbqnnrkr/"));
+        let positions = (0..960)
+            .map(|idx| ChessPosition::new_chess960(idx).fen())
+            .collect::<HashSet<_>>();
+        assert_eq!(positions.len(), 960);
+
+        /* Shredder-FEN and X-FEN castling rights */
+        for (fen, xfen) in [
+            (
+                "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1",
+                "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1",
+            ),
+            (
+                "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1",
+                "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GQgq - 0 1",
+            ),
+            ("rk5r/8/8/8/8/8/8/RK5R w Kq - 0 1", "rk5r/8/8/8/8/8/8/RK5R w Kq - 0 1"),
+        ] {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            assert!(pos.chess960);
+            assert_eq!(pos.fen(), xfen);
+            assert!(ChessPosition::from_fen(xfen).unwrap() == pos);
+            assert!(pos.flipped().flipped() == pos);
+        }
+        assert!(
+            !ChessPosition::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+                .unwrap()
+                .chess960
+        );
+        assert!(ChessPosition::from_fen("4k3/8/8/8/8/8/8/RK5R w C - 0 1").is_err());
+
+        /* Castling is encoded as the king capturing its own rook */
+        let pos = ChessPosition::from_fen("4k3/8/8/8/8/8/8/RK5R w HA - 0 1").unwrap();
+        let kingside = ChessMove::from_lan("b1h1").unwrap();
+        let queenside = ChessMove::from_lan("b1a1").unwrap();
+        assert!(pos.legal_moves().any(|m| m == kingside));
+        assert!(pos.legal_moves().any(|m| m == queenside));
+        assert_eq!(kingside.to_san(&pos), "O-O");
+        assert_eq!(ChessMove::from_san(&pos, "O-O-O").unwrap(), queenside);
+        let castled = pos.moved_position(kingside);
+        assert_eq!(castled.fen(), "4k3/8/8/8/8/8/8/R4RK1 b - - 1 1");
+        assert!(!castled.has_castling_right(chess::Color::White, false));
+        let rook_moved = pos.moved_position(ChessMove::from_lan("a1a2").unwrap());
+        assert_eq!(rook_moved.fen(), "4k3/8/8/8/8/8/R7/1K5R b K - 1 1");
+        assert!(rook_moved.has_castling_right(chess::Color::White, true));
+        assert_eq!(pos.moved_position(queenside).fen(), "4k3/8/8/8/8/8/8/2KR3R b - - 1 1");
+
+        /* The king may not pass through an attacked square, the rook may */
+        let pos = ChessPosition::from_fen("3rk3/8/8/8/8/8/8/RK5R w HA - 0 1").unwrap();
+        assert!(!pos.is_valid_move(kingside));
+        assert!(pos.is_valid_move(queenside));
+    }
+
     #[test]
     fn flip() {
         /* random FEN: */
@@ -685,7 +1423,7 @@ mod tests {
             "3r4/1b2p3/7k/1P3R2/K4nrN/1N5P/n1Pp3P/8 b - - 0 1",
         ]
         .into_iter()
-        .map(ChessPosition::from_fen)
+        .map(|fen| ChessPosition::from_fen(fen).unwrap())
         {
             assert!(pos.turn().opposite() == pos.flipped().turn());
             assert!(pos.flipped().flipped() == pos);
@@ -727,4 +1465,20 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn write_plane() {
+        let seed: u64 = rand::rng().random();
+        println!("[{}] Using seed {}", stringify!(write_plane), seed);
+        let mut rand = StdRng::seed_from_u64(seed);
+
+        for _ in 0..100 {
+            let bitboard = ChessBitboard::from_raw(rand.next_u64());
+            let mut plane = [-1.0; 64];
+            bitboard.write_plane(&mut plane);
+            for (idx, val) in plane.into_iter().enumerate() {
+                assert_eq!(val, if bitboard.get(idx) { 1.0 } else { 0.0 });
+            }
+        }
+    }
 }
@@ -0,0 +1,179 @@
+use crate::chess::pgn::parse_san;
+use crate::chess::{ChessMove, ChessPosition};
+
+/// A position of a test suite in EPD (Extended Position Description)
+pub struct EpdEntry {
+    pub pos: ChessPosition,
+    /// The position identifier, set by the 'id' opcode
+    pub id: Option<String>,
+    /// The moves considered solving the position, set by the 'bm' opcode
+    pub best_moves: Vec<ChessMove>,
+    /// The moves considered failing the position, set by the 'am' opcode
+    pub avoid_moves: Vec<ChessMove>,
+}
+
+impl EpdEntry {
+    /// Check if a move solves the position, namely it is one of the best moves and none of the avoid moves
+    pub fn is_solved_by(&self, m: ChessMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&m)) && !self.avoid_moves.contains(&m)
+    }
+}
+
+/// Split the operations of an EPD line to their opcode and operands, keeping quoted operands as a single operand
+///
+/// The last operation may be missing its terminating ';', which is common in test suites.
+fn split_operations(s: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations = Vec::new();
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            None | Some(';') if !tokens.is_empty() => {
+                let opcode = tokens.remove(0);
+                operations.push((opcode, std::mem::take(&mut tokens)));
+            }
+            None => break,
+            Some(';') => {}
+            Some('"') => {
+                let operand: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                tokens.push(operand);
+            }
+            Some(c) => {
+                let mut token = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';') {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    operations
+}
+
+/// Split the next whitespace separated field of a string from the rest of it
+fn next_field(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_once(char::is_whitespace).unwrap_or((s, ""))
+}
+
+/// Parse a single EPD line
+///
+/// The position is given by the first four FEN fields. The halfmove clock and fullmove number are taken from the
+/// 'hmvc' and 'fmvn' opcodes, or from two numeric fields following the four, as written by some test suites.
+pub fn parse_line(line: &str) -> Result<EpdEntry, String> {
+    let mut rest = line;
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        let (field, tail) = next_field(rest);
+        if field.is_empty() {
+            return Err(format!("Invalid EPD line: '{}'", line));
+        }
+        fields.push(field);
+        rest = tail;
+    }
+
+    let mut counters = ["0".to_string(), "1".to_string()];
+    for counter in counters.iter_mut() {
+        let (value, tail) = next_field(rest);
+        if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+            break;
+        }
+        *counter = value.to_string();
+        rest = tail;
+    }
+
+    let operations = split_operations(rest);
+    for (opcode, operands) in operations.iter() {
+        let idx = match opcode.as_str() {
+            "hmvc" => 0,
+            "fmvn" => 1,
+            _ => continue,
+        };
+        counters[idx] = operands
+            .first()
+            .ok_or(format!("Missing operand of '{}'", opcode))?
+            .clone();
+    }
+
+    let fen = format!("{} {}", fields.join(" "), counters.join(" "));
+    let pos = ChessPosition::from_fen(&fen)?;
+    let mut entry = EpdEntry {
+        pos,
+        id: None,
+        best_moves: Vec::new(),
+        avoid_moves: Vec::new(),
+    };
+    for (opcode, operands) in operations {
+        let moves = match opcode.as_str() {
+            "id" => {
+                entry.id = operands.into_iter().next();
+                continue;
+            }
+            "bm" => &mut entry.best_moves,
+            "am" => &mut entry.avoid_moves,
+            /* Other opcodes, such as comments or direct mates, are ignored */
+            _ => continue,
+        };
+        for san in operands {
+            moves.push(parse_san(&pos, &san)?);
+        }
+    }
+    Ok(entry)
+}
+
+/// Parse all the entries of an EPD string, skipping empty lines and lines starting with '#'
+pub fn parse(s: &str) -> Result<Vec<EpdEntry>, String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_line)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess::epd;
+    use crate::chess::ChessMove;
+
+    #[test]
+    fn parse_entries() {
+        let epd_str = r#"
+# Comment line
+2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nf5 Nxc6; am Qd2; id "with; separator";
+8/8/8/8/8/4k3/8/4K2R w K - 3 40 bm O-O;
+8/8/8/8/8/4k3/8/4K2R w K - hmvc 7; fmvn 52; c0 "comment";
+"#;
+        let entries = epd::parse(epd_str).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let lan = |s: &str| ChessMove::from_lan(s).unwrap();
+        assert_eq!(entries[0].id.as_deref(), Some("WAC.001"));
+        assert_eq!(entries[0].best_moves, vec![lan("g3g6")]);
+        assert!(entries[0].is_solved_by(lan("g3g6")));
+        assert!(!entries[0].is_solved_by(lan("e5f7")));
+
+        assert_eq!(entries[1].id.as_deref(), Some("with; separator"));
+        assert_eq!(entries[1].best_moves, vec![lan("d4f5"), lan("d4c6")]);
+        assert_eq!(entries[1].avoid_moves, vec![lan("d1d2")]);
+        assert!(entries[1].is_solved_by(lan("d4c6")));
+        assert!(!entries[1].is_solved_by(lan("d1d2")));
+
+        assert_eq!(entries[2].pos.fen(), "8/8/8/8/8/4k3/8/4K2R w K - 3 40");
+        assert_eq!(entries[2].best_moves, vec![lan("e1g1")]);
+        assert!(entries[2].id.is_none());
+
+        assert_eq!(entries[3].pos.fen(), "8/8/8/8/8/4k3/8/4K2R w K - 7 52");
+        assert!(entries[3].best_moves.is_empty() && entries[3].avoid_moves.is_empty());
+
+        assert!(epd::parse("8/8/8/8/8/4k3/8/4K2R w K").is_err());
+        assert!(epd::parse("8/8/8/8/8/4k3/8/4K2R w K - bm Ra1;").is_err());
+        assert_eq!(
+            epd::parse("8/8/8/8/8/4k3/8/4K2R w K - bm Rh8").unwrap()[0]
+                .best_moves
+                .len(),
+            1
+        );
+    }
+}
@@ -1,6 +1,11 @@
 mod core;
 pub use core::*;
 
+pub mod book;
 pub mod cli;
+pub mod epd;
 pub mod net;
+pub mod perft;
+pub mod pgn;
+pub mod syzygy;
 pub mod uci;
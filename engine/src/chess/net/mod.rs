@@ -3,58 +3,347 @@ pub mod trivial;
 #[cfg(feature = "stockfish")]
 pub mod stockfish;
 
-use crate::chess::{ChessBitboard, ChessGame, ChessPosition};
-use crate::game::{Bitboard, Game};
-use crate::mcts::value_func::ValueFunction;
-use crate::net::NNetwork;
+use std::sync::Arc;
 
-impl ValueFunction<ChessGame> for NNetwork<ChessGame> {
-    fn evaluate(&self, position: &ChessPosition) -> (Vec<(<ChessGame as Game>::Move, f32)>, f32) {
-        self.evaluate(position, position_to_planes)
+use crate::chess::{ChessBitboard, ChessDrawRules, ChessGame, ChessMove, ChessPosition};
+use crate::game::Bitboard;
+use crate::net::encoder::{EncodedGame, Encoder, FlatPolicyMap, PolicyMap};
+
+impl EncodedGame for ChessGame {
+    fn encoders() -> Vec<Arc<dyn Encoder<Self>>> {
+        vec![Arc::new(ChessBaseEncoder), Arc::new(ChessHistoryEncoder)]
+    }
+
+    fn policy_maps() -> Vec<Arc<dyn PolicyMap<Self>>> {
+        vec![Arc::new(FlatPolicyMap), Arc::new(ChessAlphaZeroPolicyMap)]
+    }
+}
+
+/// Pieces, castling rights, en passant and a plane of ones
+pub struct ChessBaseEncoder;
+impl ChessBaseEncoder {
+    pub const PLANES_NUM: usize = 18;
+}
+impl Encoder<ChessGame> for ChessBaseEncoder {
+    fn name(&self) -> &'static str {
+        "base"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn planes_num(&self) -> usize {
+        Self::PLANES_NUM
+    }
+
+    fn encode(&self, history: &[ChessPosition], planes: &mut [ChessBitboard]) {
+        assert_eq!(planes.len(), Self::PLANES_NUM);
+        let b = history.last().unwrap().get_raw_board();
+
+        /* 12 planes of pieces */
+        planes[0..12].copy_from_slice(&pieces_planes(b));
+
+        /* 4 planes of castling rights */
+        planes[12..16].copy_from_slice(&castling_planes(history.last().unwrap()));
+
+        /* A plane of en passant */
+        planes[16] = en_passant_plane(b);
+
+        /* A plane with all ones to help NN find board edges */
+        planes[17] = ChessBitboard::full(true);
+    }
+}
+
+/// The pieces and repetitions of the last positions, castling rights, en passant, fifty-move counter and a plane of
+/// ones, similar to AlphaZero and Lc0
+///
+/// Each of the last `HISTORY_LEN` positions is encoded as 12 pieces planes and 2 repetition planes, the current
+/// position first. The repetition planes are set if the position occurred at least once/twice before in the game,
+/// as counted by the draw rule. Positions before the beginning of the game are encoded as empty planes.
+pub struct ChessHistoryEncoder;
+impl ChessHistoryEncoder {
+    pub const HISTORY_LEN: usize = 8;
+    const PLANES_PER_POSITION: usize = 14;
+    /// The fifty-move counter is encoded in binary, enough for counts up to 63
+    const FIFTY_RULE_PLANES: usize = 6;
+    pub const PLANES_NUM: usize = Self::HISTORY_LEN * Self::PLANES_PER_POSITION + 4 + 1 + Self::FIFTY_RULE_PLANES + 1;
+
+    fn fifty_rule_count(pos: &ChessPosition) -> u8 {
+        (pos.halfmove_clock / 2).min((1 << Self::FIFTY_RULE_PLANES) - 1) as u8
+    }
+
+    /// The number of earlier occurrences of each of the last `HISTORY_LEN` positions, at most 2, the current first
+    fn repetitions(history: &[ChessPosition]) -> [u8; Self::HISTORY_LEN] {
+        let mut repetitions = [0; Self::HISTORY_LEN];
+        for (idx, pos) in history.iter().rev().take(Self::HISTORY_LEN).enumerate() {
+            let earlier = &history[..history.len() - 1 - idx];
+            repetitions[idx] = earlier.iter().filter(|p| *p == pos).take(2).count() as u8;
+        }
+        repetitions
+    }
+}
+impl Encoder<ChessGame> for ChessHistoryEncoder {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn planes_num(&self) -> usize {
+        Self::PLANES_NUM
+    }
+
+    fn history_len(&self) -> usize {
+        Self::HISTORY_LEN
+    }
+
+    /// Positions never repeat across a capture or a pawn move, and the game is drawn after at most 150 plies without
+    /// one, so older positions can not be repeated by the encoded ones
+    fn lookback_len(&self) -> usize {
+        Self::HISTORY_LEN + ChessDrawRules::Automatic.halfmove_limit() as usize
+    }
+
+    fn cache_key(&self, history: &[ChessPosition]) -> u64 {
+        let repetitions = Self::repetitions(history)
+            .iter()
+            .enumerate()
+            .fold(0, |key, (idx, r)| key | (*r as u64) << (2 * idx));
+        Self::fifty_rule_count(history.last().unwrap()) as u64 | repetitions << Self::FIFTY_RULE_PLANES
     }
+
+    fn encode(&self, history: &[ChessPosition], planes: &mut [ChessBitboard]) {
+        assert_eq!(planes.len(), Self::PLANES_NUM);
+        let repetitions = Self::repetitions(history);
+        let history = &history[history.len().saturating_sub(Self::HISTORY_LEN)..];
+        let pos = history.last().unwrap();
+        let b = pos.get_raw_board();
+
+        /* 14 planes for each position, the current first */
+        let (positions_planes, _) = planes.split_at_mut(Self::HISTORY_LEN * Self::PLANES_PER_POSITION);
+        positions_planes.fill(ChessBitboard::from_raw(0));
+        for (idx, (pos, position_planes)) in history
+            .iter()
+            .rev()
+            .zip(positions_planes.chunks_exact_mut(Self::PLANES_PER_POSITION))
+            .enumerate()
+        {
+            position_planes[0..12].copy_from_slice(&pieces_planes(pos.get_raw_board()));
+            position_planes[12] = ChessBitboard::full(repetitions[idx] >= 1);
+            position_planes[13] = ChessBitboard::full(repetitions[idx] >= 2);
+        }
+        let mut idx = Self::HISTORY_LEN * Self::PLANES_PER_POSITION;
+
+        /* 4 planes of castling rights */
+        planes[idx..idx + 4].copy_from_slice(&castling_planes(pos));
+        idx += 4;
+
+        /* A plane of en passant */
+        planes[idx] = en_passant_plane(b);
+        idx += 1;
+
+        /* Planes of the fifty-move counter bits, counted in full moves, least significant first */
+        let fifty_rule_count = Self::fifty_rule_count(pos);
+        for bit in 0..Self::FIFTY_RULE_PLANES {
+            planes[idx + bit] = ChessBitboard::full(fifty_rule_count & (1 << bit) != 0);
+        }
+        idx += Self::FIFTY_RULE_PLANES;
+
+        /* A plane with all ones to help NN find board edges */
+        planes[idx] = ChessBitboard::full(true);
+        assert!(idx + 1 == Self::PLANES_NUM);
+    }
+}
+
+/// The policy of AlphaZero, 73 planes of 8x8 indexed by `plane * 64 + source square`
+///
+/// The first 56 planes are queen-like moves, by 8 directions (N, NE, E, SE, S, SW, W, NW) and 7 distances. The next
+/// 8 planes are knight moves, and the last 9 planes are underpromotions to a knight, bishop or rook, capturing left,
+/// moving forward or capturing right. Promotions to a queen are queen-like moves, and castling is a king move to its
+/// destination square, or to the rook square in Chess960.
+pub struct ChessAlphaZeroPolicyMap;
+impl ChessAlphaZeroPolicyMap {
+    pub const PLANES_NUM: usize = 73;
+    /// The (file, rank) steps of the queen-like moves planes
+    const DIRECTIONS: [(i32, i32); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+    /// The (file, rank) offsets of the knight moves planes
+    const KNIGHT_MOVES: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
 }
+impl PolicyMap<ChessGame> for ChessAlphaZeroPolicyMap {
+    fn name(&self) -> &'static str {
+        "alphazero"
+    }
 
-pub const PLANES_NUM: usize = 18;
+    fn version(&self) -> u32 {
+        1
+    }
 
-pub fn position_to_planes(pos: &ChessPosition) -> Vec<ChessBitboard> {
-    let mut planes = [ChessBitboard::from_raw(0); PLANES_NUM];
-    let b = pos.get_raw_board();
+    fn moves_num(&self) -> usize {
+        Self::PLANES_NUM * 64
+    }
 
-    /* 12 planes of pieces */
-    let pawns = b.pieces(chess::Piece::Pawn);
-    let knights = b.pieces(chess::Piece::Knight);
-    let bishops = b.pieces(chess::Piece::Bishop);
-    let rooks = b.pieces(chess::Piece::Rook);
-    let queens = b.pieces(chess::Piece::Queen);
-    let kings = b.pieces(chess::Piece::King);
+    fn move_idx(&self, m: &ChessMove) -> usize {
+        let m = m.get_raw();
+        let (source, dest) = (m.get_source(), m.get_dest());
+        let df = dest.get_file().to_index() as i32 - source.get_file().to_index() as i32;
+        let dr = dest.get_rank().to_index() as i32 - source.get_rank().to_index() as i32;
+        let plane = match m.get_promotion() {
+            Some(piece) if piece != chess::Piece::Queen => {
+                let piece_idx = match piece {
+                    chess::Piece::Knight => 0,
+                    chess::Piece::Bishop => 1,
+                    chess::Piece::Rook => 2,
+                    _ => panic!("invalid promotion: {:?}", piece),
+                };
+                64 + (df + 1) as usize * 3 + piece_idx
+            }
+            _ => match Self::KNIGHT_MOVES.iter().position(|&offset| offset == (df, dr)) {
+                Some(knight_idx) => 56 + knight_idx,
+                None => {
+                    let direction = Self::DIRECTIONS
+                        .iter()
+                        .position(|&step| step == (df.signum(), dr.signum()))
+                        .unwrap();
+                    let distance = df.abs().max(dr.abs()) as usize;
+                    direction * 7 + distance - 1
+                }
+            },
+        };
+        plane * 64 + source.to_index()
+    }
+}
+
+/// 12 planes of pieces, white pawns, knights, bishops, rooks, queens and king, followed by the black ones
+fn pieces_planes(b: &chess::Board) -> [ChessBitboard; 12] {
     let white = b.color_combined(chess::Color::White);
     let black = b.color_combined(chess::Color::Black);
-    planes[0] = ChessBitboard::from(pawns & white);
-    planes[1] = ChessBitboard::from(knights & white);
-    planes[2] = ChessBitboard::from(bishops & white);
-    planes[3] = ChessBitboard::from(rooks & white);
-    planes[4] = ChessBitboard::from(queens & white);
-    planes[5] = ChessBitboard::from(kings & white);
-    planes[6] = ChessBitboard::from(pawns & black);
-    planes[7] = ChessBitboard::from(knights & black);
-    planes[8] = ChessBitboard::from(bishops & black);
-    planes[9] = ChessBitboard::from(rooks & black);
-    planes[10] = ChessBitboard::from(queens & black);
-    planes[11] = ChessBitboard::from(kings & black);
-
-    /* 4 planes of castling rights */
-    let white_cr = b.castle_rights(chess::Color::White);
-    let black_cr = b.castle_rights(chess::Color::Black);
-    planes[12] = ChessBitboard::full(white_cr.has_kingside());
-    planes[13] = ChessBitboard::full(white_cr.has_queenside());
-    planes[14] = ChessBitboard::full(black_cr.has_kingside());
-    planes[15] = ChessBitboard::full(black_cr.has_queenside());
-
-    /* A plane of en passant */
-    planes[16] = ChessBitboard::from_raw(b.en_passant().map_or(0, |s| 1 << s.to_index()));
-
-    /* A plane with all ones to help NN find board edges */
-    planes[17] = ChessBitboard::full(true);
-
-    planes.to_vec()
+    let pieces = [
+        chess::Piece::Pawn,
+        chess::Piece::Knight,
+        chess::Piece::Bishop,
+        chess::Piece::Rook,
+        chess::Piece::Queen,
+        chess::Piece::King,
+    ];
+    let mut planes = [ChessBitboard::from_raw(0); 12];
+    for (idx, piece) in pieces.into_iter().enumerate() {
+        planes[idx] = ChessBitboard::from(b.pieces(piece) & white);
+        planes[6 + idx] = ChessBitboard::from(b.pieces(piece) & black);
+    }
+    planes
+}
+
+/// 4 planes of castling rights, white kingside, white queenside, black kingside and black queenside
+fn castling_planes(pos: &ChessPosition) -> [ChessBitboard; 4] {
+    let white = chess::Color::White;
+    let black = chess::Color::Black;
+    [
+        ChessBitboard::full(pos.has_castling_right(white, true)),
+        ChessBitboard::full(pos.has_castling_right(white, false)),
+        ChessBitboard::full(pos.has_castling_right(black, true)),
+        ChessBitboard::full(pos.has_castling_right(black, false)),
+    ]
+}
+
+fn en_passant_plane(b: &chess::Board) -> ChessBitboard {
+    ChessBitboard::from_raw(b.en_passant().map_or(0, |s| 1 << s.to_index()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess::net::{ChessAlphaZeroPolicyMap, ChessBaseEncoder, ChessHistoryEncoder};
+    use crate::chess::{ChessBitboard, ChessGame, ChessMove};
+    use crate::game::{Bitboard, Game};
+    use crate::net::encoder::{Encoder, PolicyMap};
+
+    #[test]
+    fn alphazero_policy_map() {
+        /* The same indices are checked by the training package */
+        for (m, idx) in [
+            ("e2e4", 12 + 64),
+            ("g1f3", 6 + 63 * 64),
+            ("b1c3", 1 + 56 * 64),
+            ("h1a1", 7 + 6 * 7 * 64 + 6 * 64),
+            ("a1h8", 13 * 64),
+            ("e7e8q", 52),
+            ("d7c8n", 51 + 64 * 64),
+            ("d7d8b", 51 + 68 * 64),
+            ("d7e8r", 51 + 72 * 64),
+        ] {
+            assert_eq!(
+                ChessAlphaZeroPolicyMap.move_idx(&ChessMove::from_lan(m).unwrap()),
+                idx,
+                "{m}"
+            );
+        }
+        assert_eq!(ChessAlphaZeroPolicyMap.moves_num(), 4672);
+    }
+
+    #[test]
+    fn history_encoder() {
+        let mut game = ChessGame::new();
+        for m in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            game.play_single_turn(ChessMove::from_lan(m).unwrap());
+        }
+        let history = game.pos_history();
+        let planes = ChessHistoryEncoder.encode_to_vec(history);
+        assert_eq!(planes.len(), ChessHistoryEncoder::PLANES_NUM);
+
+        /* The current position pieces are the same as the base encoder's */
+        let base_planes = ChessBaseEncoder.encode_to_vec(history);
+        assert!(planes[0..12] == base_planes[0..12]);
+
+        /* The current position is the initial position repeated, the previous one is not repeated */
+        assert!(planes[12] == ChessBitboard::full(true));
+        assert!(planes[13] == ChessBitboard::full(false));
+        assert!(planes[14 + 12] == ChessBitboard::full(false));
+
+        /* Positions before the beginning of the game are empty */
+        assert!(planes[5 * 14..8 * 14].iter().all(|p| p.get_raw() == 0));
+
+        /* Castling rights, en passant, fifty-move counter of 2 and the ones plane */
+        let tail = &planes[8 * 14..];
+        assert!(tail[0..4].iter().all(|p| *p == ChessBitboard::full(true)));
+        assert!(tail[4].get_raw() == 0);
+        let fifty_rule_bits = tail[5..11]
+            .iter()
+            .map(|p| *p == ChessBitboard::full(true))
+            .collect::<Vec<_>>();
+        assert_eq!(fifty_rule_bits, [false, true, false, false, false, false]);
+        assert!(tail[11] == ChessBitboard::full(true));
+
+        /* Encoding into a used buffer overwrites all of its planes */
+        let mut reused = planes.clone();
+        ChessHistoryEncoder.encode(&history[..1], &mut reused);
+        assert!(reused == ChessHistoryEncoder.encode_to_vec(&history[..1]));
+    }
+
+    #[test]
+    fn history_encoder_old_repetitions() {
+        /* The initial position is repeated after 12 plies, before the last 8 positions */
+        let mut game = ChessGame::new();
+        for m in [
+            "g1f3", "g8f6", "f3e5", "f6h5", "e5d3", "h5f6", "d3f4", "f6g8", "f4h3", "b8c6", "h3g1", "c6b8",
+        ] {
+            game.play_single_turn(ChessMove::from_lan(m).unwrap());
+        }
+        let history = game.pos_history();
+        assert_eq!(history.len(), 13);
+        assert!(history[0] == history[12]);
+
+        let planes = ChessHistoryEncoder.encode_to_vec(history);
+        assert!(planes[12] == ChessBitboard::full(true));
+        assert!(planes[13] == ChessBitboard::full(false));
+        assert!(ChessHistoryEncoder.lookback_len() >= history.len());
+
+        /* The window alone has no repetition, so the old occurrence must be part of the cache key */
+        let window = &history[history.len() - ChessHistoryEncoder::HISTORY_LEN..];
+        assert!(ChessHistoryEncoder.encode_to_vec(window)[12] == ChessBitboard::full(false));
+        assert_ne!(
+            ChessHistoryEncoder.cache_key(history),
+            ChessHistoryEncoder.cache_key(window)
+        );
+    }
 }
@@ -12,13 +12,13 @@ use std::mem::MaybeUninit;
 
 use crate::chess::{ChessGame, ChessPosition};
 use crate::game::{Game, Position};
-use crate::mcts::value_func::ValueFunction;
+use crate::mcts::value_func::{Evaluation, ValueFunction};
 use crate::net;
 
 pub struct StockfishNet;
 impl ValueFunction<ChessGame> for StockfishNet {
-    fn evaluate(&self, position: &ChessPosition) -> (Vec<(<ChessGame as Game>::Move, f32)>, f32) {
-        let (position, is_flipped) = net::flip_pos_if_needed(*position);
+    fn evaluate(&self, history: &[ChessPosition]) -> Evaluation<<ChessGame as Game>::Move> {
+        let (position, is_flipped) = net::flip_pos_if_needed(*history.last().unwrap());
         let board = Board::from_fen(&position.fen()).unwrap();
 
         let val = Evaluation::evaluate(&board) as f32 / 1000.0;
@@ -27,7 +27,7 @@ impl ValueFunction<ChessGame> for StockfishNet {
         let move_count = moves.len() as f32;
         let moves_probs = moves.into_iter().map(|m| (m, 1.0 / move_count)).collect_vec();
 
-        net::flip_score_if_needed((moves_probs, val), is_flipped)
+        net::flip_eval_if_needed(Evaluation::new(moves_probs, val), is_flipped)
     }
 }
 
@@ -1022,19 +1022,20 @@ fn king_proximity(king_sq: SQ, sq: SQ) -> i32 {
 mod tests {
     use super::StockfishNet;
     use crate::{chess::ChessPosition, mcts::value_func::ValueFunction};
+    use std::slice;
 
     #[test]
     fn basic_evaluate() {
         let net = StockfishNet;
 
-        let pos1 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-        let pos2 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1");
-        let pos3 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
-        let pos4 = ChessPosition::from_fen("r1bqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        let pos1 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let pos2 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1").unwrap();
+        let pos3 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let pos4 = ChessPosition::from_fen("r1bqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap();
 
-        assert!(net.evaluate(&pos1).1 == -net.evaluate(&pos3).1);
-        assert!(net.evaluate(&pos2).1 == -net.evaluate(&pos4).1);
-        assert!(net.evaluate(&pos1).1 > net.evaluate(&pos2).1);
-        assert!(-net.evaluate(&pos3).1 > -net.evaluate(&pos4).1);
+        assert!(net.evaluate(slice::from_ref(&pos1)).value == -net.evaluate(slice::from_ref(&pos3)).value);
+        assert!(net.evaluate(slice::from_ref(&pos2)).value == -net.evaluate(slice::from_ref(&pos4)).value);
+        assert!(net.evaluate(slice::from_ref(&pos1)).value > net.evaluate(slice::from_ref(&pos2)).value);
+        assert!(-net.evaluate(slice::from_ref(&pos3)).value > -net.evaluate(slice::from_ref(&pos4)).value);
     }
 }
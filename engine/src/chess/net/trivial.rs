@@ -2,15 +2,16 @@ use itertools::Itertools;
 
 use crate::chess::{ChessGame, ChessPosition};
 use crate::game::{Game, Position};
-use crate::mcts::value_func::ValueFunction;
+use crate::mcts::value_func::{Evaluation, ValueFunction};
 use crate::net;
+use crate::net::encoder::FlatPolicyMap;
 
 /* Copied from https://github.com/LeelaChessZero/lc0/blob/master/src/neural/network_trivial.cc */
 
 pub struct TrivialNet;
 impl ValueFunction<ChessGame> for TrivialNet {
-    fn evaluate(&self, position: &ChessPosition) -> (Vec<(<ChessGame as Game>::Move, f32)>, f32) {
-        let (position, is_flipped) = net::flip_pos_if_needed(*position);
+    fn evaluate(&self, history: &[ChessPosition]) -> Evaluation<<ChessGame as Game>::Move> {
+        let (position, is_flipped) = net::flip_pos_if_needed(*history.last().unwrap());
         let positionf = position.flipped();
 
         let b = position.get_raw_board();
@@ -56,9 +57,9 @@ impl ValueFunction<ChessGame> for TrivialNet {
         val = 2.0 / (1.0 + (val * -10.0).exp()) - 1.0;
 
         let moves = position.legal_moves().collect_vec();
-        let moves_probs = net::calc_moves_probs::<ChessGame>(moves, &POLICY);
+        let moves_probs = net::calc_moves_probs::<ChessGame>(moves, &POLICY, &FlatPolicyMap, 1.0);
 
-        net::flip_score_if_needed((moves_probs, val), is_flipped)
+        net::flip_eval_if_needed(Evaluation::new(moves_probs, val), is_flipped)
     }
 }
 
@@ -332,19 +333,20 @@ mod tests {
     use crate::chess::net::trivial::TrivialNet;
     use crate::chess::ChessPosition;
     use crate::mcts::value_func::ValueFunction;
+    use std::slice;
 
     #[test]
     fn basic_evaluate() {
         let net = TrivialNet;
 
-        let pos1 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-        let pos2 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1");
-        let pos3 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
-        let pos4 = ChessPosition::from_fen("r1bqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        let pos1 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let pos2 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1").unwrap();
+        let pos3 = ChessPosition::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let pos4 = ChessPosition::from_fen("r1bqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap();
 
-        assert!(net.evaluate(&pos1).1 == -net.evaluate(&pos3).1);
-        assert!(net.evaluate(&pos2).1 == -net.evaluate(&pos4).1);
-        assert!(net.evaluate(&pos1).1 > net.evaluate(&pos2).1);
-        assert!(-net.evaluate(&pos3).1 > -net.evaluate(&pos4).1);
+        assert!(net.evaluate(slice::from_ref(&pos1)).value == -net.evaluate(slice::from_ref(&pos3)).value);
+        assert!(net.evaluate(slice::from_ref(&pos2)).value == -net.evaluate(slice::from_ref(&pos4)).value);
+        assert!(net.evaluate(slice::from_ref(&pos1)).value > net.evaluate(slice::from_ref(&pos2)).value);
+        assert!(-net.evaluate(slice::from_ref(&pos3)).value > -net.evaluate(slice::from_ref(&pos4)).value);
     }
 }
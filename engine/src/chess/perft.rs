@@ -0,0 +1,137 @@
+use itertools::Itertools;
+
+use crate::chess::{ChessMove, ChessPosition};
+use crate::game::Position;
+
+/// Count the leaves of the legal moves tree of a position up to some depth, used to verify the moves generation
+///
+/// As in the standard perft, only checkmates and stalemates end the game, the draw rules are ignored.
+pub fn perft(pos: &ChessPosition, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    pos.legal_moves()
+        .map(|m| perft(&pos.moved_position_unchecked(m), depth - 1))
+        .sum()
+}
+
+/// The perft count of each legal move of a position, empty for depth 0
+pub fn perft_divide(pos: &ChessPosition, depth: u32) -> Vec<(ChessMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    pos.legal_moves()
+        .map(|m| (m, perft(&pos.moved_position_unchecked(m), depth - 1)))
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::chess::perft::{perft, perft_divide};
+    use crate::chess::{ChessGame, ChessMove, ChessPosition};
+    use crate::game::{Game, GameColor, Move, Position};
+    use crate::net::encoder::EncodedGame;
+
+    /// Standard perft positions and their counts at increasing depths
+    const PERFT_POSITIONS: [(&str, &[u64]); 7] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902],
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039],
+        ),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812]),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        ),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486]),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079],
+        ),
+        /* Chess960 */
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12189],
+        ),
+    ];
+
+    #[test]
+    fn perft_positions() {
+        for (fen, counts) in PERFT_POSITIONS {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            for (depth, &count) in counts.iter().enumerate() {
+                assert_eq!(perft(&pos, depth as u32 + 1), count, "{fen} depth {}", depth + 1);
+            }
+            let divide = perft_divide(&pos, counts.len() as u32);
+            assert_eq!(divide.len() as u64, counts[0]);
+            assert_eq!(
+                divide.iter().map(|(_m, count)| count).sum::<u64>(),
+                *counts.last().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn perft_draw_rules() {
+        /* Positions drawn by the fifty-move rule or by insufficient material are expanded */
+        for (fen, counts) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 100 60", [20, 400]),
+            ("8/8/8/4k3/8/8/8/4K3 w - - 0 1", [5, 40]),
+        ] {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            assert!(!pos.status().is_ongoing());
+            for (depth, count) in counts.into_iter().enumerate() {
+                assert_eq!(perft(&pos, depth as u32 + 1), count, "{fen} depth {}", depth + 1);
+            }
+        }
+        let pos = ChessPosition::new();
+        assert_eq!(perft(&pos, 0), 1);
+        assert!(perft_divide(&pos, 0).is_empty());
+    }
+
+    #[test]
+    fn moves_indices() {
+        /* The network sees positions with white to move, black moves are flipped */
+        let policy_maps = ChessGame::policy_maps();
+        let mut nn_idx_to_move = vec![HashMap::new(); policy_maps.len()];
+        let mut check_position = |pos: &ChessPosition| {
+            let moves = pos.legal_moves().collect::<HashSet<_>>();
+            for &m in moves.iter() {
+                assert_eq!(m.flipped().flipped(), m);
+                let nn_move = match pos.turn() {
+                    GameColor::Player1 => m,
+                    GameColor::Player2 => m.flipped(),
+                };
+                assert!(nn_move.to_nn_idx() < ChessGame::MOVES_NUM);
+                for (policy_map, nn_idx_to_move) in policy_maps.iter().zip(nn_idx_to_move.iter_mut()) {
+                    let nn_idx = policy_map.move_idx(&nn_move);
+                    assert!(nn_idx < policy_map.moves_num());
+                    assert_eq!(*nn_idx_to_move.entry(nn_idx).or_insert(nn_move), nn_move);
+                }
+            }
+            let pos_t = pos.flipped();
+            assert!(pos_t.flipped() == *pos);
+            let moves_t = pos_t.legal_moves().map(|m| m.flipped()).collect::<HashSet<ChessMove>>();
+            assert_eq!(moves_t, moves);
+        };
+        for (fen, _counts) in PERFT_POSITIONS {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            check_position(&pos);
+            for m1 in pos.legal_moves() {
+                let pos1 = pos.moved_position(m1);
+                check_position(&pos1);
+                if pos1.status().is_ongoing() {
+                    for m2 in pos1.legal_moves() {
+                        check_position(&pos1.moved_position(m2));
+                    }
+                }
+            }
+        }
+    }
+}
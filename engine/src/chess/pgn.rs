@@ -0,0 +1,549 @@
+use itertools::Itertools;
+use std::fmt::{self, Display};
+
+use crate::chess::{ChessDrawRules, ChessGame, ChessMove, ChessPosition};
+use crate::game::{Game, GameColor, GameStatus, Position};
+
+/// A chess game in PGN (Portable Game Notation)
+pub struct PgnGame {
+    /// The tag pairs, in the order they are written
+    pub tags: Vec<(String, String)>,
+    /// The position before the first move, set by the 'FEN' tag
+    pub start: ChessPosition,
+    /// A comment before the first move
+    pub comment: Option<String>,
+    /// The main line moves
+    pub moves: Vec<PgnMove>,
+    pub result: GameStatus,
+}
+
+pub struct PgnMove {
+    pub m: ChessMove,
+    /// A comment following the move
+    pub comment: Option<String>,
+    /// Alternative lines to this move, each starting from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(m: ChessMove) -> Self {
+        Self {
+            m,
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+
+    pub fn with_comment(m: ChessMove, comment: String) -> Self {
+        Self {
+            m,
+            comment: Some(comment),
+            variations: Vec::new(),
+        }
+    }
+}
+
+impl PgnGame {
+    /// Create a game with the seven tag roster, with unknown values
+    pub fn new(start: ChessPosition) -> Self {
+        let mut game = Self {
+            tags: Vec::new(),
+            start,
+            comment: None,
+            moves: Vec::new(),
+            result: GameStatus::Ongoing,
+        };
+        for (name, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ] {
+            game.set_tag(name, value);
+        }
+        if start.chess960 {
+            game.set_tag("Variant", "Chess960");
+        }
+        if start != ChessPosition::new() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &start.fen());
+        }
+        game
+    }
+
+    /// Create a game from the moves and result of a played game
+    pub fn from_game(game: &ChessGame) -> Self {
+        let positions = game.pos_history();
+        let moves = positions
+            .iter()
+            .tuple_windows()
+            .map(|(pos, next)| {
+                let m = pos.legal_moves().find(|m| pos.moved_position(*m) == *next).unwrap();
+                PgnMove::new(m)
+            })
+            .collect_vec();
+        let mut pgn = Self::new(positions[0]);
+        pgn.moves = moves;
+        pgn.set_result(game.status());
+        pgn
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Set the value of a tag, replacing the existing value or adding the tag at the end
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Set the game result and the 'Result' tag
+    pub fn set_result(&mut self, result: GameStatus) {
+        self.result = result;
+        self.set_tag("Result", result_str(result));
+    }
+
+    /// The main line positions, starting with the start position
+    pub fn positions(&self) -> Vec<ChessPosition> {
+        let mut positions = vec![self.start];
+        for m in &self.moves {
+            positions.push(positions.last().unwrap().moved_position(m.m));
+        }
+        positions
+    }
+
+    /// Play the main line moves from the start position
+    ///
+    /// The game has the draw rules of the start position, which are the automatic draw rules for parsed games. Fails
+    /// if the game is over before all the moves are played.
+    pub fn to_game(&self) -> Result<ChessGame, String> {
+        let mut game = ChessGame::from_position(self.start);
+        for (idx, m) in self.moves.iter().enumerate() {
+            if !game.status().is_ongoing() {
+                return Err(format!("Game is over before move {} ({})", idx + 1, m.m));
+            }
+            game.play_single_turn(m.m);
+        }
+        Ok(game)
+    }
+}
+
+/// A move comment with the search value and number of visits, as written by the engine players
+pub fn search_comment(value: f32, visits: u32) -> String {
+    format!("{:+.3}/{}", value, visits)
+}
+
+fn result_str(result: GameStatus) -> &'static str {
+    match result {
+        GameStatus::Ongoing => "*",
+        GameStatus::Finished(Some(GameColor::Player1)) => "1-0",
+        GameStatus::Finished(Some(GameColor::Player2)) => "0-1",
+        GameStatus::Finished(None) => "1/2-1/2",
+    }
+}
+
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(comment_str(comment));
+        }
+        write_moves(&self.moves, self.start, &mut tokens);
+        tokens.push(result_str(self.result).to_string());
+
+        /* Export format lines are limited to 80 characters */
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 79 {
+                writeln!(f)?;
+                line_len = 0;
+            }
+            if line_len > 0 {
+                write!(f, " ")?;
+                line_len += 1;
+            }
+            write!(f, "{}", token)?;
+            line_len += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+fn comment_str(comment: &str) -> String {
+    format!("{{{}}}", comment.replace('}', ")"))
+}
+
+fn write_moves(moves: &[PgnMove], start: ChessPosition, tokens: &mut Vec<String>) {
+    let mut pos = start;
+    let mut need_number = true;
+    for m in moves {
+        /* The move number is kept on the same line as the move */
+        let number = pos.fullmove_number;
+        tokens.push(match pos.turn() {
+            GameColor::Player1 => format!("{}. {}", number, m.m.to_san(&pos)),
+            GameColor::Player2 if need_number => format!("{}... {}", number, m.m.to_san(&pos)),
+            GameColor::Player2 => m.m.to_san(&pos),
+        });
+        need_number = false;
+        if let Some(comment) = &m.comment {
+            tokens.push(comment_str(comment));
+            need_number = true;
+        }
+        for variation in &m.variations {
+            let mut variation_tokens = Vec::new();
+            write_moves(variation, pos, &mut variation_tokens);
+            variation_tokens[0].insert(0, '(');
+            variation_tokens.last_mut().unwrap().push(')');
+            tokens.extend(variation_tokens);
+            need_number = true;
+        }
+        pos = pos.moved_position(m.m);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    VariationBegin,
+    VariationEnd,
+    Move(String),
+    Result(GameStatus),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            /* Escaped line */
+            '%' if line_start => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+            }
+            ';' => {
+                let comment = chars.by_ref().take_while(|c| *c != '\n').collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err("Unterminated comment".to_string()),
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().join(" ")));
+            }
+            '[' => {
+                let tag = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+                let (name, value) = tag
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("Invalid tag: '{}'", tag))?;
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .ok_or_else(|| format!("Invalid tag value: '{}'", tag))?;
+                tokens.push(Token::Tag(
+                    name.to_string(),
+                    value.replace("\\\"", "\"").replace("\\\\", "\\"),
+                ));
+            }
+            '(' => tokens.push(Token::VariationBegin),
+            ')' => tokens.push(Token::VariationEnd),
+            /* Numeric annotation glyph */
+            '$' => while chars.next_if(|c| c.is_ascii_digit()).is_some() {},
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}()[];$".contains(*c)) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "1-0" => tokens.push(Token::Result(GameStatus::Finished(Some(GameColor::Player1)))),
+                    "0-1" => tokens.push(Token::Result(GameStatus::Finished(Some(GameColor::Player2)))),
+                    "1/2-1/2" => tokens.push(Token::Result(GameStatus::Finished(None))),
+                    "*" => tokens.push(Token::Result(GameStatus::Ongoing)),
+                    /* En passant suffix of the previous move */
+                    "e.p." => {}
+                    _ => {
+                        /* Move numbers such as '12.' or '12...', possibly attached to the move. Digits without a
+                         * dot are kept, as in castling written with zeros */
+                        let after_digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                        let after_dots = after_digits.trim_start_matches('.');
+                        let word = if after_dots.len() < after_digits.len() {
+                            after_dots
+                        } else {
+                            &word
+                        };
+                        if !word.is_empty() {
+                            tokens.push(Token::Move(word.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+/// Parse a move in SAN, accepting the common variants of the notation
+pub(crate) fn parse_san(pos: &ChessPosition, san: &str) -> Result<ChessMove, String> {
+    let normalized = san.trim_end_matches(['!', '?']).replace('0', "O");
+    let normalized = normalized.strip_suffix("e.p.").unwrap_or(&normalized);
+    let m = ChessMove::from_san(pos, normalized).map_err(|_| format!("Invalid move '{}' in '{}'", san, pos.fen()))?;
+    if !pos.is_valid_move(m) {
+        return Err(format!("Move '{}' after the end of the game in '{}'", san, pos.fen()));
+    }
+    Ok(m)
+}
+
+/// Parse all the games of a PGN string
+///
+/// If `keep_variations` is false the variations are skipped without being validated, otherwise they are parsed and
+/// attached to the move they replace. Comments before the first move of a variation are dropped.
+pub fn parse(s: &str, keep_variations: bool) -> Result<Vec<PgnGame>, String> {
+    let mut tokens = tokenize(s)?.into_iter().peekable();
+    let mut games = Vec::new();
+    while tokens.peek().is_some() {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = tokens.next_if(|t| matches!(t, Token::Tag(..))) {
+            tags.push((name, value));
+        }
+        let chess960 = tags.iter().any(|(name, value)| {
+            name == "Variant" && (value.contains("960") || value.eq_ignore_ascii_case("fischerandom"))
+        });
+        /* Games may continue after a draw that was not claimed */
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) if chess960 => ChessPosition::from_fen_chess960(fen)?,
+            Some((_, fen)) => ChessPosition::from_fen(fen)?,
+            None if chess960 => ChessPosition::new_chess960(518),
+            None => ChessPosition::new(),
+        }
+        .with_draw_rules(ChessDrawRules::Automatic);
+        let mut comment: Option<String> = None;
+        while let Some(Token::Comment(next)) = tokens.next_if(|t| matches!(t, Token::Comment(_))) {
+            comment = Some(match comment {
+                Some(prev) => format!("{} {}", prev, next),
+                None => next,
+            });
+        }
+        let moves = parse_moves(&mut tokens, start, keep_variations)?;
+        let result = match tokens.next() {
+            Some(Token::Result(result)) => result,
+            Some(Token::VariationEnd) => return Err("Unexpected variation end".to_string()),
+            Some(token) => return Err(format!("Unexpected token {:?}", token)),
+            None => GameStatus::Ongoing,
+        };
+        let mut game = PgnGame {
+            tags,
+            start,
+            comment,
+            moves,
+            result,
+        };
+        if game.tag("Result").is_none() {
+            game.set_result(result);
+        }
+        games.push(game);
+    }
+    Ok(games)
+}
+
+fn parse_moves(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = Token>>,
+    start: ChessPosition,
+    keep_variations: bool,
+) -> Result<Vec<PgnMove>, String> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut pos = start;
+    let mut prev_pos = start;
+    loop {
+        match tokens.peek() {
+            Some(Token::Move(_)) => {
+                let Some(Token::Move(san)) = tokens.next() else {
+                    unreachable!()
+                };
+                let m = parse_san(&pos, &san)?;
+                prev_pos = pos;
+                pos = pos.moved_position(m);
+                moves.push(PgnMove::new(m));
+            }
+            Some(Token::Comment(_)) => {
+                let Some(Token::Comment(comment)) = tokens.next() else {
+                    unreachable!()
+                };
+                if let Some(last) = moves.last_mut() {
+                    last.comment = Some(match last.comment.take() {
+                        Some(prev) => format!("{} {}", prev, comment),
+                        None => comment,
+                    });
+                }
+            }
+            Some(Token::VariationBegin) => {
+                tokens.next();
+                let Some(last) = moves.last_mut() else {
+                    return Err("Variation before the first move".to_string());
+                };
+                if keep_variations {
+                    let variation = parse_moves(tokens, prev_pos, keep_variations)?;
+                    if !variation.is_empty() {
+                        last.variations.push(variation);
+                    }
+                    if tokens.next() != Some(Token::VariationEnd) {
+                        return Err("Unterminated variation".to_string());
+                    }
+                } else {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match tokens.next() {
+                            Some(Token::VariationBegin) => depth += 1,
+                            Some(Token::VariationEnd) => depth -= 1,
+                            Some(_) => {}
+                            None => return Err("Unterminated variation".to_string()),
+                        }
+                    }
+                }
+            }
+            Some(Token::VariationEnd) | Some(Token::Result(_)) | Some(Token::Tag(..)) | None => return Ok(moves),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess::pgn::{self, PgnGame, PgnMove};
+    use crate::chess::{ChessGame, ChessMove, ChessPosition};
+    use crate::game::{Game, GameColor, GameStatus, Position};
+    use itertools::Itertools;
+
+    #[test]
+    fn parse_and_write() {
+        let pgn_str = r#"[Event "Casual Game"]
+[Site "Berlin GER"]
+[Date "1852.??.??"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Jean Dufresne"]
+[Result "1-0"]
+
+1.e4 e5 2.Nf3 Nc6 3.Bc4 Bc5 4.b4 Bxb4 5.c3 Ba5 6.d4 exd4 7.O-O d3 8.Qb3 Qf6
+9.e5 Qg6 10.Re1 Nge7 11.Ba3 b5 12.Qxb5 Rb8 13.Qa4 Bb6 14.Nbd2 Bb7 15.Ne4 Qf5
+16.Bxd3 Qh5 17.Nf6+ gxf6 18.exf6 Rg8 19.Rad1 Qxf3 20.Rxe7+ Nxe7 21.Qxd7+ Kxd7
+22.Bf5+ Ke8 23.Bd7+ Kf8 24.Bxe7# 1-0
+"#;
+        let games = pgn::parse(pgn_str, false).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Adolf Anderssen"));
+        assert_eq!(game.moves.len(), 47);
+        assert_eq!(game.result, GameStatus::Finished(Some(GameColor::Player1)));
+        assert_eq!(
+            game.to_game().unwrap().status(),
+            GameStatus::Finished(Some(GameColor::Player1))
+        );
+
+        /* Written with the export format, and parsed back */
+        let written = game.to_string();
+        assert!(written.contains("7. O-O d3"));
+        assert!(written.contains("17. Nf6+ gxf6"));
+        assert!(written.contains("24. Bxe7# 1-0"));
+        assert!(written.lines().all(|line| line.len() < 80));
+        let reparsed = pgn::parse(&written, false).unwrap();
+        assert_eq!(reparsed[0].to_string(), written);
+    }
+
+    #[test]
+    fn comments_and_variations() {
+        let pgn_str = r#"[Event "?"]
+[SetUp "1"]
+[FEN "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"]
+
+{Opening} 1... e5 {Open game} (1... c5 2. Nf3 (2. c3) d6; Sicilian
+) 2. Nf3!? $1 Nc6 (2... d6 {Philidor}) 3. Bb5 a6 *
+"#;
+        let games = pgn::parse(pgn_str, false).unwrap();
+        let game = &games[0];
+        assert_eq!(game.comment.as_deref(), Some("Opening"));
+        assert_eq!(game.moves.len(), 5);
+        assert_eq!(game.moves[0].comment.as_deref(), Some("Open game"));
+        assert!(game.moves.iter().all(|m| m.variations.is_empty()));
+        assert_eq!(game.result, GameStatus::Ongoing);
+        assert_eq!(
+            game.positions()[1].fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+        );
+
+        let games = pgn::parse(pgn_str, true).unwrap();
+        let game = &games[0];
+        assert_eq!(game.moves[0].variations.len(), 1);
+        let sicilian = &game.moves[0].variations[0];
+        assert_eq!(sicilian.len(), 3);
+        assert_eq!(sicilian[2].comment.as_deref(), Some("Sicilian"));
+        assert_eq!(sicilian[1].variations.len(), 1);
+        assert_eq!(game.moves[2].variations[0][0].comment.as_deref(), Some("Philidor"));
+
+        let written = game.to_string();
+        let movetext = written.split_whitespace().join(" ");
+        assert!(movetext.contains("{Opening} 1... e5 {Open game} (1... c5 2. Nf3 (2. c3) 2... d6 {Sicilian}) 2. Nf3"));
+        assert!(movetext.contains("Nc6 (2... d6 {Philidor}) 3. Bb5 a6 *"));
+        assert_eq!(pgn::parse(&written, true).unwrap()[0].to_string(), written);
+
+        assert!(pgn::parse("1. e4 e6 2. Ke3 *", false).is_err());
+        assert!(pgn::parse("1. e4 (1. d4 d5 *", true).is_err());
+        assert!(pgn::parse("1. e4 {unterminated", false).is_err());
+    }
+
+    #[test]
+    fn zero_castling_and_comments() {
+        let games = pgn::parse(
+            "{First} {Second} 1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 Nf6 5. d3 d6 6. Nc3 Bg4 7. h3 Qd7 8. hxg4 0-0-0 *",
+            false,
+        )
+        .unwrap();
+        let game = &games[0];
+        assert_eq!(game.comment.as_deref(), Some("First Second"));
+        assert_eq!(game.moves.len(), 16);
+        let positions = game.positions();
+        assert_eq!(game.moves[6].m, ChessMove::from_san(&positions[6], "O-O").unwrap());
+        assert_eq!(game.moves[15].m, ChessMove::from_san(&positions[15], "O-O-O").unwrap());
+    }
+
+    #[test]
+    fn from_game() {
+        let mut game = ChessGame::new();
+        let mut pos = ChessPosition::new();
+        for san in ["f3", "e5", "g4", "Qh4"] {
+            let m = ChessMove::from_san(&pos, san).unwrap();
+            pos = pos.moved_position(m);
+            game.play_single_turn(m);
+        }
+        let mut pgn = PgnGame::from_game(&game);
+        assert_eq!(pgn.tag("Result"), Some("0-1"));
+        pgn.moves[3] = PgnMove::with_comment(pgn.moves[3].m, pgn::search_comment(0.5, 800));
+        assert_eq!(
+            pgn.to_string().lines().last().unwrap(),
+            "1. f3 e5 2. g4 Qh4# {+0.500/800} 0-1"
+        );
+        assert!(PgnGame::from_game(&pgn.to_game().unwrap()).positions() == pgn.positions());
+    }
+}
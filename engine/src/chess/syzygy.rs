@@ -0,0 +1,1413 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, OnceLock};
+
+use crate::chess::{ChessGame, ChessMove, ChessPosition};
+use crate::game::{GameColor, GameStatus, Position};
+use crate::mcts::tablebase::Tablebase;
+
+/// Syzygy endgame tablebases, the WDL ('.rtbw') and DTZ ('.rtbz') files of a directory
+///
+/// The decoding follows the Stockfish implementation. The tables assume the fifty-move rule, and are not probed for
+/// positions with castling rights.
+pub struct Syzygy {
+    /// The tables by material key, such as "KRvK", each table is present for its two keys
+    tables: HashMap<String, Arc<TableEntry>>,
+    max_pieces: usize,
+}
+
+/// The value of a position with perfect play, for the side to move
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SyzygyWdl {
+    Loss = -2,
+    /// A loss that is a draw by the fifty-move rule
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win that is a draw by the fifty-move rule
+    CursedWin = 1,
+    Win = 2,
+}
+impl SyzygyWdl {
+    fn from_value(value: i32) -> Option<Self> {
+        Some(match value {
+            -2 => Self::Loss,
+            -1 => Self::BlessedLoss,
+            0 => Self::Draw,
+            1 => Self::CursedWin,
+            2 => Self::Win,
+            _ => return None,
+        })
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+impl std::ops::Neg for SyzygyWdl {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::from_value(-(self as i32)).unwrap()
+    }
+}
+
+const MAX_PIECES: usize = 7;
+const MAX_DTZ: i32 = 1 << 18;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/* File flags */
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+/* Table flags */
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+impl Syzygy {
+    /// Find the tables of a directory, the table files are read on their first probe
+    pub fn new(dir: &Path) -> std::io::Result<Self> {
+        let mut tables = HashMap::new();
+        for dir_entry in std::fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|ext| ext != "rtbw") {
+                continue;
+            }
+            let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if !is_material_key(key) {
+                continue;
+            }
+            let dtz_path = path.with_extension("rtbz");
+            let dtz_path = dtz_path.exists().then_some(dtz_path);
+            let entry = Arc::new(TableEntry::new(key, path.clone(), dtz_path));
+            tables.insert(entry.key2.clone(), Arc::clone(&entry));
+            tables.insert(entry.key.clone(), entry);
+        }
+        let max_pieces = tables.values().map(|entry| entry.piece_count).max().unwrap_or(0);
+        Ok(Self { tables, max_pieces })
+    }
+
+    /// The maximum number of pieces, kings included, of the available tables
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    fn can_probe(&self, pos: &ChessPosition) -> bool {
+        let no_castling = [chess::Color::White, chess::Color::Black]
+            .into_iter()
+            .cartesian_product([true, false])
+            .all(|(color, kingside)| !pos.has_castling_right(color, kingside));
+        no_castling && pos.board.combined().popcnt() as usize <= self.max_pieces
+    }
+
+    /// The value of a position for the side to move, or `None` if the position is not in the tables
+    pub fn probe_wdl(&self, pos: &ChessPosition) -> Option<SyzygyWdl> {
+        if !self.can_probe(pos) {
+            return None;
+        }
+        Some(self.search(&pos.board, false)?.0)
+    }
+
+    /// The number of plies to the next capture or pawn move with perfect play, positive for a win and negative for
+    /// a loss, 0 for a draw, or `None` if the position is not in the tables
+    ///
+    /// The distance is off by one ply in some cases, but always keeps the outcome within the fifty-move rule.
+    pub fn probe_dtz(&self, pos: &ChessPosition) -> Option<i32> {
+        if !self.can_probe(pos) {
+            return None;
+        }
+        self.probe_dtz_impl(&pos.board)
+    }
+
+    /// Rank the legal moves of a position, higher is better, or `None` if the position is not in the tables
+    ///
+    /// Winning moves are ranked by their distance to zeroing the fifty-move counter, and losing moves are ranked by
+    /// how long they delay the loss.
+    pub fn rank_moves(&self, pos: &ChessPosition) -> Option<Vec<(ChessMove, i32)>> {
+        if !self.can_probe(pos) || !pos.status().is_ongoing() {
+            return None;
+        }
+        let halfmove_clock = pos.halfmove_clock as i32;
+        pos.legal_moves()
+            .map(|m| {
+                let next_pos = pos.moved_position(m);
+                let dtz = match next_pos.status() {
+                    /* Only the moving player can win by its move */
+                    GameStatus::Finished(Some(_)) => 1,
+                    GameStatus::Finished(None) => 0,
+                    GameStatus::Ongoing if next_pos.halfmove_clock == 0 => {
+                        dtz_before_zeroing(-self.search(&next_pos.board, false)?.0)
+                    }
+                    GameStatus::Ongoing => {
+                        let dtz = -self.probe_dtz_impl(&next_pos.board)?;
+                        dtz + dtz.signum()
+                    }
+                };
+                let rank = match dtz.signum() {
+                    1 => MAX_DTZ - (dtz + halfmove_clock),
+                    -1 => -MAX_DTZ + (-dtz + halfmove_clock),
+                    _ => 0,
+                };
+                Some((m, rank))
+            })
+            .collect()
+    }
+
+    /// Probe the WDL table of a position, the stored value may be wrong if a capture is better
+    fn probe_wdl_table(&self, board: &chess::Board) -> Option<SyzygyWdl> {
+        if board.combined().popcnt() == 2 {
+            return Some(SyzygyWdl::Draw);
+        }
+        let entry = self.tables.get(&material_key(board))?;
+        let table = entry.wdl()?;
+        let (_, value) = table.probe(entry, board, None)?;
+        SyzygyWdl::from_value(value)
+    }
+
+    /// Search the captures of a position, and its pawn moves if `zeroing_moves`, and return its value and whether the
+    /// best move is one of the searched moves
+    ///
+    /// Tables store arbitrary values for positions in which a capture is better than the stored value, as well as
+    /// DTZ tables for positions in which a zeroing move is best.
+    fn search(&self, board: &chess::Board, zeroing_moves: bool) -> Option<(SyzygyWdl, bool)> {
+        let moves = chess::MoveGen::new_legal(board);
+        let moves_num = moves.len();
+        let mut searched_num = 0;
+        let mut best_value = SyzygyWdl::Loss;
+        for m in moves {
+            let is_pawn = board.piece_on(m.get_source()) == Some(chess::Piece::Pawn);
+            if !(is_capture(board, m) || zeroing_moves && is_pawn) {
+                continue;
+            }
+            searched_num += 1;
+            let value = -self.search(&board.make_move_new(m), false)?.0;
+            if value > best_value {
+                best_value = value;
+                if value >= SyzygyWdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        /* If all moves were searched the stored value may be wrong, for example with en passant moves */
+        let all_searched = searched_num > 0 && searched_num == moves_num;
+        let value = if all_searched {
+            best_value
+        } else {
+            self.probe_wdl_table(board)?
+        };
+        if best_value >= value {
+            Some((best_value, best_value > SyzygyWdl::Draw || all_searched))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn probe_dtz_impl(&self, board: &chess::Board) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == SyzygyWdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        let entry = self.tables.get(&material_key(board))?;
+        let table = entry.dtz()?;
+        if let Some((_, dtz)) = table.probe(entry, board, Some(wdl)) {
+            let cursed = matches!(wdl, SyzygyWdl::CursedWin | SyzygyWdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+
+        /* The table stores the other side to move only, search one ply for the best distance */
+        let mut min_dtz = i32::MAX;
+        for m in chess::MoveGen::new_legal(board) {
+            let is_zeroing = is_capture(board, m) || board.piece_on(m.get_source()) == Some(chess::Piece::Pawn);
+            let next_board = board.make_move_new(m);
+            /* For zeroing moves the distance is of the move itself, as the next distance restarts after it */
+            let mut dtz = if is_zeroing {
+                -dtz_before_zeroing(self.search(&next_board, false)?.0)
+            } else {
+                -self.probe_dtz_impl(&next_board)?
+            };
+            if dtz == 1 && next_board.status() == chess::BoardStatus::Checkmate {
+                min_dtz = 1;
+            }
+            if !is_zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        /* Without legal moves the position is a mate */
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+}
+
+impl Tablebase<ChessGame> for Syzygy {
+    fn probe(&self, pos: &ChessPosition) -> Option<Option<GameColor>> {
+        let wdl = match self.probe_wdl(pos)? {
+            /* A win or loss may be too far for the fifty-move rule, check it if the DTZ table is available */
+            wdl @ (SyzygyWdl::Win | SyzygyWdl::Loss) if pos.halfmove_clock > 0 => match self.probe_dtz(pos) {
+                Some(dtz) if dtz.abs() + pos.halfmove_clock as i32 > 100 => SyzygyWdl::Draw,
+                _ => wdl,
+            },
+            SyzygyWdl::CursedWin | SyzygyWdl::BlessedLoss => SyzygyWdl::Draw,
+            wdl => wdl,
+        };
+        Some(match wdl {
+            SyzygyWdl::Win => Some(pos.turn()),
+            SyzygyWdl::Loss => Some(pos.turn().opposite()),
+            _ => None,
+        })
+    }
+
+    fn best_moves(&self, pos: &ChessPosition) -> Option<Vec<ChessMove>> {
+        let ranked_moves = self.rank_moves(pos)?;
+        let best_rank = ranked_moves.iter().map(|(_m, rank)| *rank).max()?;
+        Some(
+            ranked_moves
+                .into_iter()
+                .filter(|(_m, rank)| *rank == best_rank)
+                .map(|(m, _rank)| m)
+                .collect_vec(),
+        )
+    }
+}
+
+fn dtz_before_zeroing(wdl: SyzygyWdl) -> i32 {
+    match wdl {
+        SyzygyWdl::Win => 1,
+        SyzygyWdl::CursedWin => 101,
+        SyzygyWdl::BlessedLoss => -101,
+        SyzygyWdl::Loss => -1,
+        SyzygyWdl::Draw => 0,
+    }
+}
+
+fn is_capture(board: &chess::Board, m: chess::ChessMove) -> bool {
+    let is_pawn = board.piece_on(m.get_source()) == Some(chess::Piece::Pawn);
+    board.piece_on(m.get_dest()).is_some() || (is_pawn && m.get_source().get_file() != m.get_dest().get_file())
+}
+
+/// The material of a position, such as "KRvKN", white first and pieces in the tables order
+fn material_key(board: &chess::Board) -> String {
+    [chess::Color::White, chess::Color::Black]
+        .into_iter()
+        .map(|color| {
+            [
+                chess::Piece::King,
+                chess::Piece::Queen,
+                chess::Piece::Rook,
+                chess::Piece::Bishop,
+                chess::Piece::Knight,
+                chess::Piece::Pawn,
+            ]
+            .into_iter()
+            .map(|piece| {
+                let count = (board.pieces(piece) & board.color_combined(color)).popcnt() as usize;
+                piece.to_string(chess::Color::White).repeat(count)
+            })
+            .collect::<String>()
+        })
+        .join("v")
+}
+
+fn is_material_key(key: &str) -> bool {
+    let Some((white, black)) = key.split_once('v') else {
+        return false;
+    };
+    key.len() <= MAX_PIECES + 1
+        && [white, black]
+            .iter()
+            .all(|side| side.starts_with('K') && side[1..].chars().all(|c| "QRBNP".contains(c)))
+}
+
+/// The piece on a square, encoded as in the table files
+fn piece_code(board: &chess::Board, square: chess::Square) -> u8 {
+    let piece = board.piece_on(square).unwrap();
+    let color = board.color_on(square).unwrap();
+    piece.to_index() as u8 + 1 + 8 * color.to_index() as u8
+}
+
+/// A table of a material, with both its WDL and DTZ files
+struct TableEntry {
+    key: String,
+    /// The key of the material with the colors swapped
+    key2: String,
+    piece_count: usize,
+    has_pawns: bool,
+    /// Whether a player has a single piece of some kind, kings excluded
+    has_unique_pieces: bool,
+    /// The number of pawns of the leading color and of the other color
+    pawn_count: [usize; 2],
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+impl TableEntry {
+    fn new(key: &str, wdl_path: PathBuf, dtz_path: Option<PathBuf>) -> Self {
+        let (white, black) = key.split_once('v').unwrap();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|piece| side.matches(piece).count() == 1));
+        /* The leading color is the one with less pawns, but at least one */
+        let (white_pawns, black_pawns) = (white.matches('P').count(), black.matches('P').count());
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Self {
+            key: key.to_string(),
+            key2: format!("{black}v{white}"),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            wdl_path,
+            dtz_path,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        }
+    }
+
+    fn wdl(&self) -> Option<&Table> {
+        self.wdl
+            .get_or_init(|| Table::open(&self.wdl_path, self, false))
+            .as_ref()
+    }
+
+    fn dtz(&self) -> Option<&Table> {
+        self.dtz
+            .get_or_init(|| Table::open(self.dtz_path.as_ref()?, self, true))
+            .as_ref()
+    }
+
+    /// The codes of the pieces of the material, sorted
+    fn piece_codes(&self) -> Vec<u8> {
+        let (white, black) = self.key.split_once('v').unwrap();
+        [white, black]
+            .iter()
+            .enumerate()
+            .flat_map(|(color, side)| {
+                side.chars()
+                    .map(move |c| "PNBRQK".find(c).unwrap() as u8 + 1 + 8 * color as u8)
+            })
+            .sorted()
+            .collect()
+    }
+}
+
+/// The content of a table file
+struct Table {
+    bytes: Vec<u8>,
+    /// The table parts, by side to move and by file of the leading pawn
+    items: Vec<Vec<PairsData>>,
+    /// The offset of the DTZ values map
+    map: usize,
+}
+
+/// A part of a table, compressed by recursive pairing and Huffman coding
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    /// About every span values there is a sparse index entry
+    span: u64,
+    num_blocks: usize,
+    /// The maximum and minimum length in bits of the Huffman codes
+    max_sym_len: usize,
+    min_sym_len: usize,
+    /// The offset of the lowest symbol of each code length
+    lowest_sym: usize,
+    /// The offset of the pairs of symbols that expand each symbol
+    btree: usize,
+    /// The offset of the number of values minus one of each block
+    block_length: usize,
+    block_length_size: usize,
+    /// The offset of the block and offset in the block of every span values
+    sparse_index: usize,
+    sparse_index_size: usize,
+    /// The offset of the compressed blocks
+    data: usize,
+    /// The lowest code of each length, padded to 64 bits
+    base64: Vec<u64>,
+    /// The number of values minus one of each symbol
+    symlen: Vec<u8>,
+    /// The pieces of the table, their order defines the groups of the position index
+    pieces: [u8; MAX_PIECES],
+    /// The index multiplier of each group
+    group_idx: [u64; MAX_PIECES + 1],
+    /// The number of pieces of each group, zero terminated
+    group_len: [usize; MAX_PIECES + 1],
+    /// The DTZ map offsets of wins, losses, cursed wins and blessed losses
+    map_idx: [usize; 4],
+}
+
+impl Table {
+    fn open(path: &Path, entry: &TableEntry, dtz: bool) -> Option<Self> {
+        Self::new(std::fs::read(path).ok()?, entry, dtz)
+    }
+
+    /// Parse a table file, or `None` if it is invalid or does not match the material of the entry
+    fn new(bytes: Vec<u8>, entry: &TableEntry, dtz: bool) -> Option<Self> {
+        if !bytes.starts_with(if dtz { &DTZ_MAGIC } else { &WDL_MAGIC }) {
+            return None;
+        }
+        let mut pos = 4;
+        let flags = *bytes.get(pos)?;
+        pos += 1;
+        if (flags & HAS_PAWNS != 0) != entry.has_pawns || (flags & SPLIT != 0) != (entry.key != entry.key2) {
+            return None;
+        }
+        let piece_codes = entry.piece_codes();
+
+        /* WDL tables store both sides to move, unless the material is symmetric */
+        let sides = if !dtz && entry.key != entry.key2 { 2 } else { 1 };
+        let files = if entry.has_pawns { 4 } else { 1 };
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut items = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let order1 = *bytes.get(pos)?;
+            let order2 = if both_pawns { *bytes.get(pos + 1)? } else { 0xFF };
+            let orders = [[order1 & 0xF, order2 & 0xF], [order1 >> 4, order2 >> 4]];
+            pos += 1 + both_pawns as usize;
+            for k in 0..entry.piece_count {
+                let piece = *bytes.get(pos)?;
+                for (side, side_items) in items.iter_mut().enumerate() {
+                    side_items[file].pieces[k] = if side == 0 { piece & 0xF } else { piece >> 4 };
+                }
+                pos += 1;
+            }
+            for (side_items, order) in items.iter_mut().zip(orders) {
+                /* The position index relies on the pieces being those of the material */
+                let d = &mut side_items[file];
+                if d.pieces[..entry.piece_count]
+                    .iter()
+                    .copied()
+                    .sorted()
+                    .ne(piece_codes.iter().copied())
+                {
+                    return None;
+                }
+                d.set_groups(entry, order, file);
+            }
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                pos = side_items[file].set_sizes(&bytes, pos)?;
+            }
+        }
+
+        let map = pos;
+        if dtz {
+            for d in items[0].iter_mut() {
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (pos - map) / 2 + 1;
+                        pos += 2 * read_u16(&bytes, pos)? + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = pos - map + 1;
+                        pos += *bytes.get(pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                side_items[file].sparse_index = pos;
+                pos += side_items[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                side_items[file].block_length = pos;
+                pos += side_items[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                pos = pos.next_multiple_of(64);
+                side_items[file].data = pos;
+                pos += side_items[file].num_blocks * side_items[file].block_size;
+            }
+        }
+
+        Some(Self { bytes, items, map })
+    }
+
+    /// The table file and value of a position, the value of a DTZ table given the position WDL, or `None` if the
+    /// DTZ table does not store the side to move of the position or the table is corrupted
+    fn probe(&self, entry: &TableEntry, board: &chess::Board, wdl: Option<SyzygyWdl>) -> Option<(usize, i32)> {
+        let (side, file, idx) = position_index(entry, &self.items, board, wdl.is_some())?;
+        let value = self.items[side][file].decompress_pairs(&self.bytes, idx)?;
+        Some(match wdl {
+            None => (file, value - 2),
+            Some(wdl) => (file, self.map_score(file, value, wdl)?),
+        })
+    }
+
+    /// Map a stored DTZ value to a number of plies
+    fn map_score(&self, file: usize, value: i32, wdl: SyzygyWdl) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        let d = &self.items[0][file];
+        let mut value = value as usize;
+        if d.flags & MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]];
+            value = if d.flags & WIDE != 0 {
+                read_u16(&self.bytes, self.map + 2 * (idx + value))?
+            } else {
+                *self.bytes.get(self.map + idx + value)? as usize
+            };
+        }
+        /* Values may be stored in moves rather than plies */
+        let in_moves = match wdl {
+            SyzygyWdl::Win => d.flags & WIN_PLIES == 0,
+            SyzygyWdl::Loss => d.flags & LOSS_PLIES == 0,
+            _ => true,
+        };
+        if in_moves {
+            value *= 2;
+        }
+        Some(value as i32 + 1)
+    }
+}
+
+/// The side and file of the table part of a position and the index of the position in it, or `None` if the position
+/// side to move is not stored in a DTZ table
+fn position_index(
+    entry: &TableEntry,
+    items: &[Vec<PairsData>],
+    board: &chess::Board,
+    dtz: bool,
+) -> Option<(usize, usize, u64)> {
+    let maps = &*INDEX_MAPS;
+    let black_to_move = board.side_to_move() == chess::Color::Black;
+    /* Symmetric material is stored for white to move only, and tables are stored with the stronger side as white */
+    let symmetric_black_to_move = entry.key == entry.key2 && black_to_move;
+    let black_stronger = material_key(board) != entry.key;
+    let flip = symmetric_black_to_move || black_stronger;
+    let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+    let stm = (flip ^ black_to_move) as usize;
+
+    let mut squares = [0usize; MAX_PIECES];
+    let mut pieces = [0u8; MAX_PIECES];
+    let mut size = 0;
+    let mut lead_pawns = chess::EMPTY;
+    let mut file = 0;
+    if entry.has_pawns {
+        /* The leading pawns are the first pieces, the leading one is the nearest to the edge and lowest rank */
+        let lead_color = if (items[0][0].pieces[0] ^ flip_color) < 8 {
+            chess::Color::White
+        } else {
+            chess::Color::Black
+        };
+        lead_pawns = board.pieces(chess::Piece::Pawn) & board.color_combined(lead_color);
+        for square in lead_pawns {
+            squares[size] = square.to_index() ^ flip_squares;
+            size += 1;
+        }
+        let lead_idx = (0..size).max_by_key(|&i| maps.map_pawns[squares[i]]).unwrap();
+        squares.swap(0, lead_idx);
+        file = (squares[0] % 8).min(7 - squares[0] % 8);
+    }
+    let lead_pawns_cnt = size;
+
+    if dtz {
+        let flags = items[0][file].flags;
+        let stores_both_sides = entry.key == entry.key2 && !entry.has_pawns;
+        if (flags & STM) as usize != stm && !stores_both_sides {
+            return None;
+        }
+    }
+
+    for square in *board.combined() ^ lead_pawns {
+        squares[size] = square.to_index() ^ flip_squares;
+        pieces[size] = piece_code(board, square) ^ flip_color;
+        size += 1;
+    }
+    let side = stm % items.len();
+    let d = &items[side][file];
+
+    /* Order the pieces as in the table */
+    for i in lead_pawns_cnt..size - 1 {
+        if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    /* Mirror the position so that the leading piece is on the files a to d */
+    if squares[0] % 8 > 3 {
+        for square in squares[..size].iter_mut() {
+            *square ^= 7;
+        }
+    }
+
+    let mut idx = if entry.has_pawns {
+        let mut idx = maps.lead_pawn_idx[lead_pawns_cnt][squares[0]];
+        squares[1..lead_pawns_cnt].sort_by_key(|&square| maps.map_pawns[square]);
+        for (i, &square) in squares[..lead_pawns_cnt].iter().enumerate().skip(1) {
+            idx += maps.binomial[i][maps.map_pawns[square]];
+        }
+        idx
+    } else {
+        /* Without pawns, also mirror the position so that the leading piece is on the ranks 1 to 4, and that the
+         * first leading piece not on the a1-h8 diagonal is below it */
+        if squares[0] / 8 > 3 {
+            for square in squares[..size].iter_mut() {
+                *square ^= 56;
+            }
+        }
+        if let Some(i) = (0..d.group_len[0]).find(|&i| off_a1h8(squares[i]) != 0)
+            && off_a1h8(squares[i]) > 0
+        {
+            for square in squares[i..size].iter_mut() {
+                *square = ((*square >> 3) | (*square << 3)) & 63;
+            }
+        }
+
+        if entry.has_unique_pieces {
+            /* The three first pieces are encoded together */
+            let rank = |square: usize| square / 8;
+            let adjust1 = (squares[1] > squares[0]) as usize;
+            let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+            (if off_a1h8(squares[0]) != 0 {
+                (maps.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2] - adjust2
+            } else if off_a1h8(squares[1]) != 0 {
+                (6 * 63 + rank(squares[0]) * 28 + maps.map_b1h1h7[squares[1]]) * 62 + squares[2] - adjust2
+            } else if off_a1h8(squares[2]) != 0 {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank(squares[0]) * 7 * 28
+                    + (rank(squares[1]) - adjust1) * 28
+                    + maps.map_b1h1h7[squares[2]]
+            } else {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank(squares[0]) * 7 * 6
+                    + (rank(squares[1]) - adjust1) * 6
+                    + rank(squares[2])
+                    - adjust2
+            }) as u64
+        } else {
+            /* Only the two kings are encoded together */
+            maps.map_kk[maps.map_a1d1d4[squares[0]]][squares[1]] as u64
+        }
+    };
+    idx *= d.group_idx[0];
+
+    /* Encode the remaining groups, the squares of a group in ascending order */
+    let mut group_start = d.group_len[0];
+    let mut remaining_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    for next in 1.. {
+        let group_len = d.group_len[next];
+        if group_len == 0 {
+            break;
+        }
+        squares[group_start..group_start + group_len].sort();
+        let mut n = 0;
+        for i in 0..group_len {
+            let square = squares[group_start + i];
+            /* Skip the squares of the previous groups */
+            let adjust = squares[..group_start].iter().filter(|&&s| square > s).count();
+            n += maps.binomial[i + 1][square - adjust - 8 * remaining_pawns as usize];
+        }
+        remaining_pawns = false;
+        idx += n * d.group_idx[next];
+        group_start += group_len;
+    }
+
+    Some((side, file, idx))
+}
+
+/// The rank minus the file of a square, zero on the a1-h8 diagonal
+fn off_a1h8(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(bytes.get(pos..pos + 2)?.try_into().ok()?) as usize)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize)
+}
+
+/// Read big endian compressed data, which may be read a bit past the end of the file
+fn read_be(bytes: &[u8], pos: usize, len: usize) -> u64 {
+    (0..len).fold(0, |acc, i| acc << 8 | *bytes.get(pos + i).unwrap_or(&0) as u64)
+}
+
+impl PairsData {
+    fn set_groups(&mut self, entry: &TableEntry, order: [u8; 2], file: usize) {
+        let maps = &*INDEX_MAPS;
+        /* The leading group is the leading pawns, or the two kings and a unique piece, or the two kings */
+        let mut first_len: i32 = if entry.has_pawns {
+            0
+        } else if entry.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        self.group_len[0] = 1;
+        for i in 1..entry.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        /* The groups are encoded in the order of the table, the remaining pawns being the second group */
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                self.group_idx[0] = idx;
+                idx *= if entry.has_pawns {
+                    maps.lead_pawns_size[self.group_len[0]][file]
+                } else if entry.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                self.group_idx[1] = idx;
+                idx *= maps.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= maps.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+    }
+
+    /// Read the sizes of the table part and return the offset following them, or `None` if they are invalid
+    fn set_sizes(&mut self, bytes: &[u8], mut pos: usize) -> Option<usize> {
+        self.flags = *bytes.get(pos)?;
+        pos += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            /* The single value of the table */
+            self.min_sym_len = *bytes.get(pos)? as usize;
+            return Some(pos + 1);
+        }
+
+        let sizes = bytes.get(pos..pos + 9)?;
+        /* Codes are decoded from a buffer holding at least 32 bits */
+        let (block_bits, span_bits, max_sym_len, min_sym_len) = (sizes[0], sizes[1], sizes[7], sizes[8]);
+        if block_bits >= 32 || span_bits >= 64 || min_sym_len == 0 || min_sym_len > max_sym_len || max_sym_len > 32 {
+            return None;
+        }
+        let table_size = self.group_idx[self.group_len.iter().position(|&len| len == 0).unwrap()];
+        self.block_size = 1 << block_bits;
+        self.span = 1 << span_bits;
+        self.sparse_index_size = table_size.div_ceil(self.span) as usize;
+        let padding = sizes[2] as usize;
+        self.num_blocks = read_u32(bytes, pos + 3)?;
+        self.block_length_size = self.num_blocks + padding;
+        self.max_sym_len = max_sym_len as usize;
+        self.min_sym_len = min_sym_len as usize;
+        pos += 9;
+
+        /* Canonical Huffman codes, longer codes have lower values */
+        self.lowest_sym = pos;
+        let lens_num = self.max_sym_len - self.min_sym_len + 1;
+        self.base64 = vec![0; lens_num];
+        for i in (0..lens_num - 1).rev() {
+            let (lowest, next_lowest) = (
+                read_u16(bytes, self.lowest_sym + 2 * i)? as u64,
+                read_u16(bytes, self.lowest_sym + 2 * (i + 1))? as u64,
+            );
+            self.base64[i] = (self.base64[i + 1] + lowest).checked_sub(next_lowest)? / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base <<= 64 - i - self.min_sym_len;
+        }
+        pos += lens_num * 2;
+
+        let sym_num = read_u16(bytes, pos)?;
+        pos += 2;
+        self.btree = pos;
+        self.symlen = vec![0; sym_num];
+        let mut visited = vec![false; sym_num];
+        for sym in 0..sym_num {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(bytes, sym, &mut visited)?;
+            }
+        }
+        Some(pos + sym_num * 3 + (sym_num & 1))
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[sym] = true;
+        let (left, right) = self.pair(bytes, sym)?;
+        if right == 0xFFF {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                self.symlen[child] = self.set_symlen(bytes, child, visited)?;
+            }
+        }
+        self.symlen[left].checked_add(self.symlen[right])?.checked_add(1)
+    }
+
+    /// The two symbols a symbol expands to, a leaf symbol stores its value as the left one
+    fn pair(&self, bytes: &[u8], sym: usize) -> Option<(usize, usize)> {
+        let lr = bytes.get(self.btree + 3 * sym..self.btree + 3 * sym + 3)?;
+        let left = ((lr[1] as usize & 0xF) << 8) | lr[0] as usize;
+        let right = ((lr[2] as usize) << 4) | (lr[1] as usize >> 4);
+        Some((left, right))
+    }
+
+    /// The value at an index of the table part, or `None` if the table is corrupted
+    fn decompress_pairs(&self, bytes: &[u8], idx: u64) -> Option<i32> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as i32);
+        }
+
+        /* Find the block of the value from the nearest sparse index entry, which points to the value at index
+         * k * span + span / 2 */
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32(bytes, entry)?;
+        let mut offset = read_u16(bytes, entry + 4)? as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+        let block_length = |block: usize| Some(read_u16(bytes, self.block_length + 2 * block)? as i64);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        /* Decode the symbols of the block until the one containing the value */
+        let mut pos = self.data + block * self.block_size;
+        let mut buf64 = read_be(bytes, pos, 8);
+        pos += 8;
+        let mut buf64_size = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf64 < *self.base64.get(len)? {
+                len += 1;
+            }
+            sym = ((buf64 - self.base64[len]) >> (64 - len - self.min_sym_len)) as u16;
+            sym = sym.wrapping_add(read_u16(bytes, self.lowest_sym + 2 * len)? as u16);
+            let sym_values = *self.symlen.get(sym as usize)? as i64 + 1;
+            if offset < sym_values {
+                break;
+            }
+            offset -= sym_values;
+            len += self.min_sym_len;
+            buf64 <<= len;
+            buf64_size -= len;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= read_be(bytes, pos, 4) << (64 - buf64_size);
+                pos += 4;
+            }
+        }
+
+        /* Expand the symbol pairs down to the value */
+        let mut sym = sym as usize;
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.pair(bytes, sym)?;
+            let left_values = self.symlen[left] as i64 + 1;
+            let next = if offset < left_values {
+                left
+            } else {
+                offset -= left_values;
+                right
+            };
+            /* The pairs of a corrupted table may form a cycle */
+            if self.symlen[next] >= self.symlen[sym] {
+                return None;
+            }
+            sym = next;
+        }
+        Some(self.pair(bytes, sym)?.0 as i32)
+    }
+}
+
+/// The maps used to index positions
+struct IndexMaps {
+    /// The squares a2 to h7 by decreasing distance to the edge, then increasing rank
+    map_pawns: [usize; 64],
+    /// The squares below the a1-h8 diagonal
+    map_b1h1h7: [usize; 64],
+    /// The squares of the a1-d1-d4 triangle, the diagonal last
+    map_a1d1d4: [usize; 64],
+    /// The 462 legal positions of two kings, the first one in the a1-d1-d4 triangle
+    map_kk: [[usize; 64]; 10],
+    /// The ways to choose k elements out of n
+    binomial: [[u64; 64]; 6],
+    /// The first index of the leading pawns by their count and the leading pawn square
+    lead_pawn_idx: [[u64; 64]; 6],
+    /// The number of leading pawns positions by their count and the leading pawn file
+    lead_pawns_size: [[u64; 4]; 6],
+}
+static INDEX_MAPS: LazyLock<IndexMaps> = LazyLock::new(IndexMaps::new);
+
+impl IndexMaps {
+    fn new() -> Self {
+        let mut maps = Self {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        for (code, square) in (0..64).filter(|&square| off_a1h8(square) < 0).enumerate() {
+            maps.map_b1h1h7[square] = code;
+        }
+
+        let triangle = (0..64).filter(|&square| square % 8 <= 3 && off_a1h8(square) <= 0);
+        let (below, diagonal): (Vec<usize>, Vec<usize>) = triangle.partition(|&square| off_a1h8(square) < 0);
+        for (code, square) in below.into_iter().chain(diagonal).enumerate() {
+            maps.map_a1d1d4[square] = code;
+        }
+
+        /* If the first king is on the diagonal, the second one is not above it, and both on it are encoded last */
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            let s1 = (0..64)
+                .find(|&square| maps.map_a1d1d4[square] == idx && (idx > 0 || square == 1))
+                .unwrap();
+            let king_area = chess::get_king_moves(chess::ALL_SQUARES[s1]).0 | 1 << s1;
+            for s2 in 0..64 {
+                if king_area >> s2 & 1 != 0 {
+                    continue;
+                }
+                if off_a1h8(s1) == 0 && off_a1h8(s2) > 0 {
+                    continue;
+                }
+                if off_a1h8(s1) == 0 && off_a1h8(s2) == 0 {
+                    both_on_diagonal.push((idx, s2));
+                } else {
+                    maps.map_kk[idx][s2] = code;
+                    code += 1;
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            maps.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        maps.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                maps.binomial[k][n] = if k > 0 { maps.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { maps.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available_squares = 48;
+        for lead_pawns_cnt in 1..6 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns_cnt == 1 {
+                        maps.map_pawns[square] = available_squares - 1;
+                        maps.map_pawns[square ^ 7] = available_squares - 2;
+                        available_squares -= 2;
+                    }
+                    maps.lead_pawn_idx[lead_pawns_cnt][square] = idx;
+                    idx += maps.binomial[lead_pawns_cnt - 1][maps.map_pawns[square]];
+                }
+                maps.lead_pawns_size[lead_pawns_cnt][file] = idx;
+            }
+        }
+        maps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::prelude::*;
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use crate::chess::syzygy::{position_index, PairsData, Syzygy, SyzygyWdl, Table, TableEntry, INDEX_MAPS};
+    use crate::chess::ChessPosition;
+    use crate::game::{GameColor, GameStatus, Position};
+    use crate::mcts::tablebase::Tablebase;
+
+    #[test]
+    fn index_maps() {
+        let maps = &*INDEX_MAPS;
+        let kk_codes = (0..10)
+            .cartesian_product(0..64)
+            .filter(|&(idx, s2)| maps.map_kk[idx][s2] != 0 || (idx, s2) == (0, 0))
+            .map(|(idx, s2)| maps.map_kk[idx][s2])
+            .collect::<HashSet<_>>();
+        assert_eq!(kk_codes, (0..462).collect());
+        let pawn_codes = (8..56).map(|square| maps.map_pawns[square]).collect::<HashSet<_>>();
+        assert_eq!(pawn_codes, (0..48).collect());
+        assert!((0..4).all(|file| maps.lead_pawns_size[1][file] == 6));
+        assert_eq!(maps.binomial[3][10], 120);
+    }
+
+    /// Write a table file of three pieces without pawns, with fixed length codes and the value of each position of
+    /// the table parts given by side to move, or `None` for an unspecified value, and return the values of the table
+    /// parts
+    fn write_table(
+        path: &Path,
+        key: &str,
+        dtz_stm: Option<u8>,
+        value: impl Fn(&chess::Board) -> Option<u8>,
+    ) -> Vec<Vec<u8>> {
+        let entry = TableEntry::new(key, path.to_path_buf(), None);
+        assert_eq!(entry.piece_count, 3);
+        assert!(!entry.has_pawns);
+        let sides = if dtz_stm.is_none() { 2 } else { 1 };
+        let pieces = key
+            .split('v')
+            .enumerate()
+            .flat_map(|(color, side)| {
+                side.chars()
+                    .map(move |c| "PNBRQK".find(c).unwrap() as u8 + 1 + 8 * color as u8)
+            })
+            .collect_vec();
+        let mut items = vec![vec![PairsData::default()]; sides];
+        for side_items in items.iter_mut() {
+            side_items[0].pieces[..3].copy_from_slice(&pieces);
+            side_items[0].flags = dtz_stm.unwrap_or(0);
+            side_items[0].set_groups(&entry, [0, 0xF], 0);
+        }
+        let table_size = items[0][0].group_idx[1] as usize;
+
+        /* Enumerate the positions with the first piece in the a1-d1-d4 triangle, which cover all indices */
+        let mut values = vec![vec![0u8; table_size]; sides];
+        let triangle = (0..64).filter(|&square| square % 8 <= 3 && square / 8 <= square % 8);
+        for ((s0, s1), s2) in triangle.cartesian_product(0..64).cartesian_product(0..64) {
+            for stm in [chess::Color::White, chess::Color::Black] {
+                if s0 == s1 || s0 == s2 || s1 == s2 {
+                    continue;
+                }
+                let setup = [s0, s1, s2].into_iter().zip(&pieces).map(|(square, &piece)| {
+                    let color = if piece < 8 {
+                        chess::Color::White
+                    } else {
+                        chess::Color::Black
+                    };
+                    (
+                        chess::ALL_SQUARES[square],
+                        chess::ALL_PIECES[(piece as usize & 7) - 1],
+                        color,
+                    )
+                });
+                let no_castling = chess::CastleRights::NoRights;
+                let setup = setup.collect_vec();
+                let Ok(board) = chess::Board::try_from(chess::BoardBuilder::setup(
+                    setup.iter(),
+                    stm,
+                    no_castling,
+                    no_castling,
+                    None,
+                )) else {
+                    continue;
+                };
+                let (side, _file, idx) = position_index(&entry, &items, &board, false).unwrap();
+                if dtz_stm.is_some_and(|dtz_stm| dtz_stm as usize != stm.to_index()) {
+                    continue;
+                }
+                let Some(value) = value(&board) else {
+                    continue;
+                };
+                let stored = &mut values[side][idx as usize];
+                assert!(
+                    *stored == 0 || *stored == value,
+                    "index of equivalent positions with different values"
+                );
+                *stored = value;
+            }
+        }
+
+        /* Each symbol is a single value of `sym_len` bits, in blocks of 64 bytes and a sparse index entry every 64
+         * values */
+        let sym_num = *values.iter().flatten().max().unwrap() as usize + 1;
+        let sym_len = (usize::BITS - (sym_num - 1).max(1).leading_zeros()) as usize;
+        let (block_size, span) = (64, 64);
+        let block_values = block_size * 8 / sym_len;
+        let num_blocks = table_size.div_ceil(block_values);
+
+        let mut bytes = if dtz_stm.is_some() {
+            vec![0xD7, 0x66, 0x0C, 0xA5]
+        } else {
+            vec![0x71, 0xE8, 0x23, 0x5D]
+        };
+        bytes.push(1);
+        bytes.push(0);
+        bytes.extend(pieces.iter().map(|piece| piece << 4 | piece));
+        bytes.resize(bytes.len().next_multiple_of(2), 0);
+        for _ in 0..sides {
+            bytes.extend([dtz_stm.unwrap_or(0), 6, 6, 0]);
+            bytes.extend((num_blocks as u32).to_le_bytes());
+            bytes.extend([sym_len as u8, sym_len as u8, 0, 0]);
+            bytes.extend((sym_num as u16).to_le_bytes());
+            for sym in 0..sym_num {
+                bytes.extend([sym as u8, 0xF0, 0xFF]);
+            }
+            bytes.resize(bytes.len().next_multiple_of(2), 0);
+        }
+        for _ in 0..sides {
+            for k in 0..table_size.div_ceil(span) {
+                let idx = k * span + span / 2;
+                let block = (idx / block_values).min(num_blocks - 1);
+                bytes.extend((block as u32).to_le_bytes());
+                bytes.extend(((idx - block * block_values) as u16).to_le_bytes());
+            }
+        }
+        for _ in 0..sides {
+            for block in 0..num_blocks {
+                let len = block_values.min(table_size - block * block_values);
+                bytes.extend(((len - 1) as u16).to_le_bytes());
+            }
+        }
+        for side_values in values.iter() {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            for block in side_values.chunks(block_values) {
+                let mut block_bytes = vec![0u8; block_size];
+                for (i, &value) in block.iter().enumerate() {
+                    for bit in 0..sym_len {
+                        if value >> (sym_len - 1 - bit) & 1 != 0 {
+                            let pos = i * sym_len + bit;
+                            block_bytes[pos / 8] |= 0x80 >> (pos % 8);
+                        }
+                    }
+                }
+                bytes.extend(block_bytes);
+            }
+        }
+        std::fs::write(path, bytes).unwrap();
+        values
+    }
+
+    fn is_stalemate(board: &chess::Board) -> bool {
+        board.status() == chess::BoardStatus::Stalemate
+    }
+
+    fn chebyshev_distance(s1: chess::Square, s2: chess::Square) -> u8 {
+        let (f1, r1) = (s1.get_file().to_index(), s1.get_rank().to_index());
+        let (f2, r2) = (s2.get_file().to_index(), s2.get_rank().to_index());
+        f1.abs_diff(f2).max(r1.abs_diff(r2)) as u8
+    }
+
+    #[test]
+    fn generated_tables() {
+        /* Real table files are large, generate a KQvK WDL table, in which the queen may be captured, and a DTZ table
+         * of made up distances */
+        let dir = std::env::temp_dir().join(format!("cattus-syzygy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wdl_values = write_table(&dir.join("KQvK.rtbw"), "KQvK", None, |board| {
+            Some(if board.side_to_move() == chess::Color::White {
+                4
+            } else if is_stalemate(board) {
+                2
+            } else {
+                0
+            })
+        });
+        let dtz_values = write_table(&dir.join("KQvK.rtbz"), "KQvK", Some(1), |board| {
+            let black_king = board.king_square(chess::Color::Black);
+            let queen = board.pieces(chess::Piece::Queen).to_square();
+            match board.status() {
+                chess::BoardStatus::Checkmate => Some(0),
+                chess::BoardStatus::Stalemate => None,
+                chess::BoardStatus::Ongoing => Some(chebyshev_distance(black_king, queen)),
+            }
+        });
+        let syzygy = Syzygy::new(&dir).unwrap();
+        assert_eq!(syzygy.max_pieces(), 3);
+
+        /* The decoded values of all positions */
+        let entry = &syzygy.tables["KQvK"];
+        for (table, values) in [(entry.wdl().unwrap(), wdl_values), (entry.dtz().unwrap(), dtz_values)] {
+            for (d, side_values) in table.items.iter().map(|side_items| &side_items[0]).zip(values) {
+                for (idx, value) in side_values.into_iter().enumerate() {
+                    assert_eq!(d.decompress_pairs(&table.bytes, idx as u64), Some(value as i32));
+                }
+            }
+        }
+
+        for (fen, wdl, winner) in [
+            (
+                "8/8/8/3k4/8/8/8/KQ6 w - - 0 1",
+                SyzygyWdl::Win,
+                Some(GameColor::Player1),
+            ),
+            (
+                "k7/8/1K6/8/8/8/8/6Q1 b - - 0 1",
+                SyzygyWdl::Loss,
+                Some(GameColor::Player1),
+            ),
+            /* The queen is captured */
+            ("8/8/8/3k4/2Q5/8/8/K7 b - - 0 1", SyzygyWdl::Draw, None),
+            /* A mate in one within the fifty-move rule */
+            (
+                "k7/8/1K6/8/8/8/8/6Q1 w - - 99 80",
+                SyzygyWdl::Win,
+                Some(GameColor::Player1),
+            ),
+            /* A win beyond the fifty-move rule */
+            ("8/8/8/3k4/8/8/8/KQ6 w - - 99 80", SyzygyWdl::Win, None),
+        ] {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            assert_eq!(syzygy.probe_wdl(&pos), Some(wdl));
+            assert_eq!(syzygy.probe(&pos), Some(winner));
+            let flipped = pos.flipped();
+            assert_eq!(syzygy.probe_wdl(&flipped), Some(wdl));
+            assert_eq!(syzygy.probe(&flipped), Some(winner.map(|winner| winner.opposite())));
+        }
+        assert_eq!(syzygy.probe(&ChessPosition::new()), None);
+        assert_eq!(
+            syzygy.probe_dtz(&ChessPosition::from_fen("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1").unwrap()),
+            Some(1)
+        );
+
+        /* The best moves of a mate in one are the mates, otherwise moves that keep the win */
+        let pos = ChessPosition::from_fen("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        let mates = pos
+            .legal_moves()
+            .filter(|&m| pos.moved_position(m).status() == GameStatus::Finished(Some(GameColor::Player1)))
+            .collect_vec();
+        assert!(!mates.is_empty());
+        assert_eq!(syzygy.best_moves(&pos), Some(mates));
+        let pos = ChessPosition::from_fen("8/8/8/3k4/8/8/8/KQ6 w - - 0 1").unwrap();
+        let best_moves = syzygy.best_moves(&pos).unwrap();
+        assert!(!best_moves.is_empty());
+        for m in best_moves {
+            assert_eq!(syzygy.probe(&pos.moved_position(m)), Some(Some(GameColor::Player1)));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_tables() {
+        let dir = std::env::temp_dir().join(format!("cattus-syzygy-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("KQvK.rtbw");
+        write_table(&path, "KQvK", None, |board| {
+            Some(if board.side_to_move() == chess::Color::White {
+                4
+            } else {
+                0
+            })
+        });
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let entry = TableEntry::new("KQvK", path.clone(), None);
+        assert!(Table::new(bytes.clone(), &entry, false).is_some());
+        /* Mismatching flags or pieces */
+        let mut pawns_flag = bytes.clone();
+        pawns_flag[4] |= 2;
+        assert!(Table::new(pawns_flag, &entry, false).is_none());
+        assert!(Table::new(bytes.clone(), &TableEntry::new("KRvK", path.clone(), None), false).is_none());
+        assert!(Table::new(bytes.clone(), &TableEntry::new("KPvK", path, None), false).is_none());
+
+        /* Truncated or corrupted tables are rejected, or their probes fail or return some value, without panicking */
+        let boards = ["8/8/8/3k4/8/8/8/KQ6 w - - 0 1", "k7/8/1K6/8/8/8/8/6Q1 b - - 0 1"]
+            .map(|fen| ChessPosition::from_fen(fen).unwrap().board);
+        let mut rng = StdRng::seed_from_u64(0);
+        let truncated = (0..bytes.len()).map(|len| bytes[..len].to_vec());
+        let corrupted = (0..200).map(|_| {
+            let mut bytes = bytes.clone();
+            for _ in 0..4 {
+                let idx = rng.random_range(4..bytes.len());
+                bytes[idx] = rng.random();
+            }
+            bytes
+        });
+        for bytes in truncated.chain(corrupted) {
+            if let Some(table) = Table::new(bytes, &entry, false) {
+                for board in boards.iter() {
+                    let _ = table.probe(&entry, board, None);
+                }
+            }
+        }
+    }
+
+    /// Known values of the real KQvK, KRvK and KPvK WDL and DTZ tables of the data/syzygy directory
+    #[test]
+    fn real_tables() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/syzygy");
+        if !dir.join("KPvK.rtbz").exists() {
+            eprintln!("skipping real_tables, the table files are missing in {}", dir.display());
+            return;
+        }
+        let syzygy = Syzygy::new(&dir).unwrap();
+        for key in ["KQvK", "KRvK", "KPvK"] {
+            assert!(syzygy.tables[key].wdl().is_some() && syzygy.tables[key].dtz().is_some());
+        }
+
+        for (fen, wdl, dtz) in [
+            ("8/8/8/3k4/8/8/8/KQ6 w - - 0 1", SyzygyWdl::Win, None),
+            /* The queen is captured */
+            ("8/8/8/3k4/2Q5/8/8/K7 b - - 0 1", SyzygyWdl::Draw, Some(0)),
+            /* Mates in one */
+            ("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1", SyzygyWdl::Win, Some(1)),
+            ("7k/8/6K1/8/8/8/8/R7 w - - 0 1", SyzygyWdl::Win, Some(1)),
+            ("8/8/3k4/8/8/8/8/KR6 b - - 0 1", SyzygyWdl::Loss, None),
+            /* The king in front of the pawn on the sixth rank wins with either side to move */
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", SyzygyWdl::Win, None),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", SyzygyWdl::Loss, None),
+            /* A stalemate */
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", SyzygyWdl::Draw, Some(0)),
+        ] {
+            let pos = ChessPosition::from_fen(fen).unwrap();
+            assert_eq!(syzygy.probe_wdl(&pos), Some(wdl), "{fen}");
+            assert_eq!(syzygy.probe_wdl(&pos.flipped()), Some(wdl), "{fen}");
+            let probed_dtz = syzygy.probe_dtz(&pos).unwrap();
+            assert_eq!(probed_dtz.signum(), wdl.signum(), "{fen}");
+            if let Some(dtz) = dtz {
+                assert_eq!(probed_dtz, dtz, "{fen}");
+            }
+        }
+    }
+}
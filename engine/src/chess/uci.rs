@@ -1,10 +1,15 @@
+use crate::chess::book::{BookConfig, BookWeighting, PolyglotBook};
+use crate::chess::perft;
+use crate::chess::syzygy::Syzygy;
 use crate::chess::{ChessGame, ChessMove, ChessPosition};
 use crate::game::player::GamePlayer;
-use crate::game::Position;
+use crate::game::{GameColor, Position};
 use crate::mcts::{MctsParams, MctsPlayer};
 use itertools::Itertools;
 use std::collections::HashMap;
 use std::io;
+use std::path::Path;
+use std::sync::Arc;
 
 pub struct UCI {
     player_params: MctsParams<ChessGame>,
@@ -12,6 +17,10 @@ pub struct UCI {
     player: Option<MctsPlayer<ChessGame>>,
     pos_history: Option<Vec<ChessPosition>>,
     best_move: Option<ChessMove>,
+    /// The contempt set by the GUI, as a fraction of a win
+    contempt: f32,
+    /// The opening book and the file it was loaded from
+    book: Option<(String, PolyglotBook)>,
 }
 
 impl UCI {
@@ -22,6 +31,8 @@ impl UCI {
             player: None,
             pos_history: None,
             best_move: None,
+            contempt: 0.0,
+            book: None,
         }
     }
 
@@ -42,7 +53,15 @@ impl UCI {
                 "uci" => {
                     self.send_response("id name _PROJECT_NAME_TODO_ v1.0.0");
                     self.send_response("id author Barak Ugav Yishai Gronich");
-                    /* TODO send options */
+                    self.send_response("option name UCI_ShowWDL type check default false");
+                    self.send_response("option name Contempt type spin default 0 min -100 max 100");
+                    self.send_response("option name UCI_Chess960 type check default false");
+                    self.send_response("option name BookFile type string default <empty>");
+                    self.send_response("option name BookDepth type spin default 20 min 0 max 1000");
+                    self.send_response(
+                        "option name BookWeighting type combo default weighted var best var weighted var uniform",
+                    );
+                    self.send_response("option name SyzygyPath type string default <empty>");
                     self.send_response("uciok");
                 }
                 "isready" => self.send_response("readyok"),
@@ -51,6 +70,11 @@ impl UCI {
                     let name = args.value("name").expect("setoption requires 'name' arg");
                     let value = args.value("value").expect("setoption requires 'value' arg");
                     self.options.insert(name.to_string(), value.to_string());
+                    if name.eq_ignore_ascii_case("SyzygyPath") {
+                        self.set_syzygy_path(value);
+                    } else if name.eq_ignore_ascii_case("Contempt") {
+                        self.set_contempt(value);
+                    }
                 }
                 "ucinewgame" => self.player = Some(MctsPlayer::new(self.player_params.clone())),
                 "position" => self.cmd_position(&args),
@@ -71,12 +95,29 @@ impl UCI {
 
     pub fn cmd_position(&mut self, args: &[&str]) {
         let args = Self::parse_args(args, &["fen", "startpos", "moves"]);
-        let fen = args.value("fen");
+        /* A FEN spans multiple whitespace separated args */
+        let fen = args.values("fen").map(|values| values.join(" "));
         let startpos = args.flag("startpos");
         let moves = args.values_iter("moves");
 
         assert_ne!(fen.is_some(), startpos, "position cmd requires either fen or startpos");
-        let mut pos = fen.map(ChessPosition::from_fen).unwrap_or_else(ChessPosition::new);
+        /* In Chess960 mode castling moves are sent as the king capturing its own rook, also in the standard setup */
+        let chess960 = self.option("UCI_Chess960").is_some_and(|s| s == "true");
+        let mut pos = match fen {
+            Some(fen) => match if chess960 {
+                ChessPosition::from_fen_chess960(&fen)
+            } else {
+                ChessPosition::from_fen(&fen)
+            } {
+                Ok(pos) => pos,
+                Err(err) => {
+                    eprintln!("invalid position: {err}");
+                    return;
+                }
+            },
+            None if chess960 => ChessPosition::new_chess960(518),
+            None => ChessPosition::new(),
+        };
         let mut pos_history = vec![pos];
         for move_str in moves {
             let m = ChessMove::from_lan(move_str).unwrap();
@@ -102,9 +143,18 @@ impl UCI {
                 "mate",
                 "movetime",
                 "infinite",
+                "perft",
             ],
         );
 
+        if let Some(depth) = args.value("perft") {
+            match depth.parse::<u32>() {
+                Ok(depth) => self.cmd_perft(depth),
+                Err(err) => eprintln!("invalid perft depth '{depth}': {err}"),
+            }
+            return;
+        }
+
         #[allow(unused)]
         struct GoParams {
             searchmoves: Vec<String>,
@@ -134,11 +184,124 @@ impl UCI {
             infinite: args.flag("infinite"),
         };
 
+        let show_wdl = self.option("UCI_ShowWDL").is_some_and(|s| s == "true");
+
+        if let Some(m) = self.book_move() {
+            self.best_move = Some(m);
+            self.send_response(format!("bestmove {m}"));
+            return;
+        }
+
+        let pos_history = self.pos_history.as_ref().unwrap();
         let player = self.player.as_mut().unwrap();
-        self.best_move = Some(player.next_move(self.pos_history.as_ref().unwrap()).unwrap());
+        player.set_contempt(self.contempt);
+        self.best_move = Some(player.next_move(pos_history).unwrap());
+
+        if let Some(wdl) = player.root_wdl() {
+            /* Scores are reported from the perspective of the side to move */
+            let wdl = match pos_history.last().unwrap().turn() {
+                GameColor::Player1 => wdl,
+                GameColor::Player2 => wdl.flipped(),
+            };
+            /* Same conversion from expected score to centipawns as Lc0 */
+            let cp = (90.0 * (1.563_754_2 * wdl.expected_score()).tan()).round() as i32;
+            let mut info = format!("info score cp {cp}");
+            if show_wdl {
+                let win = (wdl.win * 1000.0).round() as i32;
+                let loss = (wdl.loss * 1000.0).round() as i32;
+                let draw = 1000 - win - loss;
+                info += &format!(" wdl {win} {draw} {loss}");
+            }
+            self.send_response(info);
+        }
         self.send_response(format!("bestmove {}", self.best_move.unwrap()));
     }
 
+    /// Print the perft count of each legal move of the current position and their total
+    fn cmd_perft(&self, depth: u32) {
+        let pos = self.pos_history.as_ref().unwrap().last().unwrap();
+        let divide = perft::perft_divide(pos, depth);
+        for (m, count) in divide.iter() {
+            self.send_response(format!("{m}: {count}"));
+        }
+        self.send_response("");
+        /* The position itself is the single leaf at depth 0 */
+        let total: u64 = if depth == 0 {
+            1
+        } else {
+            divide.iter().map(|(_m, count)| count).sum()
+        };
+        self.send_response(format!("Nodes searched: {total}"));
+    }
+
+    /// Choose a move from the opening book set by the GUI, if the position is in book
+    fn book_move(&mut self) -> Option<ChessMove> {
+        let path = self
+            .option("BookFile")
+            .filter(|path| !path.is_empty() && *path != "<empty>")?
+            .to_string();
+        let max_depth = self.option("BookDepth").map_or(BookConfig::DEFAULT_MAX_DEPTH, |s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                eprintln!("invalid book depth '{s}'");
+                BookConfig::DEFAULT_MAX_DEPTH
+            })
+        });
+        let weighting = match self.option("BookWeighting") {
+            Some("best") => BookWeighting::Best,
+            Some("uniform") => BookWeighting::Uniform,
+            _ => BookWeighting::Weighted,
+        };
+
+        if self.book.as_ref().is_none_or(|(book_path, _book)| *book_path != path) {
+            self.book = match PolyglotBook::open(Path::new(&path)) {
+                Ok(book) => Some((path, book)),
+                Err(err) => {
+                    eprintln!("failed to open book '{path}': {err}");
+                    None
+                }
+            };
+        }
+        let (_path, book) = self.book.as_ref()?;
+        let pos = self.pos_history.as_ref().unwrap().last().unwrap();
+        book.book_move(pos, max_depth, weighting, &mut rand::rng())
+    }
+
+    /// Set the contempt, given in percents of a win. An invalid value means no contempt, and out of range values are
+    /// clamped
+    fn set_contempt(&mut self, value: &str) {
+        let contempt = value.parse::<i32>().unwrap_or_else(|_| {
+            eprintln!("invalid contempt '{value}'");
+            0
+        });
+        self.contempt = contempt.clamp(-100, 100) as f32 / 100.0;
+    }
+
+    /// Use the tablebases of a directory in the search, or none if the path is empty
+    fn set_syzygy_path(&mut self, path: &str) {
+        self.player_params.tablebase = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            match Syzygy::new(Path::new(path)) {
+                Ok(syzygy) => Some(Arc::new(syzygy)),
+                Err(err) => {
+                    eprintln!("failed to open tablebases '{path}': {err}");
+                    None
+                }
+            }
+        };
+        if self.player.is_some() {
+            self.player = Some(MctsPlayer::new(self.player_params.clone()));
+        }
+    }
+
+    /// Get the value of an option set by the GUI, option names are case insensitive
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(key, _value)| key.eq_ignore_ascii_case(name))
+            .map(|(_key, value)| value.as_str())
+    }
+
     fn send_response<S: Into<String>>(&self, s: S) {
         let s = s.into();
         log(format!("[To GUI] {}", s));
@@ -97,11 +97,47 @@ impl GameStatus {
     }
 }
 
-pub trait Bitboard: Clone {
+pub trait Bitboard: Clone + Send + Sync {
     type Game: Game<Bitboard = Self>;
 
     fn new() -> Self;
     fn full(value: bool) -> Self;
     fn get(&self, idx: usize) -> bool;
     fn set(&mut self, idx: usize, val: bool);
+
+    /// Write the bitboard as a plane of 0.0 and 1.0 values, `out[idx]` is the value of square `idx`
+    ///
+    /// `out` must be of size `BOARD_SIZE * BOARD_SIZE`. Implementations should unpack their raw words directly rather
+    /// than calling `get` per square, as this is on the hot path of the network input encoding.
+    fn write_plane(&self, out: &mut [f32]) {
+        let board_size = <Self::Game as Game>::BOARD_SIZE;
+        assert_eq!(out.len(), board_size * board_size);
+        for (idx, o) in out.iter_mut().enumerate() {
+            *o = if self.get(idx) { 1.0 } else { 0.0 };
+        }
+    }
 }
+
+/// Unpack the low `out.len()` bits of a word into 0.0 and 1.0 values
+pub fn unpack_bits(word: u64, out: &mut [f32]) {
+    assert!(out.len() <= u64::BITS as usize);
+    for (i, out) in out.chunks_mut(8).enumerate() {
+        let byte = (word >> (i * 8)) as u8;
+        out.copy_from_slice(&BYTE_TO_BITS[byte as usize][..out.len()]);
+    }
+}
+
+/* The bits of each byte value as 0.0 and 1.0 values, from the least significant bit */
+static BYTE_TO_BITS: [[f32; 8]; 256] = {
+    let mut table = [[0.0; 8]; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            table[byte][bit] = ((byte >> bit) & 1) as f32;
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+};
@@ -1,6 +1,6 @@
 use std::fmt::{self, Display};
 
-use crate::game::{Bitboard, Game, GameColor, GameStatus, Move, Position};
+use crate::game::{unpack_bits, Bitboard, Game, GameColor, GameStatus, Move, Position};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct HexMove<const BOARD_SIZE: usize> {
@@ -107,6 +107,13 @@ impl<const BOARD_SIZE: usize> Bitboard for HexBitboard<BOARD_SIZE> {
             self.bitmap &= !(1u128 << idx);
         }
     }
+
+    fn write_plane(&self, out: &mut [f32]) {
+        assert_eq!(out.len(), BOARD_SIZE * BOARD_SIZE);
+        let (low, high) = out.split_at_mut(out.len().min(u64::BITS as usize));
+        unpack_bits(self.bitmap as u64, low);
+        unpack_bits((self.bitmap >> u64::BITS) as u64, high);
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -547,6 +554,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn write_plane() {
+        fn write_plane_impl<const BOARD_SIZE: usize>(rand: &mut StdRng) {
+            for _ in 0..100 {
+                let mut bitboard = HexBitboard::<BOARD_SIZE>::new();
+                for idx in 0..BOARD_SIZE * BOARD_SIZE {
+                    bitboard.set(idx, rand.random());
+                }
+                let mut plane = vec![-1.0; BOARD_SIZE * BOARD_SIZE];
+                bitboard.write_plane(&mut plane);
+                for (idx, val) in plane.into_iter().enumerate() {
+                    assert_eq!(val, if bitboard.get(idx) { 1.0 } else { 0.0 });
+                }
+            }
+        }
+
+        let seed: u64 = rand::rng().random();
+        println!("[{}] Using seed {}", stringify!(write_plane), seed);
+        let mut rand = StdRng::seed_from_u64(seed);
+        write_plane_impl::<4>(&mut rand);
+        write_plane_impl::<8>(&mut rand);
+        write_plane_impl::<11>(&mut rand);
+    }
+
     pub fn hex_position_from_str<const BOARD_SIZE: usize>(s: &str) -> HexPosition<BOARD_SIZE> {
         assert_eq!(
             s.chars().count(),
@@ -1,24 +1,42 @@
+use std::sync::Arc;
+
 use crate::game::Bitboard;
-use crate::hex::{HexBitboard, HexGame, HexMove, HexPosition};
-use crate::mcts::value_func::ValueFunction;
-use crate::net::NNetwork;
+use crate::hex::{HexBitboard, HexGame, HexPosition};
+use crate::net::encoder::{EncodedGame, Encoder};
 
-impl<const BOARD_SIZE: usize> ValueFunction<HexGame<BOARD_SIZE>> for NNetwork<HexGame<BOARD_SIZE>> {
-    fn evaluate(&self, position: &HexPosition<BOARD_SIZE>) -> (Vec<(HexMove<BOARD_SIZE>, f32)>, f32) {
-        self.evaluate(position, position_to_planes)
+impl<const BOARD_SIZE: usize> EncodedGame for HexGame<BOARD_SIZE> {
+    fn encoders() -> Vec<Arc<dyn Encoder<Self>>> {
+        vec![Arc::new(HexBaseEncoder)]
     }
 }
 
-pub const PLANES_NUM: usize = 3;
+/// Pieces of both players and a plane of ones
+pub struct HexBaseEncoder;
+impl HexBaseEncoder {
+    pub const PLANES_NUM: usize = 3;
+}
+impl<const BOARD_SIZE: usize> Encoder<HexGame<BOARD_SIZE>> for HexBaseEncoder {
+    fn name(&self) -> &'static str {
+        "base"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn planes_num(&self) -> usize {
+        Self::PLANES_NUM
+    }
 
-pub fn position_to_planes<const BOARD_SIZE: usize>(pos: &HexPosition<BOARD_SIZE>) -> Vec<HexBitboard<BOARD_SIZE>> {
-    let planes: [_; PLANES_NUM] = [
-        /* red pieces plane */
-        pos.pieces_red(),
-        /* blue pieces plane */
-        pos.pieces_blue(),
-        /* a plane with all ones to help NN find board edges */
-        HexBitboard::full(true),
-    ];
-    planes.to_vec()
+    fn encode(&self, history: &[HexPosition<BOARD_SIZE>], planes: &mut [HexBitboard<BOARD_SIZE>]) {
+        let pos = history.last().unwrap();
+        planes.clone_from_slice(&[
+            /* red pieces plane */
+            pos.pieces_red(),
+            /* blue pieces plane */
+            pos.pieces_blue(),
+            /* a plane with all ones to help NN find board edges */
+            HexBitboard::full(true),
+        ]);
+    }
 }
@@ -1,15 +1,22 @@
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::RwLock;
 
+use crate::mcts::value_func::Evaluation;
+
+/// A positions history and an extra key of state the positions equality ignores, see [`ValueFuncCache::get_or_compute`]
+type CacheKey<Game> = (Vec<<Game as crate::game::Game>::Position>, u64);
+
 struct PositionCache<Game: crate::game::Game> {
-    #[allow(clippy::type_complexity)]
-    map: HashMap<Game::Position, (Vec<(Game::Move, f32)>, f32)>,
-    deque: VecDeque<Game::Position>,
+    map: HashMap<CacheKey<Game>, Evaluation<Game::Move>>,
+    deque: VecDeque<CacheKey<Game>>,
 }
 
 pub struct ValueFuncCache<Game: crate::game::Game> {
     lock: RwLock<PositionCache<Game>>,
     max_size: usize,
+    /// Incremented on every `clear`, values computed during an older generation are not inserted
+    generation: AtomicU64,
     hits: metrics::Counter,
     misses: metrics::Counter,
 }
@@ -23,33 +30,50 @@ impl<Game: crate::game::Game> ValueFuncCache<Game> {
                 deque: VecDeque::new(),
             }),
             max_size,
+            generation: AtomicU64::new(0),
             hits: metrics::counter!("cache.hits"),
             misses: metrics::counter!("cache.misses"),
         }
     }
 
+    /// Get the cached value of a positions history, or compute and cache it
+    ///
+    /// The history is used as the key as is, the caller should pass only the positions the value depends on. The
+    /// extra key distinguishes histories that are equal but evaluated differently, for example by a move counter
+    /// that the positions equality ignores. A value that could not be computed, None, is not cached.
     pub fn get_or_compute(
         &self,
-        position: &Game::Position,
-        mut compute: impl FnMut(&Game::Position) -> (Vec<(Game::Move, f32)>, f32),
-    ) -> (Vec<(Game::Move, f32)>, f32) {
-        // Acquire the read lock and check if the position is in the cache
+        history: Vec<Game::Position>,
+        extra_key: u64,
+        mut compute: impl FnMut(&[Game::Position]) -> Option<Evaluation<Game::Move>>,
+    ) -> Option<Evaluation<Game::Move>> {
+        let key = (history, extra_key);
+        // Acquire the read lock and check if the history is in the cache
         {
             let cache = self.lock.read().unwrap();
-            if let Some(cached_val) = cache.map.get(position) {
+            if let Some(cached_val) = cache.map.get(&key) {
                 self.hits.increment(1);
-                return cached_val.clone();
+                return Some(cached_val.clone());
             }
         }
 
         // Compute without holding any lock
-        let computed_val = compute(position);
+        let generation = self.generation.load(Ordering::SeqCst);
+        let Some(computed_val) = compute(&key.0) else {
+            self.misses.increment(1);
+            return None;
+        };
 
         // Acquire the write lock, and update the cache
         {
             let mut cache = self.lock.write().unwrap();
+            // The cache was cleared while computing, the value may be computed by an outdated value function
+            if self.generation.load(Ordering::SeqCst) != generation {
+                self.misses.increment(1);
+                return Some(computed_val);
+            }
             // Check again for the result in the cache, maybe it was added between the read and write locks acquires
-            if let Some(cached_val) = cache.map.get(position) {
+            if let Some(cached_val) = cache.map.get(&key) {
                 self.hits.increment(1);
                 let cached_val = cached_val.clone();
                 /* We would like to assert (computed_val == cached_val), but this is highly unreliable due to */
@@ -57,20 +81,30 @@ impl<Game: crate::game::Game> ValueFuncCache<Game> {
                 /* This is more significant when the number of layers and params in the model is large, and it is */
                 /* even more significant on the beginning of the training process, where the model contains random */
                 /* values which cause very large or very small numbers. */
-                return cached_val;
+                return Some(cached_val);
             }
 
             // Remove oldest cached elements if needed
             while cache.deque.len() >= self.max_size {
-                let pos = cache.deque.pop_front().unwrap();
-                cache.map.remove(&pos);
+                let key = cache.deque.pop_front().unwrap();
+                cache.map.remove(&key);
             }
 
             // Insert newly computed element to cache
-            cache.map.insert(position.clone(), computed_val.clone());
-            cache.deque.push_back(position.clone());
+            cache.map.insert(key.clone(), computed_val.clone());
+            cache.deque.push_back(key);
             self.misses.increment(1);
-            computed_val
+            Some(computed_val)
         }
     }
+
+    /// Remove all cached values
+    ///
+    /// Values that are being computed concurrently with this call will not be inserted to the cache.
+    pub fn clear(&self) {
+        let mut cache = self.lock.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        cache.map.clear();
+        cache.deque.clear();
+    }
 }
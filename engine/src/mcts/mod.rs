@@ -1,4 +1,6 @@
 pub mod cache;
+pub mod table;
+pub mod tablebase;
 pub mod value_func;
 
 use itertools::Itertools;
@@ -13,7 +15,8 @@ use std::time::Instant;
 
 use crate::game::player::GamePlayer;
 use crate::game::{GameColor, GameStatus, Position};
-use crate::mcts::value_func::ValueFunction;
+use crate::mcts::tablebase::Tablebase;
+use crate::mcts::value_func::{Evaluation, ValueFunction, Wdl};
 use crate::util::metric::RunningAverage;
 
 /// Monte Carlo Tree Search (MCTS) implementation
@@ -42,6 +45,14 @@ struct MctsEdge<Move> {
 
     /// This is the variable w from UCT formula
     score_w: f32,
+
+    /// Sum of the win/draw/loss probabilities of the simulations, from the perspective of the first player
+    wdl_w: Wdl,
+
+    /// Sum and number of the plies left until the end of the game from the target position, of the simulations
+    /// that reached a finished game or were evaluated with a moves left prediction
+    moves_left_w: f32,
+    moves_left_n: u32,
 }
 
 impl<Move> MctsEdge<Move> {
@@ -51,8 +62,15 @@ impl<Move> MctsEdge<Move> {
             init_score,
             simulations_n: 0,
             score_w: 0.0,
+            wdl_w: Wdl::new(0.0, 0.0, 0.0),
+            moves_left_w: 0.0,
+            moves_left_n: 0,
         }
     }
+
+    fn moves_left(&self) -> Option<f32> {
+        (self.moves_left_n > 0).then(|| self.moves_left_w / self.moves_left_n as f32)
+    }
 }
 
 pub struct MctsPlayer<Game: crate::game::Game> {
@@ -64,7 +82,10 @@ pub struct MctsPlayer<Game: crate::game::Game> {
     temperature: TemperaturePolicy,
     prior_noise_alpha: f32,
     prior_noise_epsilon: f32,
+    contempt: f32,
+    moves_left: MovesLeftParams,
     value_func: Arc<dyn ValueFunction<Game>>,
+    tablebase: Option<Arc<dyn Tablebase<Game>>>,
 
     search_duration_metric: RunningAverage,
 }
@@ -75,7 +96,14 @@ pub struct MctsParams<Game: crate::game::Game> {
     pub temperature: TemperaturePolicy,
     pub prior_noise_alpha: f32,
     pub prior_noise_epsilon: f32,
+    /// The value of a draw for the player to move at the root is -contempt, and +contempt for the opponent.
+    /// A positive contempt avoids draws, a negative one prefers them.
+    pub contempt: f32,
+    pub moves_left: MovesLeftParams,
     pub value_func: Arc<dyn ValueFunction<Game>>,
+    /// If set, positions known by the tablebase are leaves with their exact value, and the moves of a known root
+    /// position are chosen among the tablebase best moves
+    pub tablebase: Option<Arc<dyn Tablebase<Game>>>,
 }
 impl<Game: crate::game::Game> MctsParams<Game> {
     pub fn new(sim_num: u32, value_func: Arc<dyn ValueFunction<Game>>) -> Self {
@@ -85,7 +113,10 @@ impl<Game: crate::game::Game> MctsParams<Game> {
             temperature: TemperaturePolicy::constant(1.0),
             prior_noise_alpha: 0.0,
             prior_noise_epsilon: 0.0,
+            contempt: 0.0,
+            moves_left: MovesLeftParams::default(),
             value_func,
+            tablebase: None,
         }
     }
 }
@@ -97,7 +128,10 @@ impl<Game: crate::game::Game> Clone for MctsParams<Game> {
             temperature: self.temperature.clone(),
             prior_noise_alpha: self.prior_noise_alpha,
             prior_noise_epsilon: self.prior_noise_epsilon,
+            contempt: self.contempt,
+            moves_left: self.moves_left,
             value_func: Arc::clone(&self.value_func),
+            tablebase: self.tablebase.clone(),
         }
     }
 }
@@ -124,8 +158,11 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
             explore_factor: params.explore_factor,
             prior_noise_alpha: params.prior_noise_alpha,
             prior_noise_epsilon: params.prior_noise_epsilon,
+            contempt: params.contempt,
+            moves_left: params.moves_left,
             temperature: params.temperature,
             value_func: params.value_func,
+            tablebase: params.tablebase,
             search_duration_metric,
         }
     }
@@ -170,28 +207,39 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
                 e_target
             };
             let leaf_pos = &self.search_tree[leaf_id].position;
-
-            let eval = if repetition_reached {
-                0.0
+            /* The root is always expanded, as its moves are needed */
+            let tablebase_winner = self
+                .tablebase
+                .as_ref()
+                .filter(|_| leaf_id != self.root.unwrap())
+                .and_then(|tablebase| tablebase.probe(leaf_pos));
+
+            let (wdl, moves_left) = if repetition_reached {
+                (Wdl::from_winner(None), Some(0.0))
             } else if let GameStatus::Finished(winner) = leaf_pos.status() {
-                GameColor::to_signed_one(winner) as f32
+                (Wdl::from_winner(winner), Some(0.0))
+            } else if let Some(winner) = tablebase_winner {
+                /* Not expanded, the leaf is selected again with the same exact value */
+                (Wdl::from_winner(winner), None)
             } else {
                 /* Run value function once to obtain "simulation" value and initial children scores (probabilities) */
-                let (per_move_val, eval) = self.simulate(leaf_id);
+                let eval = self.simulate(pos_history, &path_to_selection);
+                let wdl = eval.wdl_or_value();
+                let moves_left = eval.moves_left;
 
                 /* Expand leaf and assign initial scores */
-                self.create_children(leaf_id, per_move_val);
+                self.create_children(leaf_id, eval.moves_probs);
 
                 /* Add Dirichlet noise to root initial probabilities */
                 if leaf_id == self.root.unwrap() {
                     self.add_dirichlet_noise(leaf_id);
                 }
 
-                eval
+                (wdl, moves_left)
             };
 
             /* back propagate the position score to the parents */
-            self.backpropagate(path_to_selection, eval);
+            self.backpropagate(path_to_selection, wdl, moves_left);
         }
     }
 
@@ -213,14 +261,18 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
                 .edges(node_id)
                 .map(|edge| edge.weight().simulations_n)
                 .sum::<u32>();
+            let (moves_left_w, moves_left_n) = self.search_tree.edges(node_id).fold((0.0, 0), |(w, n), edge| {
+                (w + edge.weight().moves_left_w, n + edge.weight().moves_left_n)
+            });
+            let node_moves_left = (moves_left_n > 0).then(|| moves_left_w / moves_left_n as f32);
 
             /* Node is not a leaf, choose best child and continue in it's sub tree */
             let edge = self
                 .search_tree
                 .edges(node_id)
                 .max_by(|e1, e2| {
-                    let val1 = self.calc_selection_heuristic(e1.weight(), node_simcount);
-                    let val2 = self.calc_selection_heuristic(e2.weight(), node_simcount);
+                    let val1 = self.calc_selection_heuristic(e1.weight(), node_simcount, node_moves_left);
+                    let val2 = self.calc_selection_heuristic(e2.weight(), node_simcount, node_moves_left);
                     val1.partial_cmp(&val2).unwrap_or(std::cmp::Ordering::Equal)
                 })
                 .unwrap();
@@ -230,13 +282,27 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
         }
     }
 
-    fn calc_selection_heuristic(&self, edge: &MctsEdge<Game::Move>, parent_simcount: u32) -> f32 {
-        let exploit = if edge.simulations_n == 0 {
+    fn calc_selection_heuristic(
+        &self,
+        edge: &MctsEdge<Game::Move>,
+        parent_simcount: u32,
+        parent_moves_left: Option<f32>,
+    ) -> f32 {
+        let mut exploit = if edge.simulations_n == 0 {
             0.0
         } else {
             edge.score_w / edge.simulations_n as f32
         };
 
+        /* Moves left tie-breaker, prefer shorter lines when winning and longer lines when losing */
+        if let (Some(edge_moves_left), Some(parent_moves_left)) = (edge.moves_left(), parent_moves_left)
+            && self.moves_left.slope != 0.0
+        {
+            let max_effect = self.moves_left.max_effect;
+            let effect = (self.moves_left.slope * (edge_moves_left - parent_moves_left)).clamp(-max_effect, max_effect);
+            exploit -= effect * exploit;
+        }
+
         let explore =
             self.explore_factor * edge.init_score * ((parent_simcount as f32).sqrt() / (1 + edge.simulations_n) as f32);
 
@@ -261,14 +327,37 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
         }
     }
 
-    fn simulate(&mut self, leaf_id: NodeIndex) -> (Vec<(Game::Move, f32)>, f32) {
-        let position = &self.search_tree[leaf_id].position;
-        debug_assert!(position.status().is_ongoing());
-        self.value_func.evaluate(position)
+    fn simulate(&mut self, pos_history: &[Game::Position], path: &[EdgeIndex]) -> Evaluation<Game::Move> {
+        /* The last positions of the game followed by the positions along the path, ending with the leaf */
+        let history_len = self.value_func.history_len();
+        let trajectory = path
+            .iter()
+            .rev()
+            .take(history_len)
+            .map(|idx| {
+                let (_e_source, e_target) = self.search_tree.edge_endpoints(*idx).unwrap();
+                &self.search_tree[e_target].position
+            })
+            .collect_vec();
+        let game_history = &pos_history[pos_history.len().saturating_sub(history_len - trajectory.len())..];
+        let history = game_history
+            .iter()
+            .chain(trajectory.into_iter().rev())
+            .cloned()
+            .collect_vec();
+
+        debug_assert!(history.last().unwrap().status().is_ongoing());
+        self.value_func.evaluate(&history)
     }
 
-    fn backpropagate(&mut self, path: Vec<EdgeIndex>, score: f32) {
-        for edge_id in path {
+    fn backpropagate(&mut self, path: Vec<EdgeIndex>, wdl: Wdl, moves_left: Option<f32>) {
+        /* The expected score from the first player perspective, with draws valued by the contempt */
+        let root_player = self.search_tree[self.root.unwrap()].position.turn();
+        let draw_score = -self.contempt * GameColor::to_signed_one(Some(root_player)) as f32;
+        let score = wdl.expected_score() + wdl.draw * draw_score;
+
+        let path_len = path.len();
+        for (depth, edge_id) in path.into_iter().enumerate() {
             let (e_source, _e_target) = self.search_tree.edge_endpoints(edge_id).unwrap();
             let player_to_play = self.search_tree[e_source].position.turn();
             let edge = self.search_tree.edge_weight_mut(edge_id).unwrap();
@@ -277,6 +366,14 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
                 GameColor::Player1 => score,
                 GameColor::Player2 => -score,
             };
+            edge.wdl_w.win += wdl.win;
+            edge.wdl_w.draw += wdl.draw;
+            edge.wdl_w.loss += wdl.loss;
+            if let Some(moves_left) = moves_left {
+                /* The plies from the edge target to the leaf, followed by the plies left from the leaf */
+                edge.moves_left_w += moves_left + (path_len - 1 - depth) as f32;
+                edge.moves_left_n += 1;
+            }
         }
     }
 
@@ -340,7 +437,10 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
             // Tree was saved from the last search
             // Look for the position in the first three layers of the tree
             // TODO consider increasing depth limit
-            match self.find_node_with_position(position, 3) {
+            // The scores with contempt depend on the root player, and can't be reused from the other player's search
+            let root_turn = self.search_tree[self.root.unwrap()].position.turn();
+            let reusable = self.contempt == 0.0 || root_turn == position.turn();
+            match self.find_node_with_position(position, 3).filter(|_| reusable) {
                 Some(node) => {
                     self.remove_all_but_subtree(node);
                 }
@@ -373,17 +473,69 @@ impl<Game: crate::game::Game> MctsPlayer<Game> {
 
         // normalize sim counts to create a valid distribution -> (move, simcount / simcount_total)
         let simcount_total: u32 = moves_and_simcounts.iter().map(|&(_, simcount)| simcount).sum();
-        let res = moves_and_simcounts
+        let mut res = moves_and_simcounts
             .into_iter()
             .map(|(m, simcount)| (m, simcount as f32 / simcount_total as f32))
             .collect_vec();
 
+        /* Keep only the tablebase best moves, uniformly if none of them was visited */
+        if let Some(best_moves) = self
+            .tablebase
+            .as_ref()
+            .and_then(|tablebase| tablebase.best_moves(position))
+        {
+            res.retain(|(m, _p)| best_moves.contains(m));
+            let probs_sum: f32 = res.iter().map(|(_m, p)| p).sum();
+            let res_len = res.len();
+            for (_m, p) in res.iter_mut() {
+                *p = if probs_sum > 0.0 {
+                    *p / probs_sum
+                } else {
+                    1.0 / res_len as f32
+                };
+            }
+        }
+
         self.search_duration_metric
             .set(search_start_time.elapsed().as_secs_f64());
 
         res
     }
 
+    /// The win/draw/loss probabilities of the root position estimated by the last search, from the perspective of
+    /// the first player
+    ///
+    /// The probabilities are the average over all simulations. Value functions without draw predictions estimate
+    /// no draws, but draws may still be reached during the search.
+    pub fn root_wdl(&self) -> Option<Wdl> {
+        let root = self.root?;
+        let (simulations_n, wdl_w) = self
+            .search_tree
+            .edges(root)
+            .fold((0, Wdl::new(0.0, 0.0, 0.0)), |(n, w), edge| {
+                let e = edge.weight();
+                (
+                    n + e.simulations_n,
+                    Wdl::new(w.win + e.wdl_w.win, w.draw + e.wdl_w.draw, w.loss + e.wdl_w.loss),
+                )
+            });
+        if simulations_n == 0 {
+            return None;
+        }
+        let n = simulations_n as f32;
+        Some(Wdl::new(wdl_w.win / n, wdl_w.draw / n, wdl_w.loss / n))
+    }
+
+    /// Set the contempt used by the following searches, see [`MctsParams::contempt`]
+    pub fn set_contempt(&mut self, contempt: f32) {
+        if contempt != self.contempt {
+            /* The scores of the saved tree were accumulated with the previous contempt */
+            self.search_tree.clear();
+            self.root = None;
+        }
+        self.contempt = contempt;
+    }
+
     pub fn choose_move_from_probabilities(
         &self,
         pos_history: &[Game::Position],
@@ -453,6 +605,28 @@ impl<Game: crate::game::Game> GamePlayer<Game> for MctsPlayer<Game> {
     }
 }
 
+/// Parameters of the moves left tie-breaker
+///
+/// The score of a move is scaled by `1 - effect` where the effect is `slope` times the difference in plies between
+/// the move's line and the average of its siblings, bounded by `max_effect`. Moves left are known for lines that
+/// reached a finished game, or evaluated by a value function that predicts moves left.
+///
+/// The tie-breaker is disabled by default. It should be enabled only with a value function that predicts moves left,
+/// otherwise the averages are of the lines that reached a finished game only.
+#[derive(Clone, Copy, Debug)]
+pub struct MovesLeftParams {
+    pub slope: f32,
+    pub max_effect: f32,
+}
+impl Default for MovesLeftParams {
+    fn default() -> Self {
+        Self {
+            slope: 0.0,
+            max_effect: 0.03,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct TemperaturePolicy {
     temperatures: Vec<(usize, f32)>,
@@ -487,3 +661,218 @@ impl TemperaturePolicy {
             .unwrap_or(self.last_temperature)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use itertools::Itertools;
+
+    use crate::chess::{ChessGame, ChessMove};
+    use crate::game::player::GamePlayer;
+    use crate::game::{Game, Position};
+    use crate::hex::{HexGame, HexMove};
+    use crate::mcts::table::TableValueFunction;
+    use crate::mcts::value_func::ValueFunction;
+    use crate::mcts::{MctsParams, MctsPlayer, TemperaturePolicy};
+    use crate::ttt::{TttGame, TttMove, TttPosition};
+
+    fn root_priors<Game: crate::game::Game>(player: &MctsPlayer<Game>) -> Vec<(Game::Move, f32)> {
+        player
+            .search_tree
+            .edges(player.root.unwrap())
+            .map(|e| (e.weight().m.clone(), e.weight().init_score))
+            .collect()
+    }
+
+    fn root_visits<Game: crate::game::Game>(player: &MctsPlayer<Game>) -> Vec<(Game::Move, u32)> {
+        player
+            .search_tree
+            .edges(player.root.unwrap())
+            .map(|e| (e.weight().m.clone(), e.weight().simulations_n))
+            .collect()
+    }
+
+    fn most_visited<Game: crate::game::Game>(player: &MctsPlayer<Game>) -> Game::Move {
+        root_visits(player).into_iter().max_by_key(|(_m, n)| *n).unwrap().0
+    }
+
+    #[test]
+    fn prior_noise() {
+        let start = TttPosition::new();
+        let table = TableValueFunction::<TttGame>::new(0.0).with_entry(
+            start,
+            vec![(TttMove::new(1, 1), 0.6), (TttMove::new(0, 0), 0.4)],
+            0.0,
+        );
+        let table = Arc::new(table);
+
+        /* Without noise the priors are the table policy */
+        let mut player = MctsPlayer::new(MctsParams::new(10, table.clone()));
+        player.calc_moves_probabilities(&[start]);
+        for (m, p) in root_priors(&player) {
+            let expected = match (m.row(), m.column()) {
+                (1, 1) => 0.6,
+                (0, 0) => 0.4,
+                _ => 0.0,
+            };
+            assert!((p - expected).abs() < 1e-6, "{} {} {}", m, p, expected);
+        }
+
+        /* With noise the priors are mixed with a distribution over all moves */
+        let epsilon = 0.25;
+        let mut params = MctsParams::new(10, table.clone() as Arc<dyn ValueFunction<TttGame>>);
+        params.prior_noise_alpha = 1.0;
+        params.prior_noise_epsilon = epsilon;
+        let mut player = MctsPlayer::new(params);
+        player.calc_moves_probabilities(&[start]);
+        let priors = root_priors(&player);
+        assert!((priors.iter().map(|(_m, p)| p).sum::<f32>() - 1.0).abs() < 1e-4);
+        for (m, p) in priors {
+            let table_prior = match (m.row(), m.column()) {
+                (1, 1) => 0.6,
+                (0, 0) => 0.4,
+                _ => 0.0,
+            };
+            assert!(p > 0.0);
+            assert!(p >= (1.0 - epsilon) * table_prior - 1e-6);
+            assert!(p <= (1.0 - epsilon) * table_prior + epsilon + 1e-6);
+        }
+    }
+
+    #[test]
+    fn temperature() {
+        /* Uniform priors, but the center wins for the first player */
+        let start = TttPosition::new();
+        let center = TttMove::new(1, 1);
+        let table = TableValueFunction::<TttGame>::new(0.0).with_entry(start.moved_position(center), vec![], 1.0);
+        let mut params = MctsParams::new(100, Arc::new(table) as Arc<dyn ValueFunction<TttGame>>);
+        params.temperature = TemperaturePolicy::constant(0.0);
+        let mut player = MctsPlayer::new(params);
+        for _ in 0..3 {
+            assert_eq!(player.next_move(&[start]), Some(center));
+        }
+        assert_eq!(most_visited(&player), center);
+
+        let policy = TemperaturePolicy::scheduled(vec![(2, 1.0), (5, 0.5)], 0.0);
+        assert_eq!(policy.get_temperature(0), 1.0);
+        assert_eq!(policy.get_temperature(1), 1.0);
+        assert_eq!(policy.get_temperature(2), 0.5);
+        assert_eq!(policy.get_temperature(4), 0.5);
+        assert_eq!(policy.get_temperature(5), 0.0);
+        assert_eq!(policy.get_temperature(100), 0.0);
+    }
+
+    #[test]
+    fn tree_reuse() {
+        let table = Arc::new(TableValueFunction::<TttGame>::new(0.0));
+        let mut player = MctsPlayer::new(MctsParams::new(50, table.clone()));
+        let mut game = TttGame::new();
+
+        player.calc_moves_probabilities(game.pos_history());
+        assert!(table.evaluated_positions()[0] == *game.position());
+        assert!(!table.calls().is_empty());
+
+        /* The root was already expanded and is not evaluated again */
+        table.clear_calls();
+        player.calc_moves_probabilities(game.pos_history());
+        assert!(!table.evaluated_positions().contains(game.position()));
+
+        /* The subtree of the played move is reused */
+        game.play_single_turn(most_visited(&player));
+        table.clear_calls();
+        player.calc_moves_probabilities(game.pos_history());
+        assert!(!table.evaluated_positions().contains(game.position()));
+        assert!(player.search_tree[player.root.unwrap()].position == *game.position());
+
+        /* An unrelated position discards the tree */
+        let other = TttGame::from_position(TttPosition::new().moved_position(TttMove::new(2, 2)));
+        table.clear_calls();
+        player.calc_moves_probabilities(other.pos_history());
+        assert!(table.evaluated_positions()[0] == *other.position());
+    }
+
+    #[test]
+    fn hex_history_and_priors() {
+        type Hex4 = HexGame<4>;
+        let mut game = Hex4::new();
+        game.play_single_turn(HexMove::new(0, 0));
+        game.play_single_turn(HexMove::new(3, 3));
+
+        let favorite = HexMove::new(1, 2);
+        let table = TableValueFunction::<Hex4>::new(0.0)
+            .with_entry(*game.position(), vec![(favorite, 0.9)], 0.0)
+            .with_history_len(3);
+        let table = Arc::new(table);
+        let mut player = MctsPlayer::new(MctsParams::new(200, table.clone()));
+        player.calc_moves_probabilities(game.pos_history());
+
+        /* Equal values, the visits follow the priors */
+        assert_eq!(most_visited(&player), favorite);
+
+        /* The histories are connected sequences of positions ending at the evaluated leaf */
+        let calls = table.calls();
+        assert!(!calls.is_empty());
+        assert!(calls[0] == game.pos_history());
+        for history in calls {
+            assert_eq!(history.len(), 3);
+            assert!(history.last().unwrap().status().is_ongoing());
+            for (pos, next) in history.iter().tuple_windows() {
+                assert!(pos.legal_moves().any(|m| pos.moved_position(m) == *next));
+            }
+        }
+    }
+
+    #[test]
+    fn chess_repetition() {
+        let mut game = ChessGame::new();
+        for m in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"] {
+            game.play_single_turn(ChessMove::from_lan(m).unwrap());
+        }
+        let start = game.pos_history()[0];
+
+        /* Every evaluated position is winning for white, so black should repeat the start position for a draw */
+        let table = Arc::new(TableValueFunction::<ChessGame>::new(1.0));
+        let mut player = MctsPlayer::new(MctsParams::new(300, table.clone()));
+        player.calc_moves_probabilities(game.pos_history());
+        assert_eq!(most_visited(&player), ChessMove::from_lan("f6g8").unwrap());
+
+        /* The repeated position is scored as a draw without being evaluated */
+        assert!(!table.evaluated_positions().contains(&start));
+    }
+
+    #[test]
+    fn table_from_json() {
+        let parse_position = |s: &str| {
+            s.split_whitespace()
+                .map(|idx| TttMove::from_idx(idx.parse().unwrap()))
+                .fold(TttPosition::new(), |pos, m| pos.moved_position(m))
+        };
+        let json = r#"{
+            "default_value": -0.5,
+            "positions": [
+                { "position": "", "value": 0.25, "policy": { "(1, 1)": 3.0, "(0, 2)": 1.0 } },
+                { "position": "4 0", "value": 1.0 }
+            ]
+        }"#;
+        let table = TableValueFunction::<TttGame>::from_json(json, parse_position);
+
+        let eval = table.evaluate(&[TttPosition::new()]);
+        assert_eq!(eval.value, 0.25);
+        for (m, p) in eval.moves_probs {
+            let expected = match m.to_idx() {
+                4 => 0.75,
+                2 => 0.25,
+                _ => 0.0,
+            };
+            assert_eq!(p, expected);
+        }
+
+        let eval = table.evaluate(&[parse_position("4 0")]);
+        assert_eq!(eval.value, 1.0);
+        assert!(eval.moves_probs.iter().all(|(_m, p)| *p == 1.0 / 7.0));
+
+        assert_eq!(table.evaluate(&[parse_position("4")]).value, -0.5);
+        assert_eq!(table.calls().len(), 3);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use itertools::Itertools;
+
+use crate::game::Position;
+use crate::mcts::value_func::{Evaluation, ValueFunction};
+
+/// An entry of a [`TableValueFunction`]
+#[derive(Clone, Debug)]
+pub struct TableEntry<Move> {
+    /// Probabilities of some of the legal moves. Legal moves that are not listed get a zero probability and the
+    /// probabilities are normalized. If none of the legal moves is listed, the probabilities are uniform.
+    pub policy: Vec<(Move, f32)>,
+    /// The position value in range [-1,1], from the perspective of the first player
+    pub value: f32,
+}
+
+/// A deterministic value function answering from a table of positions, for tests
+///
+/// Positions that are not in the table are evaluated with uniform probabilities and the default value. Every call is
+/// recorded, and can be inspected using [`Self::calls`].
+pub struct TableValueFunction<Game: crate::game::Game> {
+    table: HashMap<Game::Position, TableEntry<Game::Move>>,
+    default_value: f32,
+    history_len: usize,
+    calls: Mutex<Vec<Vec<Game::Position>>>,
+}
+
+impl<Game: crate::game::Game> TableValueFunction<Game> {
+    pub fn new(default_value: f32) -> Self {
+        Self {
+            table: HashMap::new(),
+            default_value,
+            history_len: 1,
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Parse a table from JSON
+    ///
+    /// The JSON format is:
+    /// ```json
+    /// {
+    ///     "default_value": 0.0,
+    ///     "positions": [
+    ///         { "position": "...", "value": 0.5, "policy": { "<move>": 0.7, "<move>": 0.3 } }
+    ///     ]
+    /// }
+    /// ```
+    /// Positions are parsed by the given function, and moves are matched by their `Display` string against the
+    /// legal moves of the position. `default_value` and `policy` are optional.
+    pub fn from_json(json: &str, parse_position: impl Fn(&str) -> Game::Position) -> Self {
+        let json_table: JsonTable = serde_json::from_str(json).expect("invalid table json");
+        let mut table = Self::new(json_table.default_value);
+        for entry in json_table.positions {
+            let pos = parse_position(&entry.position);
+            let policy = entry
+                .policy
+                .into_iter()
+                .map(|(move_str, prob)| {
+                    let m = pos
+                        .legal_moves()
+                        .find(|m| m.to_string() == move_str)
+                        .unwrap_or_else(|| panic!("not a legal move: {:?}", move_str));
+                    (m, prob)
+                })
+                .collect_vec();
+            table.insert(pos, policy, entry.value);
+        }
+        table
+    }
+
+    pub fn insert(&mut self, pos: Game::Position, policy: Vec<(Game::Move, f32)>, value: f32) {
+        self.table.insert(pos, TableEntry { policy, value });
+    }
+
+    pub fn with_entry(mut self, pos: Game::Position, policy: Vec<(Game::Move, f32)>, value: f32) -> Self {
+        self.insert(pos, policy, value);
+        self
+    }
+
+    /// Set the number of positions requested from the search, see [`ValueFunction::history_len`]
+    pub fn with_history_len(mut self, history_len: usize) -> Self {
+        assert!(history_len > 0);
+        self.history_len = history_len;
+        self
+    }
+
+    /// The histories of all the calls so far, in order
+    pub fn calls(&self) -> Vec<Vec<Game::Position>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The evaluated positions of all the calls so far, in order
+    pub fn evaluated_positions(&self) -> Vec<Game::Position> {
+        let calls = self.calls.lock().unwrap();
+        calls.iter().map(|history| history.last().unwrap().clone()).collect()
+    }
+
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+}
+
+impl<Game: crate::game::Game> ValueFunction<Game> for TableValueFunction<Game> {
+    fn evaluate(&self, history: &[Game::Position]) -> Evaluation<Game::Move> {
+        self.calls.lock().unwrap().push(history.to_vec());
+
+        let pos = history.last().unwrap();
+        let moves = pos.legal_moves().collect_vec();
+        let (probs, value) = match self.table.get(pos) {
+            Some(entry) => {
+                let probs = moves
+                    .iter()
+                    .map(|m| entry.policy.iter().find(|(m2, _p)| m2 == m).map_or(0.0, |(_m, p)| *p))
+                    .collect_vec();
+                (probs, entry.value)
+            }
+            None => (vec![0.0; moves.len()], self.default_value),
+        };
+
+        let p_sum: f32 = probs.iter().sum();
+        let probs = if p_sum > 0.0 {
+            probs.into_iter().map(|p| p / p_sum).collect_vec()
+        } else {
+            vec![1.0 / moves.len() as f32; moves.len()]
+        };
+        Evaluation::new(moves.into_iter().zip(probs).collect(), value)
+    }
+
+    fn history_len(&self) -> usize {
+        self.history_len
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct JsonTable {
+    #[serde(default)]
+    default_value: f32,
+    positions: Vec<JsonEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonEntry {
+    position: String,
+    value: f32,
+    #[serde(default)]
+    policy: HashMap<String, f32>,
+}
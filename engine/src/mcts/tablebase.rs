@@ -0,0 +1,11 @@
+use crate::game::GameColor;
+
+/// Perfect play knowledge of some positions, such as endgame tablebases
+pub trait Tablebase<Game: crate::game::Game>: Sync + Send {
+    /// The winner of a position with perfect play, `Some(None)` for a draw, or `None` if the position is unknown
+    fn probe(&self, pos: &Game::Position) -> Option<Option<GameColor>>;
+
+    /// The moves of a position that keep its outcome and make progress toward it, or `None` if the position is
+    /// unknown
+    fn best_moves(&self, pos: &Game::Position) -> Option<Vec<Game::Move>>;
+}
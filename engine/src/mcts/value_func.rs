@@ -1,13 +1,103 @@
+use crate::game::{GameColor, Move};
+
 pub trait ValueFunction<Game: crate::game::Game>: Sync + Send {
     /// Evaluate a position
     ///
-    /// position - The position to evaluate
+    /// history - The positions of the game so far, the last one is the position to evaluate. Contains at least the
+    /// last `history_len()` positions, or all of the positions if the game is shorter.
     ///
-    /// Returns a tuple of a scalar value score of the position and per-move scores/probabilities.
-    /// The scalar is the current position value in range [-1,1]. 1 if player1 is winning and -1 if
-    /// player2 is winning The per-move probabilities should have a sum of 1, greater value is a
-    /// better move
-    fn evaluate(&self, position: &Game::Position) -> (Vec<(Game::Move, f32)>, f32);
+    /// Returns the per-move probabilities and the value of the position, see [`Evaluation`].
+    fn evaluate(&self, history: &[Game::Position]) -> Evaluation<Game::Move>;
+
+    /// The number of last positions the evaluation depends on, including the evaluated position
+    fn history_len(&self) -> usize {
+        1
+    }
+}
+
+/// The output of a value function for a single position
+#[derive(Clone, Debug)]
+pub struct Evaluation<Move> {
+    /// Per-move scores/probabilities. The probabilities should have a sum of 1, greater value is a better move
+    pub moves_probs: Vec<(Move, f32)>,
+    /// The position value in range [-1,1]. 1 if player1 is winning and -1 if player2 is winning
+    pub value: f32,
+    /// Win/draw/loss probabilities, available if the value function predicts them.
+    /// If available, `value` is the expected score of them.
+    pub wdl: Option<Wdl>,
+    /// The predicted number of plies until the end of the game, if the value function predicts it
+    pub moves_left: Option<f32>,
+}
+impl<M: Move> Evaluation<M> {
+    pub fn new(moves_probs: Vec<(M, f32)>, value: f32) -> Self {
+        Self {
+            moves_probs,
+            value,
+            wdl: None,
+            moves_left: None,
+        }
+    }
+
+    pub fn from_wdl(moves_probs: Vec<(M, f32)>, wdl: Wdl) -> Self {
+        Self {
+            moves_probs,
+            value: wdl.expected_score(),
+            wdl: Some(wdl),
+            moves_left: None,
+        }
+    }
+
+    /// The win/draw/loss probabilities, or probabilities without draws derived from the value if not available
+    pub fn wdl_or_value(&self) -> Wdl {
+        self.wdl.unwrap_or_else(|| Wdl::from_value(self.value))
+    }
+
+    /// The evaluation of the flipped position, see [`crate::game::Position::flipped`]
+    pub fn flipped(self) -> Self {
+        Self {
+            moves_probs: self.moves_probs.into_iter().map(|(m, p)| (m.flipped(), p)).collect(),
+            value: -self.value,
+            wdl: self.wdl.map(|wdl| wdl.flipped()),
+            moves_left: self.moves_left,
+        }
+    }
+}
+
+/// Win, draw and loss probabilities of a position, from the perspective of the first player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wdl {
+    pub win: f32,
+    pub draw: f32,
+    pub loss: f32,
+}
+impl Wdl {
+    pub fn new(win: f32, draw: f32, loss: f32) -> Self {
+        Self { win, draw, loss }
+    }
+
+    /// Probabilities without draws with an expected score of the given value, in range [-1,1]
+    pub fn from_value(value: f32) -> Self {
+        Self::new((1.0 + value) / 2.0, 0.0, (1.0 - value) / 2.0)
+    }
+
+    /// The certain outcome of a finished game
+    pub fn from_winner(winner: Option<GameColor>) -> Self {
+        match winner {
+            Some(GameColor::Player1) => Self::new(1.0, 0.0, 0.0),
+            Some(GameColor::Player2) => Self::new(0.0, 0.0, 1.0),
+            None => Self::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    /// The expected score in range [-1,1], a win is 1, a draw is 0 and a loss is -1
+    pub fn expected_score(&self) -> f32 {
+        self.win - self.loss
+    }
+
+    /// The probabilities from the perspective of the second player
+    pub fn flipped(&self) -> Self {
+        Self::new(self.loss, self.draw, self.win)
+    }
 }
 
 // pub struct ValueFunctionRand {
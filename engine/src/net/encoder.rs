@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use crate::game::{Bitboard, Move};
+
+/// Encodes positions into the input planes of a network
+///
+/// A model can only be used with the encoder it was trained with. Encoders are identified by a name and a version,
+/// and an encoder must not change once models were trained with it. A new input representation is added as a new
+/// encoder, or as a new version of an existing one, so old models keep working.
+pub trait Encoder<Game: crate::game::Game>: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn version(&self) -> u32;
+
+    /// The identifier of the encoder used in configs, `<name>-v<version>`
+    fn id(&self) -> String {
+        format!("{}-v{}", self.name(), self.version())
+    }
+
+    /// The number of planes of an encoded position
+    fn planes_num(&self) -> usize;
+
+    /// The number of last positions the encoding depends on, including the encoded position
+    fn history_len(&self) -> usize {
+        1
+    }
+
+    /// The number of last positions read by the encoder, at least `history_len()`
+    ///
+    /// Positions before the last `history_len()` ones only affect the encoding through [`Self::cache_key`], such as
+    /// older occurrences of a repeated position.
+    fn lookback_len(&self) -> usize {
+        self.history_len()
+    }
+
+    /// Encode the last position of a history into `planes`, which must be of size `planes_num`
+    ///
+    /// All the planes are overwritten, so the caller may reuse its buffer between positions.
+    /// The history is the positions of the game so far, oldest first. Only the last `lookback_len()` positions are
+    /// used, and a shorter history is allowed at the beginning of a game.
+    /// The history is always given from the perspective of the first player, see [`super::flip_history_if_needed`].
+    fn encode(&self, history: &[Game::Position], planes: &mut [Game::Bitboard]);
+
+    /// State of the history read by the encoder and ignored by the positions equality, such as a move counter
+    ///
+    /// Evaluations are cached by the history together with this key, so histories that are encoded differently never
+    /// share a cache entry.
+    fn cache_key(&self, history: &[Game::Position]) -> u64 {
+        let _ = history;
+        0
+    }
+
+    /// Encode the last position of a history into newly allocated planes, see [`Self::encode`]
+    fn encode_to_vec(&self, history: &[Game::Position]) -> Vec<Game::Bitboard> {
+        let mut planes = vec![Game::Bitboard::new(); self.planes_num()];
+        self.encode(history, &mut planes);
+        planes
+    }
+}
+
+/// Maps moves to the indices of the policy output of a network
+///
+/// Like encoders, a model can only be used with the policy map it was trained with, and a policy map must not change
+/// once models were trained with it.
+pub trait PolicyMap<Game: crate::game::Game>: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn version(&self) -> u32;
+
+    /// The identifier of the policy map used in configs, `<name>-v<version>`
+    fn id(&self) -> String {
+        format!("{}-v{}", self.name(), self.version())
+    }
+
+    /// The size of the policy output
+    fn moves_num(&self) -> usize;
+
+    /// The index of a move in the policy output, in range `[0, moves_num())`
+    ///
+    /// The move is always given from the perspective of the first player, see [`super::flip_pos_if_needed`].
+    fn move_idx(&self, m: &Game::Move) -> usize;
+}
+
+/// The policy map of [`Move::to_nn_idx`], of size `Game::MOVES_NUM`
+pub struct FlatPolicyMap;
+impl<Game: crate::game::Game> PolicyMap<Game> for FlatPolicyMap {
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn moves_num(&self) -> usize {
+        Game::MOVES_NUM
+    }
+
+    fn move_idx(&self, m: &Game::Move) -> usize {
+        m.to_nn_idx()
+    }
+}
+
+/// A game with network encoders and policy maps
+pub trait EncodedGame: crate::game::Game {
+    /// All the encoders of the game, the first one is the default
+    fn encoders() -> Vec<Arc<dyn Encoder<Self>>>;
+
+    fn default_encoder() -> Arc<dyn Encoder<Self>> {
+        Self::encoders().into_iter().next().unwrap()
+    }
+
+    /// Get an encoder by its id, panics if the game has no such encoder
+    fn encoder(id: &str) -> Arc<dyn Encoder<Self>> {
+        Self::encoders()
+            .into_iter()
+            .find(|encoder| encoder.id() == id)
+            .unwrap_or_else(|| panic!("unknown encoder: {:?}", id))
+    }
+
+    /// All the policy maps of the game, the first one is the default
+    fn policy_maps() -> Vec<Arc<dyn PolicyMap<Self>>> {
+        vec![Arc::new(FlatPolicyMap)]
+    }
+
+    fn default_policy_map() -> Arc<dyn PolicyMap<Self>> {
+        Self::policy_maps().into_iter().next().unwrap()
+    }
+
+    /// Get a policy map by its id, panics if the game has no such policy map
+    fn policy_map(id: &str) -> Arc<dyn PolicyMap<Self>> {
+        Self::policy_maps()
+            .into_iter()
+            .find(|policy_map| policy_map.id() == id)
+            .unwrap_or_else(|| panic!("unknown policy map: {:?}", id))
+    }
+}
@@ -1,158 +1,726 @@
+pub mod encoder;
 pub mod model;
+pub mod remote;
+pub mod server;
+mod tune;
 
-use crate::game::{Bitboard, GameColor, Move, Position};
+use crate::game::{Bitboard, GameColor, Position};
 use crate::mcts::cache::ValueFuncCache;
+use crate::mcts::value_func::{Evaluation, ValueFunction, Wdl};
 use crate::util::batch::Batcher;
 use crate::util::metric::RunningAverage;
+use encoder::{Encoder, PolicyMap};
 use itertools::Itertools;
 use model::{InferenceConfig, Model};
 use ndarray::{Array2, Array4};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use server::InferenceServer;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use tune::BatchTuner;
+
+#[derive(Clone, Debug)]
+pub struct NNetworkParams {
+    /// Maximum number of positions evaluated in a single model run
+    pub batch_size: usize,
+    /// Maximum time a position waits for its batch to fill before the batch is computed partially filled
+    pub batch_deadline: Duration,
+    /// Run the model on a dedicated inference thread, see [`InferenceServer`].
+    /// If false, the search thread that fills a batch (or whose deadline expires) runs the model itself.
+    pub inference_server: bool,
+    /// Softmax temperature of the policy, a temperature greater than 1 flattens the moves probabilities
+    pub policy_temperature: f32,
+    /// Minimum probability of each legal move, the probabilities are normalized again after it is applied
+    pub min_prior: f32,
+    /// Choose the number of positions that triggers a batch and the batch deadline automatically, see
+    /// [`BatchTuner`]. `batch_size` and `batch_deadline` are used as upper bounds.
+    /// Not supported with the inference server, creating a network with both panics.
+    pub auto_batch: bool,
+    /// What to do when the network outputs a non-finite value or a non-finite score of a legal move
+    pub non_finite_policy: NonFinitePolicy,
+    /// If set, the inputs that produced non-finite outputs are appended to this file, as JSON lines
+    pub non_finite_log: Option<PathBuf>,
+}
+impl NNetworkParams {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size,
+            batch_deadline: Duration::from_millis(20),
+            inference_server: false,
+            policy_temperature: 1.0,
+            min_prior: 0.0,
+            auto_batch: false,
+            non_finite_policy: NonFinitePolicy::Uniform,
+            non_finite_log: None,
+        }
+    }
+}
+
+/// The handling of non-finite network outputs
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NonFinitePolicy {
+    /// Evaluate the position again, and fallback to [`Self::Uniform`] if the output is still not finite
+    ///
+    /// If the model was replaced during the evaluation, the position is evaluated by the new model, up to
+    /// [`NON_FINITE_MAX_RETRIES`] times. Otherwise, the position is evaluated once more in a batch of its own, as the
+    /// output of a sample may depend on the other samples of its batch. With the inference server, the position is
+    /// sent again to the server, and may be batched with the requests of other searches.
+    Retry,
+    /// Use uniform moves probabilities and a zero value
+    ///
+    /// The fallback evaluation is never cached, so the position is evaluated by the network again on its next visit.
+    Uniform,
+    /// Panic
+    Fail,
+}
+
+pub const NON_FINITE_MAX_RETRIES: usize = 3;
 
 pub struct NNetwork<Game: crate::game::Game> {
-    model: Mutex<Model>,
+    runner: Runner<Game>,
+    model_path: Mutex<PathBuf>,
+    inference_cfg: InferenceConfig,
+    encoder: Arc<dyn Encoder<Game>>,
+    policy_map: Arc<dyn PolicyMap<Game>>,
     cache: Option<Arc<ValueFuncCache<Game>>>,
-
-    batcher: Batcher<Vec<Game::Bitboard>, (Vec<f32>, f32)>,
+    policy_temperature: f32,
+    min_prior: f32,
+    non_finite_policy: NonFinitePolicy,
+    non_finite_log: Option<Mutex<std::fs::File>>,
+    /// Incremented on every model replacement
+    model_generation: AtomicU64,
 
     metrics: Mutex<Metrics>,
 }
 
+#[allow(clippy::large_enum_variant)]
+enum Runner<Game: crate::game::Game> {
+    Local {
+        model: Mutex<ModelRunner>,
+        batcher: Batcher<Vec<Game::Position>, NetOutput>,
+        batch_deadline: Duration,
+        /// The batch size of the model, batches are padded to it
+        capacity: usize,
+        tuner: Option<BatchTuner>,
+    },
+    Server(InferenceServer<Game>),
+}
+
 impl<Game: crate::game::Game> NNetwork<Game> {
     pub fn new(
         model_path: impl AsRef<Path>,
         inference_cfg: InferenceConfig,
-        batch_size: usize,
+        encoder: Arc<dyn Encoder<Game>>,
+        policy_map: Arc<dyn PolicyMap<Game>>,
+        params: NNetworkParams,
         cache: Option<Arc<ValueFuncCache<Game>>>,
-    ) -> Self {
+    ) -> Self
+    where
+        Game: 'static,
+    {
+        assert!(params.policy_temperature > 0.0);
+        assert!(params.min_prior >= 0.0);
+        let model_path = model_path.as_ref().to_path_buf();
+        let model = Model::new(&model_path, inference_cfg.clone());
+        let model = check_policy_output(model, &*encoder, &*policy_map, params.batch_size)
+            .unwrap_or_else(|e| panic!("{}: {}", model_path.display(), e));
+        let non_finite_log = params.non_finite_log.as_ref().map(|path| {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap_or_else(|e| panic!("failed to open {}: {}", path.display(), e));
+            Mutex::new(file)
+        });
+        assert!(
+            !(params.inference_server && params.auto_batch),
+            "automatic batch tuning is not supported with the inference server"
+        );
+        let runner = if params.inference_server {
+            Runner::Server(InferenceServer::new(
+                model,
+                Arc::clone(&encoder),
+                params.batch_size,
+                params.batch_deadline,
+            ))
+        } else {
+            let mut model = ModelRunner::new(model);
+            let tuner = params.auto_batch.then(|| {
+                let run_latencies = calibrate_run_latencies(&mut model, &*encoder, params.batch_size);
+                log::info!(
+                    "Calibrated model run latencies (batch size, latency): {:?}",
+                    run_latencies
+                );
+                BatchTuner::new(params.batch_size, params.batch_deadline, &run_latencies)
+            });
+            Runner::Local {
+                model: Mutex::new(model),
+                batcher: Batcher::new(params.batch_size),
+                batch_deadline: params.batch_deadline,
+                capacity: params.batch_size,
+                tuner,
+            }
+        };
         Self {
-            model: Mutex::new(Model::new(model_path, inference_cfg)),
+            runner,
+            model_path: Mutex::new(model_path),
+            inference_cfg,
+            encoder,
+            policy_map,
             cache,
-            batcher: Batcher::new(batch_size),
+            policy_temperature: params.policy_temperature,
+            min_prior: params.min_prior,
+            non_finite_policy: params.non_finite_policy,
+            non_finite_log,
+            model_generation: AtomicU64::new(0),
             metrics: Mutex::new(Metrics {
-                activation_count: metrics::counter!("model.activation_count"),
-                run_duration: RunningAverage::new(0.99, metrics::gauge!("model.run_duration")),
+                reload_count: metrics::counter!("model.reload_count"),
+                non_finite_count: metrics::counter!("model.non_finite.count"),
+                non_finite_retry_count: metrics::counter!("model.non_finite.retry_count"),
+                non_finite_uniform_count: metrics::counter!("model.non_finite.uniform_count"),
+            }),
+        }
+    }
+
+    /// Replace the model used by the network
+    ///
+    /// Batches that are already computed finish with the old model, every batch computed after this call uses the
+    /// new model. The cache is cleared, and evaluations computed concurrently by the old model are not cached. If the
+    /// policy output of the model does not match the policy map of the network, the error is returned and the current
+    /// model is kept.
+    pub fn replace_model(&self, model: Model) -> Result<(), String> {
+        let batch_size = match &self.runner {
+            Runner::Local { capacity, .. } => *capacity,
+            Runner::Server(server) => server.batch_size(),
+        };
+        let model = check_policy_output(model, &*self.encoder, &*self.policy_map, batch_size)?;
+        match &self.runner {
+            Runner::Local { model: runner, .. } => runner.lock().unwrap().model = model,
+            Runner::Server(server) => server.replace_model(model),
+        }
+        self.model_generation.fetch_add(1, Ordering::SeqCst);
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        self.metrics.lock().unwrap().reload_count.increment(1);
+        Ok(())
+    }
+
+    /// Load a model from a file with the network inference config, and replace the current model with it
+    ///
+    /// The model is loaded without blocking the searches that use the network, see [`Self::replace_model`]. If the
+    /// model can not be loaded, the error is returned and the current model is kept.
+    pub fn reload_model(&self, model_path: impl AsRef<Path>) -> Result<(), String> {
+        let model_path = model_path.as_ref();
+        log::info!("Reloading model from {}", model_path.display());
+        let model = Model::load(model_path, self.inference_cfg.clone())?;
+        self.replace_model(model)
+            .map_err(|e| format!("invalid model {}: {}", model_path.display(), e))?;
+        *self.model_path.lock().unwrap() = model_path.to_path_buf();
+        Ok(())
+    }
+
+    /// Watch the model file and reload the model whenever it is modified
+    ///
+    /// The file modification time is polled every `interval`, and the model is reloaded once the modification time
+    /// is the same in two consecutive polls, to avoid loading a partially written file. The model file should
+    /// preferably be replaced atomically, by writing to a temporary file and renaming it. If the modified file can
+    /// not be loaded, the error is logged, the current model is kept and the reload is retried on the next
+    /// modification.
+    ///
+    /// The watching thread exits when the network is dropped.
+    pub fn watch_model(self: &Arc<Self>, interval: Duration) -> JoinHandle<()>
+    where
+        Game: 'static,
+    {
+        let modified_time = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        let network: Weak<Self> = Arc::downgrade(self);
+        let model_path = self.model_path.lock().unwrap().clone();
+        let mut loaded_time: Option<SystemTime> = modified_time(&model_path);
+        let mut pending_time: Option<SystemTime> = loaded_time;
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let Some(network) = network.upgrade() else {
+                return;
+            };
+            let modified = modified_time(&model_path);
+            if modified.is_some() && modified != loaded_time {
+                if modified == pending_time {
+                    if let Err(e) = network.reload_model(&model_path) {
+                        log::error!("{}, keeping the current model", e);
+                    }
+                    loaded_time = modified;
+                } else {
+                    pending_time = modified;
+                }
+            }
+        })
+    }
+
+    pub fn encoder(&self) -> &Arc<dyn Encoder<Game>> {
+        &self.encoder
+    }
+
+    pub fn policy_map(&self) -> &Arc<dyn PolicyMap<Game>> {
+        &self.policy_map
+    }
+
+    /// Evaluate a position by the network, or None if the output is not finite and the fallback should be used
+    fn evaluate_impl(&self, history: &[Game::Position]) -> Option<Evaluation<Game::Move>> {
+        let moves = history.last().unwrap().legal_moves().collect_vec();
+
+        let mut retries = 0;
+        let mut run_alone = false;
+        let output = loop {
+            let model_generation = self.model_generation.load(Ordering::SeqCst);
+            let output = if run_alone {
+                self.run_history_alone(history.to_vec())
+            } else {
+                self.run_history(history.to_vec())
+            };
+            if is_output_finite(&output, &moves, &*self.policy_map) {
+                break output;
+            }
+
+            self.metrics.lock().unwrap().non_finite_count.increment(1);
+            log::warn!(
+                "Non-finite network output: value {}, wdl {:?}, moves left {:?}",
+                output.value,
+                output.wdl,
+                output.moves_left
+            );
+            self.log_non_finite(history, &output);
+            match self.non_finite_policy {
+                NonFinitePolicy::Retry
+                    if retries < NON_FINITE_MAX_RETRIES
+                        && self.model_generation.load(Ordering::SeqCst) != model_generation =>
+                {
+                    retries += 1;
+                    self.metrics.lock().unwrap().non_finite_retry_count.increment(1);
+                }
+                NonFinitePolicy::Retry if !run_alone => {
+                    run_alone = true;
+                    self.metrics.lock().unwrap().non_finite_retry_count.increment(1);
+                }
+                NonFinitePolicy::Retry | NonFinitePolicy::Uniform => {
+                    self.metrics.lock().unwrap().non_finite_uniform_count.increment(1);
+                    return None;
+                }
+                NonFinitePolicy::Fail => panic!("non-finite network output"),
+            }
+        };
+
+        let mut moves_probs = calc_moves_probs(moves, &output.moves_scores, &*self.policy_map, self.policy_temperature);
+        apply_min_prior(&mut moves_probs, self.min_prior);
+        Some(Evaluation {
+            moves_probs,
+            value: output.value,
+            wdl: output.wdl,
+            moves_left: output.moves_left,
+        })
+    }
+
+    fn run_history(&self, history: Vec<Game::Position>) -> NetOutput {
+        match &self.runner {
+            Runner::Local {
+                model,
+                batcher,
+                batch_deadline,
+                capacity,
+                tuner: None,
+            } => batcher.apply(history, *batch_deadline, |histories| {
+                model.lock().unwrap().run(&*self.encoder, &histories, *capacity)
             }),
+            Runner::Local {
+                model,
+                batcher,
+                capacity,
+                tuner: Some(tuner),
+                ..
+            } => {
+                batcher.set_batch_size(tuner.request_begin());
+                let output = batcher.apply(history, tuner.deadline(), |histories| {
+                    let run_begin = Instant::now();
+                    let outputs = model.lock().unwrap().run(&*self.encoder, &histories, *capacity);
+                    tuner.record_run(histories.len(), run_begin.elapsed());
+                    outputs
+                });
+                tuner.request_end();
+                output
+            }
+            Runner::Server(server) => server.evaluate(history),
+        }
+    }
+
+    /// Run the model on a single history, without batching it with other requests
+    fn run_history_alone(&self, history: Vec<Game::Position>) -> NetOutput {
+        match &self.runner {
+            Runner::Local { model, capacity, .. } => {
+                let mut outputs = model.lock().unwrap().run(&*self.encoder, &[history], *capacity);
+                outputs.pop().unwrap()
+            }
+            Runner::Server(server) => server.evaluate(history),
+        }
+    }
+
+    /// Append the input planes of a non-finite output to the log file, as a JSON line
+    fn log_non_finite(&self, history: &[Game::Position], output: &NetOutput) {
+        let Some(log_file) = &self.non_finite_log else {
+            return;
+        };
+        let plane_size = Game::BOARD_SIZE * Game::BOARD_SIZE;
+        let planes = self
+            .encoder
+            .encode_to_vec(history)
+            .iter()
+            .map(|plane| (0..plane_size).map(|idx| plane.get(idx) as u8).collect_vec())
+            .collect_vec();
+        #[derive(serde::Serialize)]
+        struct NonFiniteEntry<'a> {
+            model: &'a Path,
+            encoder: String,
+            policy_map: String,
+            planes: Vec<Vec<u8>>,
+            value: Option<f32>,
+            wdl: Option<[Option<f32>; 3]>,
+            moves_left: Option<f32>,
+        }
+        /* Non-finite numbers are not valid JSON, and are written as null */
+        let finite = |x: f32| x.is_finite().then_some(x);
+        let model_path = self.model_path.lock().unwrap().clone();
+        let entry = NonFiniteEntry {
+            model: &model_path,
+            encoder: self.encoder.id(),
+            policy_map: self.policy_map.id(),
+            planes,
+            value: finite(output.value),
+            wdl: output
+                .wdl
+                .map(|wdl| [finite(wdl.win), finite(wdl.draw), finite(wdl.loss)]),
+            moves_left: output.moves_left.and_then(finite),
+        };
+        let mut line = serde_json::to_string(&entry).unwrap();
+        line.push('\n');
+        if let Err(e) = log_file.lock().unwrap().write_all(line.as_bytes()) {
+            log::error!("Failed to write non-finite output log: {}", e);
+        }
+    }
+}
+
+/// Check that the value and the scores of the legal moves are finite
+fn is_output_finite<Game: crate::game::Game>(
+    output: &NetOutput,
+    moves: &[Game::Move],
+    policy_map: &dyn PolicyMap<Game>,
+) -> bool {
+    output.value.is_finite()
+        && output
+            .wdl
+            .is_none_or(|wdl| wdl.win.is_finite() && wdl.draw.is_finite() && wdl.loss.is_finite())
+        && output.moves_left.is_none_or(f32::is_finite)
+        && moves
+            .iter()
+            .all(|m| output.moves_scores[policy_map.move_idx(m)].is_finite())
+}
+
+impl<Game: crate::game::Game> ValueFunction<Game> for NNetwork<Game> {
+    fn evaluate(&self, history: &[Game::Position]) -> Evaluation<Game::Move> {
+        /* Only the positions read by the encoder */
+        let history = &history[history.len().saturating_sub(self.history_len())..];
+        let (history, is_flipped) = flip_history_if_needed(history);
+
+        let res = if let Some(cache) = &self.cache {
+            /* The older positions are part of the cache key only through the encoder key */
+            let extra_key = self.encoder.cache_key(&history);
+            let key_history = history[history.len().saturating_sub(self.encoder.history_len())..].to_vec();
+            cache.get_or_compute(key_history, extra_key, |_| self.evaluate_impl(&history))
+        } else {
+            self.evaluate_impl(&history)
+        };
+        /* The fallback of a non-finite output, uniform moves probabilities and a zero value */
+        let res = res.unwrap_or_else(|| {
+            let moves = history.last().unwrap().legal_moves().collect_vec();
+            let p = 1.0 / moves.len() as f32;
+            Evaluation::new(moves.into_iter().map(|m| (m, p)).collect(), 0.0)
+        });
+
+        flip_eval_if_needed(res, is_flipped)
+    }
+
+    fn history_len(&self) -> usize {
+        self.encoder.lookback_len()
+    }
+}
+
+/// The output of a network for a single position
+#[derive(Clone, Debug)]
+pub struct NetOutput {
+    /// Per-move scores, indexed by the [`PolicyMap`] of the model. Not normalized, see [`calc_moves_probs`]
+    pub moves_scores: Vec<f32>,
+    /// The position value in range [-1,1]
+    pub value: f32,
+    /// Win/draw/loss probabilities, if the model has a WDL value head
+    pub wdl: Option<Wdl>,
+    /// The predicted number of plies until the end of the game, if the model has a moves left head
+    pub moves_left: Option<f32>,
+}
+
+/// A model with a reusable input buffer
+struct ModelRunner {
+    model: Model,
+    input: Array4<f32>,
+    metrics: RunMetrics,
+}
+impl ModelRunner {
+    fn new(model: Model) -> Self {
+        Self {
+            model,
+            input: Array4::zeros((0, 0, 0, 0)),
+            metrics: RunMetrics::new(),
         }
     }
 
-    pub fn run_net(&self, input: Array4<f32>) -> Vec<(Vec<f32>, f32)> {
+    fn run<Game: crate::game::Game>(
+        &mut self,
+        encoder: &dyn Encoder<Game>,
+        histories: &[Vec<Game::Position>],
+        batch_size: usize,
+    ) -> Vec<NetOutput> {
+        /* An inference server combines the samples of its clients, and pads the batch itself */
+        let batch_size = if self.model.is_remote() {
+            histories.len()
+        } else {
+            batch_size
+        };
+        let dims = (batch_size, encoder.planes_num(), Game::BOARD_SIZE, Game::BOARD_SIZE);
+        if self.input.dim() != dims {
+            self.input = Array4::zeros(dims);
+        }
+        histories_to_tensor_into(encoder, histories, &mut self.input);
+
         let net_run_begin = Instant::now();
-        let outputs = self.model.lock().unwrap().run([input.into_dyn()].to_vec());
+        let outputs = self.model.run(&[self.input.view().into_dyn()]);
         let run_duration = net_run_begin.elapsed();
 
-        let (moves_scores, vals) = outputs.into_iter().collect_tuple().unwrap();
-        let moves_scores: Array2<f32> = moves_scores.into_dimensionality().unwrap();
+        /* Policy and value outputs, and an optional moves left output */
+        assert!(
+            (2..=3).contains(&outputs.len()),
+            "unexpected outputs num: {}",
+            outputs.len()
+        );
+        let mut outputs = outputs.into_iter();
+        let moves_scores: Array2<f32> = outputs.next().unwrap().into_dimensionality().unwrap();
+        let vals = outputs.next().unwrap();
+        let moves_left: Option<Array2<f32>> = outputs.next().map(|m| m.into_dimensionality().unwrap());
+        /* The value head outputs either a scalar value, or win/draw/loss logits */
         let vals: Array2<f32> = vals.into_dimensionality().unwrap();
+        let is_wdl = match vals.ncols() {
+            1 => false,
+            3 => true,
+            n => panic!("unexpected value output size: {n}"),
+        };
 
-        // let batch_size = vals.len();
         let ret = moves_scores
             .rows()
             .into_iter()
-            .zip(vals)
-            .map(|(sample_scores, val)| {
-                let mut sample_scores = sample_scores.to_vec();
-                for s in sample_scores.iter_mut() {
-                    if !s.is_finite() {
-                        *s = f32::MIN;
-                    }
+            .zip(vals.rows())
+            .take(histories.len())
+            .enumerate()
+            .map(|(idx, (sample_scores, val))| {
+                let moves_scores = sample_scores.to_vec();
+                let (value, wdl) = if is_wdl {
+                    let wdl = wdl_from_logits([val[0], val[1], val[2]]);
+                    (wdl.expected_score(), Some(wdl))
+                } else {
+                    (val[0], None)
+                };
+                NetOutput {
+                    moves_scores,
+                    value,
+                    wdl,
+                    /* max() would hide a NaN */
+                    moves_left: moves_left
+                        .as_ref()
+                        .map(|m| m[(idx, 0)])
+                        .map(|m| if m < 0.0 { 0.0 } else { m }),
                 }
-                (sample_scores, val)
             })
             .collect_vec();
 
         // update metrics
-        let mut metrics = self.metrics.lock().unwrap();
-        metrics.activation_count.increment(1);
-        metrics.run_duration.set(run_duration.as_secs_f64());
+        self.metrics.activation_count.increment(1);
+        self.metrics.run_duration.set(run_duration.as_secs_f64());
 
         ret
     }
+}
 
-    pub fn evaluate(
-        &self,
-        position: &Game::Position,
-        to_planes: impl Fn(&Game::Position) -> Vec<Game::Bitboard>,
-    ) -> (Vec<(Game::Move, f32)>, f32) {
-        let (position, is_flipped) = flip_pos_if_needed(position.clone());
-
-        let res = if let Some(cache) = &self.cache {
-            cache.get_or_compute(&position, |pos| self.evaluate_impl(pos, &to_planes))
-        } else {
-            self.evaluate_impl(&position, &to_planes)
-        };
-
-        flip_score_if_needed(res, is_flipped)
+/// Run a model on the start position, and check that its policy output size matches the policy map
+///
+/// The check is done when a model is loaded, the policy map is assumed to match the model during the search.
+fn check_policy_output<Game: crate::game::Game>(
+    model: Model,
+    encoder: &dyn Encoder<Game>,
+    policy_map: &dyn PolicyMap<Game>,
+    batch_size: usize,
+) -> Result<Model, String> {
+    let mut model = ModelRunner::new(model);
+    let outputs = model.run(encoder, &[vec![Game::Position::new()]], batch_size);
+    let output_size = outputs[0].moves_scores.len();
+    if output_size != policy_map.moves_num() {
+        return Err(format!(
+            "policy output size {} does not match the policy map '{}' of size {}",
+            output_size,
+            policy_map.id(),
+            policy_map.moves_num()
+        ));
     }
-    fn evaluate_impl(
-        &self,
-        pos: &Game::Position,
-        to_planes: &impl Fn(&Game::Position) -> Vec<Game::Bitboard>,
-    ) -> (Vec<(Game::Move, f32)>, f32) {
-        let planes = to_planes(pos);
-
-        let (move_scores, val) = self.batcher.apply(planes, Duration::from_millis(20), |inputs| {
-            self.run_net(planes_to_tensor::<Game>(&inputs, self.batcher.batch_size()))
-        });
+    Ok(model.model)
+}
 
-        let moves = pos.legal_moves().collect_vec();
-        let moves_probs = calc_moves_probs::<Game>(moves, &move_scores);
-        (moves_probs, val)
-    }
+/// Measure the median latency of model runs of a few batch sizes, up to a full batch
+///
+/// A local model is always padded to the full batch, but the encoding of the samples and a remote model, which is
+/// not padded, depend on the number of samples.
+fn calibrate_run_latencies<Game: crate::game::Game>(
+    model: &mut ModelRunner,
+    encoder: &dyn Encoder<Game>,
+    batch_size: usize,
+) -> Vec<(usize, Duration)> {
+    let sizes = [1, batch_size / 4, batch_size / 2, batch_size]
+        .into_iter()
+        .filter(|size| *size > 0)
+        .dedup()
+        .collect_vec();
+    let samples = vec![vec![Game::Position::new()]; batch_size];
+    /* The first run is usually slower */
+    model.run(encoder, &samples, batch_size);
+    sizes
+        .into_iter()
+        .map(|size| {
+            let mut latencies = (0..5)
+                .map(|_| {
+                    let run_begin = Instant::now();
+                    model.run(encoder, &samples[..size], batch_size);
+                    run_begin.elapsed()
+                })
+                .collect_vec();
+            latencies.sort();
+            (size, latencies[latencies.len() / 2])
+        })
+        .collect()
 }
 
+/// Softmax of the legal moves scores, indexed by the policy map, with the given temperature
 pub fn calc_moves_probs<Game: crate::game::Game>(
     moves: Vec<Game::Move>,
     move_scores: &[f32],
+    policy_map: &dyn PolicyMap<Game>,
+    temperature: f32,
 ) -> Vec<(Game::Move, f32)> {
-    let moves_scores = moves.iter().map(|m| move_scores[m.to_nn_idx()]).collect_vec();
+    let moves_scores = moves.iter().map(|m| move_scores[policy_map.move_idx(m)]).collect_vec();
 
     // Softmax normalization
     let max_p = moves_scores.iter().cloned().fold(f32::MIN, f32::max);
-    let scores = moves_scores.into_iter().map(|p| (p - max_p).exp()).collect_vec();
+    let scores = moves_scores
+        .into_iter()
+        .map(|p| ((p - max_p) / temperature).exp())
+        .collect_vec();
     let p_sum: f32 = scores.iter().sum();
     let probs = scores.into_iter().map(|p| p / p_sum).collect_vec();
 
     moves.into_iter().zip(probs).collect_vec()
 }
 
+/// Raise the probabilities below the minimum to it, and normalize the probabilities again
+fn apply_min_prior<Move>(moves_probs: &mut [(Move, f32)], min_prior: f32) {
+    if min_prior <= 0.0 || moves_probs.is_empty() {
+        return;
+    }
+    for (_m, p) in moves_probs.iter_mut() {
+        *p = p.max(min_prior);
+    }
+    let p_sum: f32 = moves_probs.iter().map(|(_m, p)| p).sum();
+    for (_m, p) in moves_probs.iter_mut() {
+        *p /= p_sum;
+    }
+}
+
+fn wdl_from_logits(logits: [f32; 3]) -> Wdl {
+    let max = logits.into_iter().fold(f32::MIN, f32::max);
+    let [w, d, l] = logits.map(|x| (x - max).exp());
+    let sum = w + d + l;
+    Wdl::new(w / sum, d / sum, l / sum)
+}
+
 pub fn planes_to_tensor<Game: crate::game::Game>(samples: &[Vec<Game::Bitboard>], batch_size: usize) -> Array4<f32> {
+    assert!(!samples.is_empty(), "invalid sample len 0, 1..={}", batch_size);
+    let dims = (batch_size, samples[0].len(), Game::BOARD_SIZE, Game::BOARD_SIZE);
+    let mut tensor = Array4::<f32>::zeros(dims);
+    planes_to_tensor_into::<Game>(samples, &mut tensor);
+    tensor
+}
+
+/// Write a batch of samples into an existing tensor of shape (batch_size, planes_num, BOARD_SIZE, BOARD_SIZE)
+///
+/// The tensor is meant to be reused between batches. Entries of the batch beyond the given samples are zeroed.
+pub fn planes_to_tensor_into<Game: crate::game::Game>(samples: &[Vec<Game::Bitboard>], tensor: &mut Array4<f32>) {
+    let (batch_size, planes_num, height, width) = tensor.dim();
     assert!(
         (1..=batch_size).contains(&samples.len()),
         "invalid sample len {}, 1..={}",
         samples.len(),
         batch_size
     );
-    let planes_num = samples[0].len();
-    let dims = (batch_size, planes_num, Game::BOARD_SIZE, Game::BOARD_SIZE);
-    let mut tensor = Array4::<f32>::uninit(dims);
-
-    for (b, sample) in samples.iter().enumerate() {
-        for (c, plane) in sample.iter().enumerate() {
-            for h in 0..(Game::BOARD_SIZE) {
-                for w in 0..(Game::BOARD_SIZE) {
-                    tensor[(b, c, h, w)].write(match plane.get(h * Game::BOARD_SIZE + w) {
-                        true => 1.0,
-                        false => 0.0,
-                    });
-                }
-            }
+    assert_eq!((height, width), (Game::BOARD_SIZE, Game::BOARD_SIZE));
+    let plane_size = Game::BOARD_SIZE * Game::BOARD_SIZE;
+
+    let data = tensor.as_slice_mut().expect("tensor is not in standard layout");
+    let (data, padding) = data.split_at_mut(samples.len() * planes_num * plane_size);
+    for (sample, sample_data) in samples.iter().zip(data.chunks_exact_mut(planes_num * plane_size)) {
+        assert_eq!(sample.len(), planes_num);
+        for (plane, plane_data) in sample.iter().zip(sample_data.chunks_exact_mut(plane_size)) {
+            plane.write_plane(plane_data);
         }
     }
-    for i in samples.len()..batch_size {
-        for c in 0..planes_num {
-            for h in 0..(Game::BOARD_SIZE) {
-                for w in 0..(Game::BOARD_SIZE) {
-                    tensor[(i, c, h, w)].write(0.0);
-                }
-            }
+    padding.fill(0.0);
+}
+
+/// Encode a batch of histories into an existing tensor of shape (batch_size, planes_num, BOARD_SIZE, BOARD_SIZE)
+///
+/// Each history is encoded into a planes buffer shared by all the samples of the batch, and unpacked directly into
+/// its entry of the tensor. Entries of the batch beyond the given histories are zeroed.
+pub fn histories_to_tensor_into<Game: crate::game::Game>(
+    encoder: &dyn Encoder<Game>,
+    histories: &[Vec<Game::Position>],
+    tensor: &mut Array4<f32>,
+) {
+    let (batch_size, planes_num, height, width) = tensor.dim();
+    assert!(
+        (1..=batch_size).contains(&histories.len()),
+        "invalid sample len {}, 1..={}",
+        histories.len(),
+        batch_size
+    );
+    assert_eq!(planes_num, encoder.planes_num());
+    assert_eq!((height, width), (Game::BOARD_SIZE, Game::BOARD_SIZE));
+    let plane_size = Game::BOARD_SIZE * Game::BOARD_SIZE;
+
+    let mut planes = vec![Game::Bitboard::new(); planes_num];
+    let data = tensor.as_slice_mut().expect("tensor is not in standard layout");
+    let (data, padding) = data.split_at_mut(histories.len() * planes_num * plane_size);
+    for (history, sample_data) in histories.iter().zip(data.chunks_exact_mut(planes_num * plane_size)) {
+        encoder.encode(history, &mut planes);
+        for (plane, plane_data) in planes.iter().zip(sample_data.chunks_exact_mut(plane_size)) {
+            plane.write_plane(plane_data);
         }
     }
-
-    // Safety: we wrote to all elements
-    unsafe { tensor.assume_init() }
+    padding.fill(0.0);
 }
 
 pub fn flip_pos_if_needed<Position: crate::game::Position>(pos: Position) -> (Position, bool) {
@@ -163,6 +731,23 @@ pub fn flip_pos_if_needed<Position: crate::game::Position>(pos: Position) -> (Po
     }
 }
 
+/// Flip all the positions if the last position is not of the first player, see [`flip_pos_if_needed`]
+pub fn flip_history_if_needed<Position: crate::game::Position>(history: &[Position]) -> (Vec<Position>, bool) {
+    if history.last().unwrap().turn() == GameColor::Player1 {
+        (history.to_vec(), false)
+    } else {
+        (history.iter().map(|pos| pos.flipped()).collect(), true)
+    }
+}
+
+pub fn flip_eval_if_needed<Move: crate::game::Move>(eval: Evaluation<Move>, pos_flipped: bool) -> Evaluation<Move> {
+    if pos_flipped {
+        eval.flipped()
+    } else {
+        eval
+    }
+}
+
 pub fn flip_score_if_needed<Move: crate::game::Move>(
     net_res: (Vec<(Move, f32)>, f32),
     pos_flipped: bool,
@@ -182,6 +767,286 @@ pub fn flip_score_if_needed<Move: crate::game::Move>(
 }
 
 struct Metrics {
+    reload_count: metrics::Counter,
+    non_finite_count: metrics::Counter,
+    non_finite_retry_count: metrics::Counter,
+    non_finite_uniform_count: metrics::Counter,
+}
+
+struct RunMetrics {
     activation_count: metrics::Counter,
     run_duration: RunningAverage,
 }
+impl RunMetrics {
+    fn new() -> Self {
+        Self {
+            activation_count: metrics::counter!("model.activation_count"),
+            run_duration: RunningAverage::new(0.99, metrics::gauge!("model.run_duration")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn};
+
+    use crate::chess::net::ChessHistoryEncoder;
+    use crate::chess::{ChessGame, ChessPosition};
+    use crate::game::{Game, Position};
+    use crate::mcts::cache::ValueFuncCache;
+    use crate::mcts::value_func::{ValueFunction, Wdl};
+    use crate::net::encoder::{Encoder, FlatPolicyMap};
+    use crate::net::model::{InferenceConfig, Model, RemoteConfig, TractConfig};
+    use crate::net::remote::{RemoteServer, RunFn};
+    use crate::net::{
+        apply_min_prior, calc_moves_probs, is_output_finite, NNetwork, NNetworkParams, NetOutput, NonFinitePolicy,
+    };
+    use crate::ttt::{TttGame, TttMove};
+
+    #[test]
+    fn policy_temperature_and_min_prior() {
+        let moves = (0..3).map(TttMove::from_idx).collect::<Vec<_>>();
+        let scores = [2.0, 1.0, -3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+        let probs = |temperature| {
+            calc_moves_probs::<TttGame>(moves.clone(), &scores, &FlatPolicyMap, temperature)
+                .into_iter()
+                .map(|(_m, p)| p)
+                .collect::<Vec<_>>()
+        };
+        let plain = probs(1.0);
+        let flat = probs(2.0);
+        assert!((plain.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((flat.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        /* A higher temperature moves probability from the best move to the worst */
+        assert!(flat[0] < plain[0]);
+        assert!(flat[2] > plain[2]);
+        /* The order of the moves is kept */
+        assert!(flat[0] > flat[1] && flat[1] > flat[2]);
+
+        let mut moves_probs = calc_moves_probs::<TttGame>(moves.clone(), &scores, &FlatPolicyMap, 1.0);
+        assert!(moves_probs[2].1 < 0.05);
+        apply_min_prior(&mut moves_probs, 0.05);
+        assert!((moves_probs.iter().map(|(_m, p)| p).sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(moves_probs[2].1 > 0.04);
+        assert!(moves_probs[0].1 > moves_probs[1].1);
+    }
+
+    #[test]
+    fn non_finite_output() {
+        let moves = [TttMove::from_idx(0), TttMove::from_idx(1)];
+        let mut output = NetOutput {
+            moves_scores: vec![0.0; 9],
+            value: 0.5,
+            wdl: Some(Wdl::new(0.6, 0.3, 0.1)),
+            moves_left: Some(4.0),
+        };
+        assert!(is_output_finite::<TttGame>(&output, &moves, &FlatPolicyMap));
+
+        /* Scores of illegal moves are ignored */
+        output.moves_scores[5] = f32::NAN;
+        assert!(is_output_finite::<TttGame>(&output, &moves, &FlatPolicyMap));
+
+        output.moves_scores[1] = f32::NEG_INFINITY;
+        assert!(!is_output_finite::<TttGame>(&output, &moves, &FlatPolicyMap));
+        output.moves_scores[1] = 0.0;
+
+        output.wdl = Some(Wdl::new(f32::NAN, 0.3, 0.1));
+        assert!(!is_output_finite::<TttGame>(&output, &moves, &FlatPolicyMap));
+        output.wdl = None;
+
+        output.value = f32::NAN;
+        assert!(!is_output_finite::<TttGame>(&output, &moves, &FlatPolicyMap));
+    }
+
+    #[test]
+    fn load_model_error() {
+        /* Take a free port, nothing listens on it once the listener is dropped */
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let cfg = InferenceConfig::Remote(RemoteConfig { address });
+        assert!(Model::load("", cfg).is_err());
+        assert!(Model::load("nonexistent.onnx", InferenceConfig::OnnxTract(TractConfig::default())).is_err());
+    }
+
+    #[test]
+    fn cache_key_halfmove_clock() {
+        /* A model whose value is the sum of its input planes, served by an in-process inference server */
+        let run_fn = Box::new(|input: ArrayViewD<f32>| {
+            let batch_size = input.shape()[0];
+            let sums = input.sum_axis(Axis(3)).sum_axis(Axis(2)).sum_axis(Axis(1));
+            vec![
+                ArrayD::zeros(IxDyn(&[batch_size, ChessGame::MOVES_NUM])),
+                (sums / 10000.0).insert_axis(Axis(1)),
+            ]
+        });
+        let network = NNetwork::<ChessGame>::new(
+            "",
+            serve_run_fn(run_fn),
+            Arc::new(ChessHistoryEncoder),
+            Arc::new(FlatPolicyMap),
+            NNetworkParams::new(1),
+            Some(Arc::new(ValueFuncCache::new(100))),
+        );
+        /* Equal boards with different fifty-move counters are encoded differently */
+        let pos1 = ChessPosition::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let pos2 = ChessPosition::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 20 11").unwrap();
+        assert!(pos1 == pos2);
+        let value1 = network.evaluate(&[pos1]).value;
+        let value2 = network.evaluate(&[pos2]).value;
+        assert!(value1 != value2);
+        assert_eq!(network.evaluate(&[pos1]).value, value1);
+    }
+
+    #[test]
+    fn non_finite_retry() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let run_fn = {
+            let runs = Arc::clone(&runs);
+            Box::new(move |input: ArrayViewD<f32>| {
+                runs.fetch_add(1, Ordering::Relaxed);
+                let batch_size = input.shape()[0];
+                vec![
+                    ArrayD::zeros(IxDyn(&[batch_size, ChessGame::MOVES_NUM])),
+                    ArrayD::from_elem(IxDyn(&[batch_size, 1]), f32::NAN),
+                ]
+            })
+        };
+        let mut params = NNetworkParams::new(1);
+        params.non_finite_policy = NonFinitePolicy::Retry;
+        let network = NNetwork::<ChessGame>::new(
+            "",
+            serve_run_fn(run_fn),
+            Arc::new(ChessHistoryEncoder),
+            Arc::new(FlatPolicyMap),
+            params,
+            Some(Arc::new(ValueFuncCache::new(100))),
+        );
+        /* The model was not replaced, the position is evaluated once more on its own before the fallback */
+        let load_runs = runs.load(Ordering::Relaxed);
+        let eval = network.evaluate(&[ChessPosition::new()]);
+        assert_eq!(runs.load(Ordering::Relaxed), load_runs + 2);
+        assert_eq!(eval.value, 0.0);
+        assert!(eval.moves_probs.iter().all(|(_m, p)| *p == 1.0 / 20.0));
+
+        /* The fallback evaluation is not cached */
+        network.evaluate(&[ChessPosition::new()]);
+        assert_eq!(runs.load(Ordering::Relaxed), load_runs + 4);
+    }
+
+    #[test]
+    fn policy_output_size() {
+        let run_fn = |moves_num: usize| {
+            Box::new(move |input: ArrayViewD<f32>| {
+                let batch_size = input.shape()[0];
+                vec![
+                    ArrayD::zeros(IxDyn(&[batch_size, moves_num])),
+                    ArrayD::zeros(IxDyn(&[batch_size, 1])),
+                ]
+            })
+        };
+        let network = NNetwork::<ChessGame>::new(
+            "",
+            serve_run_fn(run_fn(ChessGame::MOVES_NUM)),
+            Arc::new(ChessHistoryEncoder),
+            Arc::new(FlatPolicyMap),
+            NNetworkParams::new(1),
+            None,
+        );
+        /* A model of another policy map is rejected, and the current model is kept */
+        let model = Model::new("", serve_run_fn(run_fn(ChessGame::MOVES_NUM + 1)));
+        let err = network.replace_model(model).unwrap_err();
+        assert!(err.contains("policy output size"), "{err}");
+        assert_eq!(network.evaluate(&[ChessPosition::new()]).moves_probs.len(), 20);
+        let model = Model::new("", serve_run_fn(run_fn(ChessGame::MOVES_NUM)));
+        assert!(network.replace_model(model).is_ok());
+    }
+
+    #[test]
+    fn replace_model_cache() {
+        let (runs1, runs2) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let network = NNetwork::<ChessGame>::new(
+            "",
+            serve_run_fn(constant_value_run_fn(0.5, Arc::clone(&runs1))),
+            Arc::new(ChessHistoryEncoder),
+            Arc::new(FlatPolicyMap),
+            NNetworkParams::new(1),
+            Some(Arc::new(ValueFuncCache::new(100))),
+        );
+        let pos = ChessPosition::new();
+        assert_eq!(network.evaluate(&[pos]).value, 0.5);
+        assert_eq!(network.evaluate(&[pos]).value, 0.5);
+        /* A run when the model is loaded, and a single evaluation, the second one is cached */
+        assert_eq!(runs1.load(Ordering::Relaxed), 2);
+
+        let generation = network.model_generation.load(Ordering::SeqCst);
+        let model = Model::new("", serve_run_fn(constant_value_run_fn(-0.5, Arc::clone(&runs2))));
+        network.replace_model(model).unwrap();
+        assert_eq!(network.model_generation.load(Ordering::SeqCst), generation + 1);
+
+        /* The cached value of the old model is dropped, and the new model is used from now on */
+        assert_eq!(network.evaluate(&[pos]).value, -0.5);
+        assert_eq!(network.evaluate(&[pos]).value, -0.5);
+        assert_eq!(runs1.load(Ordering::Relaxed), 2);
+        assert_eq!(runs2.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn reload_model_error() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut network = NNetwork::<ChessGame>::new(
+            "",
+            serve_run_fn(constant_value_run_fn(0.5, Arc::clone(&runs))),
+            Arc::new(ChessHistoryEncoder),
+            Arc::new(FlatPolicyMap),
+            NNetworkParams::new(1),
+            Some(Arc::new(ValueFuncCache::new(100))),
+        );
+        let pos = ChessPosition::new();
+        assert_eq!(network.evaluate(&[pos]).value, 0.5);
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+
+        /* The model file can not be loaded, the current model and its cached evaluations are kept */
+        let generation = network.model_generation.load(Ordering::SeqCst);
+        network.inference_cfg = InferenceConfig::OnnxTract(TractConfig::default());
+        assert!(network.reload_model("nonexistent.onnx").is_err());
+        assert_eq!(network.model_generation.load(Ordering::SeqCst), generation);
+        assert_eq!(*network.model_path.lock().unwrap(), std::path::PathBuf::from(""));
+        assert_eq!(network.evaluate(&[pos]).value, 0.5);
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+    }
+
+    /// A model function with all zero policy and a constant value, counting its runs
+    fn constant_value_run_fn(value: f32, runs: Arc<AtomicUsize>) -> RunFn {
+        Box::new(move |input: ArrayViewD<f32>| {
+            runs.fetch_add(1, Ordering::Relaxed);
+            let batch_size = input.shape()[0];
+            vec![
+                ArrayD::zeros(IxDyn(&[batch_size, ChessGame::MOVES_NUM])),
+                ArrayD::from_elem(IxDyn(&[batch_size, 1]), value),
+            ]
+        })
+    }
+
+    /// Serve a model function of chess history inputs by an in-process inference server, and return the config of a
+    /// remote model using it
+    fn serve_run_fn(run_fn: RunFn) -> InferenceConfig {
+        let sample_shape = vec![
+            ChessHistoryEncoder.planes_num(),
+            ChessGame::BOARD_SIZE,
+            ChessGame::BOARD_SIZE,
+        ];
+        let server = RemoteServer::with_run_fn(run_fn, sample_shape, 1, Duration::ZERO);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || server.serve_tcp(listener));
+        InferenceConfig::Remote(RemoteConfig { address })
+    }
+}
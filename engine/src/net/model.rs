@@ -1,5 +1,7 @@
-use ndarray::ArrayD;
-use std::path::Path;
+use ndarray::{ArrayD, ArrayViewD};
+use std::path::{Path, PathBuf};
+
+use crate::net::remote::{RemoteAddress, RemoteModel};
 
 #[cfg(feature = "torch-python")]
 use {crate::util::python::Unwrapy, pyo3::prelude::*};
@@ -14,21 +16,22 @@ pub enum TorchDevice {
     Mps,
 }
 
-#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Deserialize)]
 #[serde(tag = "engine")]
 #[serde(rename_all = "kebab-case")]
 pub enum InferenceConfig {
-    OnnxOrt,
-    OnnxTract,
+    OnnxOrt(OrtConfig),
+    OnnxTract(TractConfig),
     TorchPy { device: Option<TorchDevice> },
     Executorch,
+    Remote(RemoteConfig),
 }
 impl Default for InferenceConfig {
     fn default() -> Self {
         if cfg!(feature = "onnx-ort") {
-            Self::OnnxOrt
+            Self::OnnxOrt(OrtConfig::default())
         } else if cfg!(feature = "onnx-tract") {
-            Self::OnnxTract
+            Self::OnnxTract(TractConfig::default())
         } else if cfg!(feature = "executorch") {
             Self::Executorch
         } else if cfg!(feature = "torch-python") {
@@ -38,6 +41,126 @@ impl Default for InferenceConfig {
         }
     }
 }
+impl InferenceConfig {
+    /// The default config of each model implementation available in this build
+    pub fn available() -> Vec<Self> {
+        let mut configs = Vec::new();
+        if cfg!(feature = "onnx-ort") {
+            configs.push(Self::OnnxOrt(OrtConfig::default()));
+        }
+        if cfg!(feature = "onnx-tract") {
+            configs.push(Self::OnnxTract(TractConfig::default()));
+        }
+        if cfg!(feature = "executorch") {
+            configs.push(Self::Executorch);
+        }
+        if cfg!(feature = "torch-python") {
+            configs.push(Self::TorchPy { device: None });
+        }
+        configs
+    }
+
+    /// The name of the model implementation, as used in the config `engine` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::OnnxOrt(_) => "onnx-ort",
+            Self::OnnxTract(_) => "onnx-tract",
+            Self::TorchPy { .. } => "torch-py",
+            Self::Executorch => "executorch",
+            Self::Remote(_) => "remote",
+        }
+    }
+
+    /// The extension of the model files loaded by the implementation, None for a remote model
+    pub fn model_file_extension(&self) -> Option<&'static str> {
+        match self {
+            Self::OnnxOrt(_) | Self::OnnxTract(_) => Some("onnx"),
+            Self::TorchPy { .. } => Some("jit"),
+            Self::Executorch => Some("pte"),
+            Self::Remote(_) => None,
+        }
+    }
+}
+
+/// ONNX Runtime session options
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct OrtConfig {
+    /// Execution providers, by order of preference. The CPU provider is always used as a fallback.
+    pub execution_providers: Vec<OrtExecutionProvider>,
+    /// Number of threads used to parallelize the execution within nodes, None for ORT default
+    pub intra_threads: Option<usize>,
+    /// Number of threads used to parallelize the execution of the graph (across nodes), None for ORT default
+    pub inter_threads: Option<usize>,
+    /// Execute independent nodes of the graph in parallel, using the inter-op threads
+    pub parallel_execution: bool,
+    pub optimization_level: OrtOptimizationLevel,
+    /// Memory pattern optimization, pre-allocating memory for the known input shapes
+    pub memory_pattern: bool,
+    /// Use an arena allocator for the CPU execution provider
+    pub cpu_arena_allocator: bool,
+    /// If set, ORT profiling is enabled and the profile is written to a file with this prefix
+    pub profiling: Option<PathBuf>,
+}
+impl Default for OrtConfig {
+    fn default() -> Self {
+        Self {
+            execution_providers: vec![
+                OrtExecutionProvider::TensorRt,
+                OrtExecutionProvider::Cuda,
+                OrtExecutionProvider::CoreMl,
+            ],
+            intra_threads: None,
+            inter_threads: None,
+            parallel_execution: false,
+            optimization_level: OrtOptimizationLevel::Level3,
+            memory_pattern: true,
+            cpu_arena_allocator: true,
+            profiling: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+pub enum OrtExecutionProvider {
+    #[serde(rename = "tensorrt")]
+    TensorRt,
+    #[serde(rename = "cuda")]
+    Cuda,
+    #[serde(rename = "coreml")]
+    CoreMl,
+    #[serde(rename = "cpu")]
+    Cpu,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrtOptimizationLevel {
+    Disable,
+    Level1,
+    Level2,
+    Level3,
+}
+
+/// Tract model options
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct TractConfig {
+    /// Optimize the model graph after loading it
+    pub optimize: bool,
+}
+impl Default for TractConfig {
+    fn default() -> Self {
+        Self { optimize: true }
+    }
+}
+
+/// Inference server options, see [`crate::net::remote::RemoteServer`]
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RemoteConfig {
+    /// The server address, either `unix:<path>` for a Unix domain socket or `<host>:<port>` for TCP
+    pub address: String,
+}
 
 #[allow(clippy::large_enum_variant)]
 enum ModelImpl {
@@ -52,14 +175,26 @@ enum ModelImpl {
         model: ort::session::Session,
         output_names: Vec<String>,
     },
+    Remote(RemoteModel),
 }
 
 pub struct Model {
     model: ModelImpl,
 }
 impl Model {
+    /// Load a model from a file, panicking if it can not be loaded
+    ///
+    /// A remote model is loaded by the inference server, and the path is ignored.
     pub fn new(path: impl AsRef<Path>, cfg: InferenceConfig) -> Self {
+        Self::load(path, cfg).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Load a model from a file
+    ///
+    /// Unlike [`Model::new`], a missing, partially written or invalid model file is reported as an error.
+    pub fn load(path: impl AsRef<Path>, cfg: InferenceConfig) -> Result<Self, String> {
         let path = path.as_ref();
+        let load_err = |e: &dyn std::fmt::Debug| format!("failed to load model {}: {:?}", path.display(), e);
         #[allow(unused)]
         let model = match cfg {
             #[cfg(feature = "torch-python")]
@@ -90,8 +225,8 @@ class Model:
                         TorchDevice::Cuda => "cuda",
                         TorchDevice::Mps => "mps",
                     });
-                    py_class.call1((path, device)).unwrapy(py).into()
-                });
+                    py_class.call1((path, device)).map(Into::into).map_err(|e| load_err(&e))
+                })?;
                 ModelImpl::Py(model)
             }
             #[cfg(feature = "executorch")]
@@ -99,32 +234,77 @@ class Model:
                 let mut model = executorch::module::Module::from_file_path(path);
                 model
                     .load(Some(executorch::program::ProgramVerification::InternalConsistency))
-                    .unwrap();
-                model.load_method("forward", None, None).unwrap();
+                    .map_err(|e| load_err(&e))?;
+                model.load_method("forward", None, None).map_err(|e| load_err(&e))?;
                 ModelImpl::Executorch(model)
             }
             #[cfg(feature = "onnx-tract")]
-            InferenceConfig::OnnxTract => {
-                let model = tract_onnx::onnx()
-                    .model_for_path(path)
-                    .unwrap()
-                    .into_optimized()
-                    .unwrap()
-                    .into_runnable()
-                    .unwrap();
-                ModelImpl::Tract(model)
+            InferenceConfig::OnnxTract(cfg) => {
+                let model = tract_onnx::onnx().model_for_path(path).map_err(|e| load_err(&e))?;
+                let model = if cfg.optimize {
+                    model.into_optimized()
+                } else {
+                    model.into_typed()
+                }
+                .map_err(|e| load_err(&e))?;
+                ModelImpl::Tract(model.into_runnable().map_err(|e| load_err(&e))?)
             }
             #[cfg(feature = "onnx-ort")]
-            InferenceConfig::OnnxOrt => {
-                let model = ort::session::Session::builder()
-                    .unwrap()
-                    .with_optimization_level(ort::session::builder::GraphOptimizationLevel::Level3)
-                    .unwrap()
-                    .commit_from_file(path)
-                    .unwrap();
+            InferenceConfig::OnnxOrt(cfg) => {
+                use ort::execution_providers::*;
+                use ort::session::builder::GraphOptimizationLevel;
+
+                let mut execution_providers = cfg.execution_providers.clone();
+                if !execution_providers.contains(&OrtExecutionProvider::Cpu) {
+                    execution_providers.push(OrtExecutionProvider::Cpu);
+                }
+                let execution_providers = execution_providers
+                    .into_iter()
+                    .map(|provider| match provider {
+                        OrtExecutionProvider::TensorRt => TensorRTExecutionProvider::default().build(),
+                        OrtExecutionProvider::Cuda => CUDAExecutionProvider::default().build(),
+                        OrtExecutionProvider::CoreMl => CoreMLExecutionProvider::default().build(),
+                        OrtExecutionProvider::Cpu => CPUExecutionProvider::default()
+                            .with_arena_allocator(cfg.cpu_arena_allocator)
+                            .build(),
+                    })
+                    .collect::<Vec<_>>();
+                let optimization_level = match cfg.optimization_level {
+                    OrtOptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+                    OrtOptimizationLevel::Level1 => GraphOptimizationLevel::Level1,
+                    OrtOptimizationLevel::Level2 => GraphOptimizationLevel::Level2,
+                    OrtOptimizationLevel::Level3 => GraphOptimizationLevel::Level3,
+                };
+
+                let mut builder = ort::session::Session::builder()
+                    .map_err(|e| load_err(&e))?
+                    .with_execution_providers(execution_providers)
+                    .map_err(|e| load_err(&e))?
+                    .with_optimization_level(optimization_level)
+                    .map_err(|e| load_err(&e))?
+                    .with_parallel_execution(cfg.parallel_execution)
+                    .map_err(|e| load_err(&e))?
+                    .with_memory_pattern(cfg.memory_pattern)
+                    .map_err(|e| load_err(&e))?;
+                if let Some(intra_threads) = cfg.intra_threads {
+                    builder = builder.with_intra_threads(intra_threads).map_err(|e| load_err(&e))?;
+                }
+                if let Some(inter_threads) = cfg.inter_threads {
+                    builder = builder.with_inter_threads(inter_threads).map_err(|e| load_err(&e))?;
+                }
+                if let Some(profiling) = &cfg.profiling {
+                    builder = builder.with_profiling(profiling).map_err(|e| load_err(&e))?;
+                }
+                let model = builder.commit_from_file(path).map_err(|e| load_err(&e))?;
                 let output_names = model.outputs.iter().map(|o| o.name.clone()).collect();
                 ModelImpl::Ort { model, output_names }
             }
+            InferenceConfig::Remote(cfg) => {
+                let address = RemoteAddress::parse(&cfg.address);
+                ModelImpl::Remote(
+                    RemoteModel::connect(&address).map_err(|e| format!("failed to connect to {:?}: {}", address, e))?,
+                )
+            }
             #[cfg(not(all(
                 feature = "torch-python",
                 feature = "executorch",
@@ -132,27 +312,31 @@ class Model:
                 feature = "onnx-ort"
             )))]
             unsupported_type => {
-                let _ = path;
-                panic!(
+                let _ = load_err;
+                return Err(format!(
                     "The requested model implementation is not supported in this build: {:?}",
                     unsupported_type
-                );
+                ));
             }
         };
-        #[allow(unreachable_code)]
-        Self { model }
+        Ok(Self { model })
     }
 
-    pub fn run(&mut self, inputs: Vec<ArrayD<f32>>) -> Vec<ArrayD<f32>> {
+    /// Run the model on borrowed inputs, allowing the caller to reuse its input buffers between runs
+    ///
+    /// ONNX Runtime and remote models read the inputs in place. Tract, ExecuTorch and TorchScript models take
+    /// ownership of their input tensors, so the inputs are copied once per run with these implementations.
+    pub fn run(&mut self, inputs: &[ArrayViewD<f32>]) -> Vec<ArrayD<f32>> {
         match &mut self.model {
             #[cfg(feature = "torch-python")]
             ModelImpl::Py(model) => Python::attach(|py| {
                 use itertools::Itertools;
                 use numpy::{IntoPyArray, PyArrayMethods};
 
+                /* A numpy array passed to Python must own its data */
                 let inputs = inputs
-                    .into_iter()
-                    .map(|input| input.into_pyarray(py))
+                    .iter()
+                    .map(|input| input.to_owned().into_pyarray(py))
                     .collect::<Vec<_>>();
                 let outputs = model.bind(py).call_method1("run", (inputs,)).unwrap();
                 outputs
@@ -164,9 +348,10 @@ class Model:
             }),
             #[cfg(feature = "executorch")]
             ModelImpl::Executorch(model) => {
+                /* The tensors are kept alive by the method inputs, and must own their data */
                 let inputs = inputs
-                    .into_iter()
-                    .map(|input| executorch::tensor::TensorPtr::from_array(input).unwrap())
+                    .iter()
+                    .map(|input| executorch::tensor::TensorPtr::from_array(input.to_owned()).unwrap())
                     .collect::<Vec<_>>();
                 let inputs = inputs.iter().map(executorch::evalue::EValue::from).collect::<Vec<_>>();
                 let outputs = model.forward(&inputs).unwrap();
@@ -177,7 +362,14 @@ class Model:
             }
             #[cfg(feature = "onnx-tract")]
             ModelImpl::Tract(model) => {
-                let inputs = TVec::from_vec(inputs.into_iter().map(Tensor::from).map(TValue::from).collect());
+                /* Tract runs on owned tensor values */
+                let inputs = TVec::from_vec(
+                    inputs
+                        .iter()
+                        .map(|input| Tensor::from(input.to_owned()))
+                        .map(TValue::from)
+                        .collect(),
+                );
                 let outputs = model.run(inputs).unwrap();
                 outputs
                     .into_iter()
@@ -187,8 +379,12 @@ class Model:
             #[cfg(feature = "onnx-ort")]
             ModelImpl::Ort { model, output_names } => {
                 let inputs = inputs
-                    .into_iter()
-                    .map(|input| ort::session::SessionInputValue::from(ort::value::Value::from_array(input).unwrap()))
+                    .iter()
+                    .map(|input| {
+                        ort::session::SessionInputValue::from(
+                            ort::value::TensorRef::from_array_view(input.view()).unwrap(),
+                        )
+                    })
                     .collect::<Vec<_>>();
                 let inputs: &[ort::session::SessionInputValue] = &inputs;
                 let mut outputs = model.run(inputs).unwrap();
@@ -204,16 +400,12 @@ class Model:
                     })
                     .collect::<Vec<_>>()
             }
-            #[cfg(not(any(
-                feature = "torch-python",
-                feature = "executorch",
-                feature = "onnx-tract",
-                feature = "onnx-ort"
-            )))]
-            _ => {
-                let _ = inputs;
-                unreachable!()
-            }
+            ModelImpl::Remote(model) => model.run(inputs),
         }
     }
+
+    /// Whether the model is run by an inference server, which pads the batches itself
+    pub(crate) fn is_remote(&self) -> bool {
+        matches!(self.model, ModelImpl::Remote(_))
+    }
 }
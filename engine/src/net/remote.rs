@@ -0,0 +1,467 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn, Slice};
+
+use crate::net::model::Model;
+use crate::util::metric::RunningAverage;
+
+/// The address of an inference server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoteAddress {
+    /// A Unix domain socket
+    Unix(PathBuf),
+    /// A TCP address, `<host>:<port>`
+    Tcp(String),
+}
+impl RemoteAddress {
+    /// Parse an address, either `unix:<path>` for a Unix domain socket or `<host>:<port>` for TCP
+    pub fn parse(address: &str) -> Self {
+        match address.strip_prefix("unix:") {
+            Some(path) => Self::Unix(PathBuf::from(path)),
+            None => Self::Tcp(address.to_string()),
+        }
+    }
+}
+
+trait Connection: Read + Write + Send {}
+impl<C: Read + Write + Send> Connection for C {}
+
+fn connect(address: &RemoteAddress) -> io::Result<Box<dyn Connection>> {
+    Ok(match address {
+        #[cfg(unix)]
+        RemoteAddress::Unix(path) => Box::new(std::os::unix::net::UnixStream::connect(path)?),
+        #[cfg(not(unix))]
+        RemoteAddress::Unix(_) => panic!("Unix domain sockets are not supported on this platform"),
+        RemoteAddress::Tcp(address) => {
+            let stream = TcpStream::connect(address)?;
+            stream.set_nodelay(true)?;
+            Box::new(stream)
+        }
+    })
+}
+
+/// A model running in an inference server, possibly shared with other processes
+///
+/// Each run sends the inputs to the server and blocks until the outputs are received. The inputs are not padded to
+/// a batch size, the server combines the inputs of all its clients into batches of its own model.
+pub(crate) struct RemoteModel {
+    connection: Box<dyn Connection>,
+}
+impl RemoteModel {
+    pub(crate) fn connect(address: &RemoteAddress) -> io::Result<Self> {
+        Ok(Self {
+            connection: connect(address)?,
+        })
+    }
+
+    pub(crate) fn run(&mut self, inputs: &[ArrayViewD<f32>]) -> Vec<ArrayD<f32>> {
+        write_tensors(&mut self.connection, inputs).expect("failed to send request to inference server");
+        read_tensors(&mut self.connection, usize::MAX).expect("failed to receive response from inference server")
+    }
+}
+
+/* A message is the number of tensors followed by the tensors. A tensor is its number of dimensions, the dimensions
+ * and the data in standard layout. All numbers are little endian u32, and the data is little endian f32. */
+fn write_tensors(w: &mut impl Write, tensors: &[ArrayViewD<f32>]) -> io::Result<()> {
+    let mut buf = Vec::new();
+    buf.extend((tensors.len() as u32).to_le_bytes());
+    for tensor in tensors {
+        buf.extend((tensor.ndim() as u32).to_le_bytes());
+        for dim in tensor.shape() {
+            buf.extend((*dim as u32).to_le_bytes());
+        }
+        for x in tensor.iter() {
+            buf.extend(x.to_le_bytes());
+        }
+    }
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+/// The maximum size of the data of a request received by the server
+const MAX_REQUEST_BYTES: usize = 1 << 28;
+
+/// Read a message, failing if the data of its tensors is larger than `max_bytes`
+fn read_tensors(r: &mut impl Read, max_bytes: usize) -> io::Result<Vec<ArrayD<f32>>> {
+    let read_u32 = |r: &mut dyn Read| -> io::Result<u32> {
+        let mut buf = [0; 4];
+        r.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    };
+    let tensors_num = read_u32(r)?;
+    let mut total_bytes = 0usize;
+    (0..tensors_num)
+        .map(|_| {
+            let ndim = read_u32(r)?;
+            if ndim > 16 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("too many dimensions: {ndim}"),
+                ));
+            }
+            let shape = (0..ndim)
+                .map(|_| read_u32(r).map(|d| d as usize))
+                .try_collect::<_, Vec<_>, _>()?;
+            let bytes_len = shape
+                .iter()
+                .try_fold(4usize, |len, &dim| len.checked_mul(dim))
+                .filter(|&len| len <= max_bytes - total_bytes);
+            let Some(bytes_len) = bytes_len else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("tensor too large: {shape:?}"),
+                ));
+            };
+            total_bytes += bytes_len;
+            let mut bytes = vec![0; bytes_len];
+            r.read_exact(&mut bytes)?;
+            let data = bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect_vec();
+            Ok(ArrayD::from_shape_vec(IxDyn(&shape), data).unwrap())
+        })
+        .collect()
+}
+
+pub(crate) type RunFn = Box<dyn FnMut(ArrayViewD<f32>) -> Vec<ArrayD<f32>> + Send>;
+
+struct Job {
+    input: ArrayD<f32>,
+    respond: mpsc::SyncSender<Vec<ArrayD<f32>>>,
+}
+
+/// An inference server, running a single model for many clients
+///
+/// Each client connection is served by its own thread, which forwards the requests to a batching thread. The
+/// batching thread waits for the first request, and then collects more requests until the batch is full or
+/// `batch_deadline` passed since the first request was received. The requests are concatenated along the batch
+/// dimension, padded to the model batch size, and the outputs are split back to the clients.
+///
+/// The model must have a single input, of shape `sample_shape` except for the batch dimension. A request of another
+/// shape, or larger than [`MAX_REQUEST_BYTES`], closes its connection without affecting the other clients. The server
+/// is usually run by the `inference-server` binary, and used by [`InferenceConfig::Remote`].
+///
+/// [`InferenceConfig::Remote`]: crate::net::model::InferenceConfig::Remote
+pub struct RemoteServer {
+    jobs: mpsc::Sender<Job>,
+    sample_shape: Vec<usize>,
+}
+impl RemoteServer {
+    pub fn new(model: Model, sample_shape: Vec<usize>, batch_size: usize, batch_deadline: Duration) -> Self {
+        let mut model = model;
+        Self::with_run_fn(
+            Box::new(move |input| model.run(&[input])),
+            sample_shape,
+            batch_size,
+            batch_deadline,
+        )
+    }
+
+    pub(crate) fn with_run_fn(
+        run: RunFn,
+        sample_shape: Vec<usize>,
+        batch_size: usize,
+        batch_deadline: Duration,
+    ) -> Self {
+        assert!(batch_size > 0);
+        let (jobs, jobs_rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("inference-batcher".to_string())
+            .spawn(move || {
+                let mut batcher = BatchThread {
+                    run,
+                    jobs: jobs_rx,
+                    batch_size,
+                    batch_deadline,
+                    carry: None,
+                    batch_fill: RunningAverage::new(0.99, metrics::gauge!("model.batch_fill")),
+                };
+                batcher.run();
+            })
+            .unwrap();
+        Self { jobs, sample_shape }
+    }
+
+    /// Listen on the given address and serve clients, never returns unless an error occurs
+    pub fn serve(&self, address: &RemoteAddress) -> io::Result<()> {
+        match address {
+            #[cfg(unix)]
+            RemoteAddress::Unix(path) => {
+                /* Remove the socket file left by a previous server, but never another file at the same path */
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    use std::os::unix::fs::FileTypeExt;
+                    if !metadata.file_type().is_socket() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ));
+                    }
+                    std::fs::remove_file(path)?;
+                }
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                log::info!("Inference server listening on {}", path.display());
+                for connection in listener.incoming() {
+                    self.spawn_connection(connection?);
+                }
+                Ok(())
+            }
+            #[cfg(not(unix))]
+            RemoteAddress::Unix(_) => panic!("Unix domain sockets are not supported on this platform"),
+            RemoteAddress::Tcp(address) => self.serve_tcp(TcpListener::bind(address)?),
+        }
+    }
+
+    pub(crate) fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        log::info!("Inference server listening on {}", listener.local_addr()?);
+        for connection in listener.incoming() {
+            let connection = connection?;
+            connection.set_nodelay(true)?;
+            self.spawn_connection(connection);
+        }
+        Ok(())
+    }
+
+    fn spawn_connection(&self, mut connection: impl Connection + 'static) {
+        let jobs = self.jobs.clone();
+        let sample_shape = self.sample_shape.clone();
+        std::thread::spawn(move || {
+            loop {
+                let input = match read_tensors(&mut connection, MAX_REQUEST_BYTES) {
+                    Ok(inputs) if inputs.len() == 1 => inputs.into_iter().next().unwrap(),
+                    Ok(inputs) => {
+                        log::error!("Expected a single input, got {}", inputs.len());
+                        return;
+                    }
+                    /* The client disconnected */
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return,
+                    Err(e) => {
+                        log::error!("Invalid request: {}", e);
+                        return;
+                    }
+                };
+                /* Invalid requests would fail the batch of all the clients */
+                if input.ndim() == 0 || input.shape()[0] == 0 || input.shape()[1..] != sample_shape[..] {
+                    log::error!(
+                        "Invalid input shape {:?}, expected [batch, {}]",
+                        input.shape(),
+                        sample_shape.iter().join(", ")
+                    );
+                    return;
+                }
+                let (respond, result) = mpsc::sync_channel(1);
+                jobs.send(Job { input, respond }).expect("batching thread terminated");
+                let outputs = result.recv().expect("batching thread terminated");
+                let outputs = outputs.iter().map(|o| o.view()).collect_vec();
+                if write_tensors(&mut connection, &outputs).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+struct BatchThread {
+    run: RunFn,
+    jobs: mpsc::Receiver<Job>,
+    batch_size: usize,
+    batch_deadline: Duration,
+    /// A job that did not fit in the previous batch
+    carry: Option<Job>,
+    batch_fill: RunningAverage,
+}
+impl BatchThread {
+    fn run(&mut self) {
+        let mut batch: Vec<Job> = Vec::new();
+        loop {
+            /* Wait for the first request of the batch */
+            let first = match self.carry.take() {
+                Some(job) => job,
+                None => match self.jobs.recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                },
+            };
+            let mut rows = first.input.shape()[0];
+            batch.push(first);
+
+            /* Collect requests until the batch is full or the deadline is reached */
+            let deadline = Instant::now() + self.batch_deadline;
+            while rows < self.batch_size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                let Ok(job) = self.jobs.recv_timeout(timeout) else {
+                    break;
+                };
+                let same_shape = job.input.shape()[1..] == batch[0].input.shape()[1..];
+                if !same_shape || rows + job.input.shape()[0] > self.batch_size {
+                    self.carry = Some(job);
+                    break;
+                }
+                rows += job.input.shape()[0];
+                batch.push(job);
+            }
+
+            self.run_batch(&mut batch, rows);
+        }
+    }
+
+    fn run_batch(&mut self, batch: &mut Vec<Job>, rows: usize) {
+        let inputs = batch.iter().map(|job| job.input.view()).collect_vec();
+        let input = ndarray::concatenate(Axis(0), &inputs).unwrap();
+
+        /* A single request larger than the batch size is computed in multiple batches */
+        let mut outputs: Vec<Vec<ArrayD<f32>>> = Vec::new();
+        for begin in (0..rows).step_by(self.batch_size) {
+            let end = (begin + self.batch_size).min(rows);
+            let mut shape = input.shape().to_vec();
+            shape[0] = self.batch_size;
+            let mut chunk = ArrayD::zeros(IxDyn(&shape));
+            chunk
+                .slice_axis_mut(Axis(0), Slice::from(0..end - begin))
+                .assign(&input.slice_axis(Axis(0), Slice::from(begin..end)));
+            self.batch_fill.set((end - begin) as f64 / self.batch_size as f64);
+
+            let chunk_outputs = (self.run)(chunk.view());
+            outputs.push(
+                chunk_outputs
+                    .into_iter()
+                    .map(|o| o.slice_axis(Axis(0), Slice::from(0..end - begin)).to_owned())
+                    .collect(),
+            );
+        }
+        let outputs_num = outputs[0].len();
+        let outputs = (0..outputs_num)
+            .map(|idx| {
+                let parts = outputs.iter().map(|o| o[idx].view()).collect_vec();
+                ndarray::concatenate(Axis(0), &parts).unwrap()
+            })
+            .collect_vec();
+
+        let mut begin = 0;
+        for job in batch.drain(..) {
+            let end = begin + job.input.shape()[0];
+            let job_outputs = outputs
+                .iter()
+                .map(|o| o.slice_axis(Axis(0), Slice::from(begin..end)).to_owned())
+                .collect();
+            /* Ignore clients that are gone */
+            let _ = job.respond.send(job_outputs);
+            begin = end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use ndarray::{ArrayD, Axis, IxDyn};
+
+    use crate::net::remote::{read_tensors, write_tensors, RemoteAddress, RemoteModel, RemoteServer};
+
+    #[test]
+    fn remote_batching() {
+        let batch_size = 8;
+        let runs = Arc::new(AtomicUsize::new(0));
+        let run_fn = {
+            let runs = Arc::clone(&runs);
+            Box::new(move |input: ndarray::ArrayViewD<f32>| {
+                assert_eq!(input.shape()[0], batch_size);
+                runs.fetch_add(1, Ordering::Relaxed);
+                /* The sum of each sample, and the sample first entry */
+                let sums = input.sum_axis(Axis(2)).sum_axis(Axis(1));
+                let firsts = input.index_axis(Axis(1), 0).index_axis(Axis(1), 0).to_owned();
+                vec![sums.insert_axis(Axis(1)), firsts.insert_axis(Axis(1))]
+            })
+        };
+        let server = RemoteServer::with_run_fn(run_fn, vec![2, 3], batch_size, Duration::from_millis(50));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = RemoteAddress::parse(&listener.local_addr().unwrap().to_string());
+        std::thread::spawn(move || server.serve_tcp(listener));
+
+        let clients = (0..4)
+            .map(|client| {
+                let address = address.clone();
+                std::thread::spawn(move || {
+                    let mut model = RemoteModel::connect(&address).unwrap();
+                    for rows in [3, 1, 12] {
+                        let input = ArrayD::from_shape_fn(IxDyn(&[rows, 2, 3]), |idx| {
+                            (client * 100 + idx[0] * 10 + idx[1] * 3 + idx[2]) as f32
+                        });
+                        let outputs = model.run(&[input.view()]);
+                        assert_eq!(outputs.len(), 2);
+                        for row in 0..rows {
+                            let sample = input.index_axis(Axis(0), row);
+                            assert_eq!(outputs[0].shape(), &[rows, 1]);
+                            assert_eq!(outputs[0][[row, 0]], sample.sum());
+                            assert_eq!(outputs[1][[row, 0]], sample[[0, 0]]);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for client in clients {
+            client.join().unwrap();
+        }
+        /* The requests of multiple clients are computed together */
+        assert!(runs.load(Ordering::Relaxed) < 4 * (1 + 1 + 2));
+
+        assert_eq!(
+            RemoteAddress::parse("unix:/tmp/cattus.sock"),
+            RemoteAddress::Unix("/tmp/cattus.sock".into())
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let run_fn = Box::new(|input: ndarray::ArrayViewD<f32>| vec![input.sum_axis(Axis(1))]);
+        let server = RemoteServer::with_run_fn(run_fn, vec![2], 4, Duration::ZERO);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve_tcp(listener));
+
+        let scalar = ArrayD::zeros(IxDyn(&[]));
+        let no_rows = ArrayD::zeros(IxDyn(&[0, 2]));
+        let wrong_shape = ArrayD::zeros(IxDyn(&[1, 3]));
+        for input in [scalar, no_rows, wrong_shape] {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write_tensors(&mut stream, &[input.view()]).unwrap();
+            /* The connection is closed without a response */
+            assert!(read_tensors(&mut stream, usize::MAX).is_err());
+        }
+        /* A tensor of u32::MAX x u32::MAX values, without its data */
+        let mut stream = TcpStream::connect(address).unwrap();
+        let header = [1u32, 2, u32::MAX, u32::MAX];
+        stream
+            .write_all(&header.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>())
+            .unwrap();
+        assert!(read_tensors(&mut stream, usize::MAX).is_err());
+
+        /* The other clients are still served */
+        let mut model = RemoteModel::connect(&RemoteAddress::Tcp(address.to_string())).unwrap();
+        let input = ArrayD::from_shape_vec(IxDyn(&[2, 2]), vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let outputs = model.run(&[input.view()]);
+        assert_eq!(outputs[0].as_slice().unwrap(), &[3.0, 7.0]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serve_existing_file() {
+        let server = RemoteServer::with_run_fn(Box::new(|_input| Vec::new()), vec![1], 1, Duration::ZERO);
+        let path = std::env::temp_dir().join(format!("cattus-remote-test-{}", std::process::id()));
+        std::fs::write(&path, "not a socket").unwrap();
+        /* A file that is not a socket is never removed */
+        assert!(server.serve(&RemoteAddress::Unix(path.clone())).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
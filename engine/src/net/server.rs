@@ -0,0 +1,297 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::net::encoder::Encoder;
+use crate::net::model::Model;
+use crate::net::{ModelRunner, NetOutput};
+use crate::util::metric::RunningAverage;
+
+enum Message<Game: crate::game::Game> {
+    Evaluate {
+        history: Vec<Game::Position>,
+        respond: mpsc::SyncSender<NetOutput>,
+    },
+    ReplaceModel(Model),
+}
+
+/// An inference service owning a model on a dedicated thread
+///
+/// Positions histories are sent to the inference thread over a queue, and are encoded by it directly into the batch
+/// input. The thread waits for the first request, and then collects more requests until the batch is full or
+/// `max_latency` passed since the first request was received. The results are returned to each requester through its
+/// own channel. The search threads never run the model themselves, they only wait for their own result.
+pub struct InferenceServer<Game: crate::game::Game> {
+    requests: Option<mpsc::Sender<Message<Game>>>,
+    queue_depth: Arc<AtomicUsize>,
+    batch_size: usize,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<Game: crate::game::Game + 'static> InferenceServer<Game> {
+    pub fn new(model: Model, encoder: Arc<dyn Encoder<Game>>, batch_size: usize, max_latency: Duration) -> Self {
+        assert!(batch_size > 0);
+        let (requests, requests_rx) = mpsc::channel();
+        let queue_depth = Arc::new(AtomicUsize::new(0));
+
+        let thread = {
+            let queue_depth = Arc::clone(&queue_depth);
+            std::thread::Builder::new()
+                .name("inference-server".to_string())
+                .spawn(move || {
+                    let mut server = ServerThread::<Game> {
+                        runner: ModelRunner::new(model),
+                        encoder,
+                        requests: requests_rx,
+                        batch_size,
+                        max_latency,
+                        queue_depth,
+                        metrics: ServerMetrics::new(),
+                    };
+                    server.run();
+                })
+                .unwrap()
+        };
+
+        Self {
+            requests: Some(requests),
+            queue_depth,
+            batch_size,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<Game: crate::game::Game> InferenceServer<Game> {
+    /// Evaluate a positions history, blocking until the batch containing it is computed
+    pub fn evaluate(&self, history: Vec<Game::Position>) -> NetOutput {
+        let (respond, result) = mpsc::sync_channel(1);
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        self.requests
+            .as_ref()
+            .unwrap()
+            .send(Message::Evaluate { history, respond })
+            .expect("inference thread terminated");
+        result.recv().expect("inference thread terminated")
+    }
+
+    /// Replace the model used by the inference thread
+    ///
+    /// Requests that are already queued might be computed by either the old or the new model.
+    pub fn replace_model(&self, model: Model) {
+        self.requests
+            .as_ref()
+            .unwrap()
+            .send(Message::ReplaceModel(model))
+            .expect("inference thread terminated");
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+impl<Game: crate::game::Game> Drop for InferenceServer<Game> {
+    fn drop(&mut self) {
+        /* Closing the queue terminates the inference thread */
+        drop(self.requests.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct ServerThread<Game: crate::game::Game> {
+    runner: ModelRunner,
+    encoder: Arc<dyn Encoder<Game>>,
+    requests: mpsc::Receiver<Message<Game>>,
+    batch_size: usize,
+    max_latency: Duration,
+    queue_depth: Arc<AtomicUsize>,
+    metrics: ServerMetrics,
+}
+
+impl<Game: crate::game::Game> ServerThread<Game> {
+    fn run(&mut self) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        loop {
+            /* Wait for the first request of the batch */
+            while batch.is_empty() {
+                let Ok(message) = self.requests.recv() else {
+                    return;
+                };
+                self.handle_message(message, &mut batch);
+            }
+
+            /* Collect requests until the batch is full or the latency limit is reached */
+            let deadline = Instant::now() + self.max_latency;
+            while batch.len() < self.batch_size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match self.requests.recv_timeout(timeout) {
+                    Ok(message) => self.handle_message(message, &mut batch),
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            self.run_batch(&mut batch);
+        }
+    }
+
+    fn handle_message(&mut self, message: Message<Game>, batch: &mut Vec<Request<Game>>) {
+        match message {
+            Message::Evaluate { history, respond } => {
+                let queue_depth = self.queue_depth.fetch_sub(1, Ordering::Relaxed) - 1;
+                self.metrics.queue_depth.set(queue_depth as f64);
+                batch.push((history, respond));
+            }
+            Message::ReplaceModel(model) => self.runner.model = model,
+        }
+    }
+
+    fn run_batch(&mut self, batch: &mut Vec<Request<Game>>) {
+        let (inputs, responders): (Vec<_>, Vec<_>) = batch.drain(..).unzip();
+        self.metrics
+            .batch_fill
+            .set(inputs.len() as f64 / self.batch_size as f64);
+
+        let outputs = self.runner.run(&*self.encoder, &inputs, self.batch_size);
+        for (output, respond) in outputs.into_iter().zip(responders) {
+            /* Ignore requesters that are gone */
+            let _ = respond.send(output);
+        }
+    }
+}
+
+type Request<Game> = (Vec<<Game as crate::game::Game>::Position>, mpsc::SyncSender<NetOutput>);
+
+struct ServerMetrics {
+    queue_depth: metrics::Gauge,
+    batch_fill: RunningAverage,
+}
+impl ServerMetrics {
+    fn new() -> Self {
+        Self {
+            queue_depth: metrics::gauge!("model.queue_depth"),
+            batch_fill: RunningAverage::new(0.99, metrics::gauge!("model.batch_fill")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use ndarray::Axis;
+
+    use crate::game::{Game, Position};
+    use crate::net::model::{InferenceConfig, Model, RemoteConfig};
+    use crate::net::remote::RemoteServer;
+    use crate::net::server::InferenceServer;
+    use crate::ttt::net::TttBaseEncoder;
+    use crate::ttt::{TttGame, TttMove, TttPosition};
+
+    /// A model served by a stub inference server, recording the number of samples of each run
+    ///
+    /// The value of a sample is the sum of its planes multiplied by `scale`, and the policy is all zeros.
+    fn stub_model(runs: Arc<Mutex<Vec<usize>>>, scale: f32) -> Model {
+        let run_fn = Box::new(move |input: ndarray::ArrayViewD<f32>| {
+            let sums = input.sum_axis(Axis(3)).sum_axis(Axis(2)).sum_axis(Axis(1));
+            /* The stub server pads the batch with zero samples, a real sample always has the ones plane */
+            runs.lock().unwrap().push(sums.iter().filter(|s| **s > 0.0).count());
+            let policy = ndarray::ArrayD::zeros(ndarray::IxDyn(&[input.shape()[0], TttGame::BOARD_SIZE.pow(2)]));
+            vec![policy, (sums * scale).insert_axis(Axis(1)).into_dyn()]
+        });
+        let sample_shape = vec![TttBaseEncoder::PLANES_NUM, TttGame::BOARD_SIZE, TttGame::BOARD_SIZE];
+        let server = RemoteServer::with_run_fn(run_fn, sample_shape, 64, Duration::ZERO);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || server.serve_tcp(listener));
+        Model::new("", InferenceConfig::Remote(RemoteConfig { address }))
+    }
+
+    /// Histories of the positions after 0, 1, 2, ... moves, the value of the k-th one is `9 + k` by the stub model
+    fn histories(num: usize) -> Vec<Vec<TttPosition>> {
+        let mut game = TttGame::new();
+        for idx in 0..num - 1 {
+            game.play_single_turn(TttMove::from_idx(idx));
+        }
+        (0..num).map(|k| game.pos_history()[..=k].to_vec()).collect()
+    }
+
+    fn new_server(model: Model, batch_size: usize, max_latency: Duration) -> InferenceServer<TttGame> {
+        InferenceServer::new(model, Arc::new(TttBaseEncoder), batch_size, max_latency)
+    }
+
+    #[test]
+    fn flush_full_batch() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let server = new_server(stub_model(Arc::clone(&runs), 1.0), 4, Duration::from_secs(60));
+
+        /* The batch is computed as soon as it is full, long before the latency limit */
+        let begin = Instant::now();
+        std::thread::scope(|s| {
+            let requesters = histories(4)
+                .into_iter()
+                .enumerate()
+                .map(|(k, history)| {
+                    let server = &server;
+                    s.spawn(move || (k, server.evaluate(history)))
+                })
+                .collect::<Vec<_>>();
+            /* Each requester receives the output of its own sample */
+            for requester in requesters {
+                let (k, output) = requester.join().unwrap();
+                assert_eq!(output.value, (9 + k) as f32);
+                assert_eq!(output.moves_scores.len(), 9);
+            }
+        });
+        assert!(begin.elapsed() < Duration::from_secs(30));
+        assert_eq!(*runs.lock().unwrap(), [4]);
+    }
+
+    #[test]
+    fn flush_on_latency() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let max_latency = Duration::from_millis(50);
+        let server = new_server(stub_model(Arc::clone(&runs), 1.0), 4, max_latency);
+
+        /* A partial batch is computed once the latency limit passed */
+        let begin = Instant::now();
+        let output = server.evaluate(histories(3).pop().unwrap());
+        assert!(begin.elapsed() >= max_latency);
+        assert_eq!(output.value, 11.0);
+        assert_eq!(*runs.lock().unwrap(), [1]);
+    }
+
+    #[test]
+    fn replace_model() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let server = new_server(stub_model(Arc::clone(&runs), 1.0), 1, Duration::ZERO);
+        let history = vec![TttPosition::new()];
+        assert_eq!(server.evaluate(history.clone()).value, 9.0);
+
+        /* Requests sent after the replacement are computed by the new model */
+        server.replace_model(stub_model(Arc::clone(&runs), -1.0));
+        assert_eq!(server.evaluate(history.clone()).value, -9.0);
+        assert_eq!(server.evaluate(history).value, -9.0);
+        assert_eq!(*runs.lock().unwrap(), [1, 1, 1]);
+    }
+
+    #[test]
+    fn shutdown_on_drop() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let encoder = Arc::new(TttBaseEncoder);
+        let server =
+            InferenceServer::<TttGame>::new(stub_model(Arc::clone(&runs), 1.0), encoder.clone(), 1, Duration::ZERO);
+        server.evaluate(vec![TttPosition::new()]);
+
+        /* Dropping the server joins the inference thread, which releases the encoder */
+        assert_eq!(Arc::strong_count(&encoder), 2);
+        drop(server);
+        assert_eq!(Arc::strong_count(&encoder), 1);
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Interval between two updates of the tuned batch size and deadline
+const UPDATE_INTERVAL: Duration = Duration::from_millis(200);
+/// The smallest deadline chosen by the tuner
+const MIN_DEADLINE: Duration = Duration::from_micros(100);
+
+/// Automatic choice of the effective batch size and deadline of a network batcher
+///
+/// The model is always run with a tensor of its own batch size (the capacity), padded if needed, so the cost of a
+/// run does not depend on the number of samples in it. The tuner chooses when a batch is computed:
+/// - The effective batch size is the number of requests that were pending concurrently during the last interval,
+///   bounded by the capacity. Waiting for more requests than there are searching threads only adds latency, as
+///   all the threads are blocked on the batch.
+/// - The deadline is twice the expected time to fill a batch at the observed arrival rate, bounded by the run
+///   latency of a batch of the effective size and the configured deadline. Waiting longer than a model run costs
+///   more than running a partial batch and another one later.
+///
+/// The run latency of a few batch sizes is calibrated at startup, as the encoding of the samples and remote models
+/// depend on the number of samples, and is updated by the observed runs.
+pub(crate) struct BatchTuner {
+    capacity: usize,
+    max_deadline: Duration,
+    state: Mutex<TunerState>,
+    deadline_ns: AtomicU64,
+    clock: Box<dyn Fn() -> Instant + Send + Sync>,
+    metrics: TunerMetrics,
+}
+
+struct TunerState {
+    batch_size: usize,
+    /// Average model run latency of each calibrated batch size, in seconds, sorted by the batch size
+    run_latencies: Vec<(usize, f64)>,
+    window_start: Instant,
+    arrivals: u64,
+    pending: usize,
+    max_pending: usize,
+}
+
+impl BatchTuner {
+    /// Create a tuner given the calibrated run latencies of a few batch sizes, which must include the capacity
+    pub(crate) fn new(capacity: usize, max_deadline: Duration, run_latencies: &[(usize, Duration)]) -> Self {
+        Self::with_clock(capacity, max_deadline, run_latencies, Box::new(Instant::now))
+    }
+
+    fn with_clock(
+        capacity: usize,
+        max_deadline: Duration,
+        run_latencies: &[(usize, Duration)],
+        clock: Box<dyn Fn() -> Instant + Send + Sync>,
+    ) -> Self {
+        assert!(capacity > 0);
+        let mut run_latencies = run_latencies
+            .iter()
+            .map(|(batch_size, latency)| (*batch_size, latency.as_secs_f64()))
+            .collect::<Vec<_>>();
+        run_latencies.sort_by_key(|(batch_size, _latency)| *batch_size);
+        assert!(
+            run_latencies
+                .last()
+                .is_some_and(|(batch_size, _latency)| *batch_size == capacity),
+            "the run latency of a full batch is required"
+        );
+        let full_run_latency = Duration::from_secs_f64(run_latencies.last().unwrap().1);
+        let tuner = Self {
+            capacity,
+            max_deadline,
+            state: Mutex::new(TunerState {
+                batch_size: capacity,
+                run_latencies,
+                window_start: clock(),
+                arrivals: 0,
+                pending: 0,
+                max_pending: 0,
+            }),
+            deadline_ns: AtomicU64::new(max_deadline.min(full_run_latency).as_nanos() as u64),
+            clock,
+            metrics: TunerMetrics::new(),
+        };
+        tuner.metrics.batch_size.set(capacity as f64);
+        tuner.metrics.deadline.set(tuner.deadline().as_secs_f64());
+        tuner.metrics.run_latency.set(full_run_latency.as_secs_f64());
+        tuner
+    }
+
+    /// Record the arrival of a request, and return the effective batch size
+    pub(crate) fn request_begin(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.arrivals += 1;
+        state.pending += 1;
+        state.max_pending = state.max_pending.max(state.pending);
+
+        let now = (self.clock)();
+        let elapsed = now.saturating_duration_since(state.window_start);
+        if elapsed >= UPDATE_INTERVAL {
+            self.update(&mut state, now, elapsed);
+        }
+        state.batch_size
+    }
+
+    pub(crate) fn request_end(&self) {
+        self.state.lock().unwrap().pending -= 1;
+    }
+
+    /// Record the duration of a model run of the given number of samples
+    pub(crate) fn record_run(&self, samples: usize, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let idx = TunerState::latency_idx(&state.run_latencies, samples);
+        let run_latency = &mut state.run_latencies[idx].1;
+        *run_latency = 0.9 * *run_latency + 0.1 * duration.as_secs_f64();
+    }
+
+    pub(crate) fn deadline(&self) -> Duration {
+        Duration::from_nanos(self.deadline_ns.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, state: &mut TunerState, now: Instant, elapsed: Duration) {
+        let arrival_rate = state.arrivals as f64 / elapsed.as_secs_f64();
+        let batch_size = state.max_pending.clamp(1, self.capacity);
+
+        let fill_time = Duration::from_secs_f64(2.0 * batch_size as f64 / arrival_rate);
+        let run_latency = state.run_latencies[TunerState::latency_idx(&state.run_latencies, batch_size)].1;
+        let max_deadline = self.max_deadline.min(Duration::from_secs_f64(run_latency));
+        let deadline = fill_time.min(max_deadline).max(MIN_DEADLINE.min(max_deadline));
+
+        if batch_size != state.batch_size {
+            log::debug!("Batch size tuned from {} to {}", state.batch_size, batch_size);
+        }
+        state.batch_size = batch_size;
+        self.deadline_ns.store(deadline.as_nanos() as u64, Ordering::Relaxed);
+
+        self.metrics.batch_size.set(batch_size as f64);
+        self.metrics.deadline.set(deadline.as_secs_f64());
+        self.metrics.arrival_rate.set(arrival_rate);
+        self.metrics.run_latency.set(run_latency);
+
+        state.window_start = now;
+        state.arrivals = 0;
+        state.max_pending = state.pending;
+    }
+}
+
+impl TunerState {
+    /// The index of the smallest calibrated batch size that fits the given number of samples
+    fn latency_idx(run_latencies: &[(usize, f64)], samples: usize) -> usize {
+        run_latencies
+            .iter()
+            .position(|(batch_size, _latency)| *batch_size >= samples)
+            .unwrap_or(run_latencies.len() - 1)
+    }
+}
+
+struct TunerMetrics {
+    batch_size: metrics::Gauge,
+    deadline: metrics::Gauge,
+    arrival_rate: metrics::Gauge,
+    run_latency: metrics::Gauge,
+}
+impl TunerMetrics {
+    fn new() -> Self {
+        Self {
+            batch_size: metrics::gauge!("model.tuned_batch_size"),
+            deadline: metrics::gauge!("model.tuned_batch_deadline"),
+            arrival_rate: metrics::gauge!("model.arrival_rate"),
+            run_latency: metrics::gauge!("model.tuned_run_latency"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::net::tune::{BatchTuner, UPDATE_INTERVAL};
+
+    #[test]
+    fn batch_tuner() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let clock = {
+            let now = Arc::clone(&now);
+            Box::new(move || *now.lock().unwrap())
+        };
+        let advance = |d: Duration| *now.lock().unwrap() += d;
+        let run_latencies = [
+            (1, Duration::from_millis(2)),
+            (16, Duration::from_millis(3)),
+            (64, Duration::from_millis(5)),
+        ];
+        let tuner = BatchTuner::with_clock(64, Duration::from_millis(20), &run_latencies, clock);
+        assert_eq!(tuner.request_begin(), 64);
+        tuner.request_end();
+        assert_close(tuner.deadline(), Duration::from_millis(5));
+
+        /* Three concurrent requesters, the batch size is reduced to three */
+        for _ in 0..3 {
+            tuner.request_begin();
+        }
+        for _ in 0..3 {
+            tuner.request_end();
+        }
+        advance(UPDATE_INTERVAL);
+        assert_eq!(tuner.request_begin(), 3);
+        tuner.request_end();
+        /* Few arrivals, the deadline is bounded by the latency of a batch of three */
+        assert_close(tuner.deadline(), Duration::from_millis(3));
+
+        /* No update before the interval passed */
+        for _ in 0..10 {
+            tuner.request_begin();
+        }
+        for _ in 0..10 {
+            tuner.request_end();
+        }
+        assert_eq!(tuner.request_begin(), 3);
+        tuner.request_end();
+
+        /* Many concurrent requesters, bounded by the capacity */
+        for _ in 0..100 {
+            tuner.request_begin();
+        }
+        for _ in 0..100 {
+            tuner.request_end();
+        }
+        advance(UPDATE_INTERVAL);
+        assert_eq!(tuner.request_begin(), 64);
+        tuner.request_end();
+        assert_close(tuner.deadline(), Duration::from_millis(5));
+    }
+
+    fn assert_close(d1: Duration, d2: Duration) {
+        assert!(d1.abs_diff(d2) < Duration::from_micros(1), "{:?} != {:?}", d1, d2);
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::{self, Display};
 
-use crate::game::{Bitboard, Game, GameColor, GameStatus, Move, Position};
+use crate::game::{unpack_bits, Bitboard, Game, GameColor, GameStatus, Move, Position};
 
 pub fn color_to_str(c: Option<GameColor>) -> String {
     match c {
@@ -93,6 +93,11 @@ impl Bitboard for TttBitboard {
             self.bitmap &= !(1u16 << idx);
         }
     }
+
+    fn write_plane(&self, out: &mut [f32]) {
+        assert_eq!(out.len(), TttGame::BOARD_SIZE * TttGame::BOARD_SIZE);
+        unpack_bits(self.bitmap as u64, out);
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
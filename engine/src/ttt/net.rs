@@ -1,24 +1,42 @@
-use crate::game::{Bitboard, Game};
-use crate::mcts::value_func::ValueFunction;
-use crate::net::NNetwork;
+use std::sync::Arc;
+
+use crate::game::Bitboard;
+use crate::net::encoder::{EncodedGame, Encoder};
 use crate::ttt::{TttBitboard, TttGame, TttPosition};
 
-impl ValueFunction<TttGame> for NNetwork<TttGame> {
-    fn evaluate(&self, position: &TttPosition) -> (Vec<(<TttGame as Game>::Move, f32)>, f32) {
-        self.evaluate(position, position_to_planes)
+impl EncodedGame for TttGame {
+    fn encoders() -> Vec<Arc<dyn Encoder<Self>>> {
+        vec![Arc::new(TttBaseEncoder)]
     }
 }
 
-pub const PLANES_NUM: usize = 3;
+/// Pieces of both players and a plane of ones
+pub struct TttBaseEncoder;
+impl TttBaseEncoder {
+    pub const PLANES_NUM: usize = 3;
+}
+impl Encoder<TttGame> for TttBaseEncoder {
+    fn name(&self) -> &'static str {
+        "base"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn planes_num(&self) -> usize {
+        Self::PLANES_NUM
+    }
 
-pub fn position_to_planes(pos: &TttPosition) -> Vec<TttBitboard> {
-    let planes: [_; PLANES_NUM] = [
-        // x pieces plane
-        pos.pieces_x(),
-        // o pieces plane
-        pos.pieces_o(),
-        // a plane with all ones to help NN find board edges
-        TttBitboard::full(true),
-    ];
-    planes.to_vec()
+    fn encode(&self, history: &[TttPosition], planes: &mut [TttBitboard]) {
+        let pos = history.last().unwrap();
+        planes.clone_from_slice(&[
+            // x pieces plane
+            pos.pieces_x(),
+            // o pieces plane
+            pos.pieces_o(),
+            // a plane with all ones to help NN find board edges
+            TttBitboard::full(true),
+        ]);
+    }
 }
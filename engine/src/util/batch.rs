@@ -1,4 +1,4 @@
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -35,19 +35,20 @@ impl<I, O> Batch<I, O> {
 
 pub(crate) struct Batcher<I, O> {
     next_batch: Mutex<Arc<Batch<I, O>>>,
-    batch_size: usize,
+    batch_size: AtomicUsize,
 }
 
 impl<I, O> Batcher<I, O> {
     pub fn new(batch_size: usize) -> Self {
         Self {
             next_batch: Mutex::new(Arc::new(Batch::new())),
-            batch_size,
+            batch_size: AtomicUsize::new(batch_size),
         }
     }
 
     pub fn apply(&self, input: I, deadline: Duration, apply_impl: impl FnOnce(Vec<I>) -> Vec<O>) -> O {
-        if self.batch_size <= 1 {
+        let batch_size = self.batch_size();
+        if batch_size <= 1 {
             let outputs = apply_impl(vec![input]);
             let [output] = outputs.try_into().map_err(|_| unreachable!()).unwrap();
             return output;
@@ -64,7 +65,7 @@ impl<I, O> Batcher<I, O> {
                 if let BatchInner::Collect(batch_samples) = &mut *batch {
                     let input_idx = batch_samples.len();
                     batch_samples.push(input);
-                    if batch_samples.len() < self.batch_size {
+                    if batch_samples.len() < batch_size {
                         drop(batch);
                         break (batch_ptr, input_idx);
                     }
@@ -177,6 +178,14 @@ impl<I, O> Batcher<I, O> {
     }
 
     pub fn batch_size(&self) -> usize {
-        self.batch_size
+        self.batch_size.load(Ordering::Relaxed)
+    }
+
+    /// Change the number of samples that triggers the computation of a batch
+    ///
+    /// A batch that is already collecting samples is computed when it reaches the new size.
+    pub fn set_batch_size(&self, batch_size: usize) {
+        assert!(batch_size > 0);
+        self.batch_size.store(batch_size, Ordering::Relaxed);
     }
 }
@@ -86,13 +86,7 @@ pub fn init_globals() {
 
     #[cfg(feature = "onnx-ort")]
     {
-        ort::init()
-            .with_execution_providers(vec![
-                ort::execution_providers::TensorRTExecutionProvider::default().build(),
-                ort::execution_providers::CUDAExecutionProvider::default().build(),
-                ort::execution_providers::CoreMLExecutionProvider::default().build(),
-            ])
-            .commit()
-            .unwrap();
+        /* Execution providers are configured per session, see OrtConfig */
+        ort::init().commit().unwrap();
     }
 }
@@ -1,9 +1,10 @@
 from pathlib import Path
+from typing import Optional
 
 import numpy as np
 import torch.nn as nn
-from chess import BISHOP, KNIGHT, QUEEN, ROOK, Move, square_file
-from construct import Array, Float32l, Int8sl, Int8ul, Int64ul, Struct
+from chess import BISHOP, KNIGHT, QUEEN, ROOK, SQUARES, Move, square, square_file, square_rank
+from construct import Array, Float32l, Int8sl, Int8ul, Int16ul, Int64ul, Struct
 
 from cattus_train import net_utils
 from cattus_train.trainable_game import DataEntry, DataEntryParseError, Game
@@ -16,15 +17,35 @@ class NetType:
 
 class Chess(Game):
     BOARD_SIZE = 8
-    PLANES_NUM = 18
-    MOVE_NUM = 1880
+    ENCODERS = {"base-v1": 18, "history-v1": 124}
+    POLICY_MAPS = ["flat-v1", "alphazero-v1"]
 
-    ENTRY_FORMAT = Struct(
-        "planes" / Array(PLANES_NUM, Int64ul),
-        "moves_bitmap" / Array(235, Int8ul),
-        "probs" / Array(225, Float32l),
-        "winner" / Int8sl,
-    )
+    def __init__(self, encoder: Optional[str] = None, policy_map: Optional[str] = None):
+        self._init_encoder(encoder)
+        self._init_policy_map(policy_map)
+        # The moves of the policy indices, None for indices of no move
+        self.NN_INDEX_TO_MOVE: list[Optional[Move]]
+        if self.POLICY_MAP == "flat-v1":
+            self.MOVE_NUM = 1880
+            self.NN_INDEX_TO_MOVE = NN_INDEX_TO_MOVE
+        else:
+            self.MOVE_NUM = ALPHAZERO_PLANES_NUM * 64
+            self.NN_INDEX_TO_MOVE = ALPHAZERO_NN_INDEX_TO_MOVE
+        # Indices of the planes used by the data augmentation
+        if self.ENCODER == "base-v1":
+            self.PAWNS_PLANES = [0, 6]
+            self.CASTLING_PLANES = [12, 13, 14, 15]
+        else:
+            # 8 positions of 14 planes (12 pieces and 2 repetitions), followed by 4 castling planes
+            self.PAWNS_PLANES = [p * 14 + c for p in range(8) for c in [0, 6]]
+            self.CASTLING_PLANES = [112, 113, 114, 115]
+        self.ENTRY_FORMAT = Struct(
+            "planes" / Array(self.PLANES_NUM, Int64ul),
+            "moves_bitmap" / Array((self.MOVE_NUM + 7) // 8, Int8ul),
+            "probs" / Array(225, Float32l),
+            "winner" / Int8sl,
+            "moves_left" / Int16ul,
+        )
 
     def load_data_entry(self, path: Path) -> DataEntry:
         with open(path, "rb") as f:
@@ -38,6 +59,7 @@ class Chess(Game):
         moves_bitmap = np.array(entry.moves_bitmap, dtype=np.uint8)
         probs = np.array(entry.probs, dtype=np.float32)
         winner = float(entry.winner)
+        moves_left = float(entry.moves_left)
 
         probs_all = np.full((self.MOVE_NUM,), -1.0, dtype=np.float32)
         move_indices = np.where(np.unpackbits(moves_bitmap, count=self.MOVE_NUM, bitorder="little"))[0]
@@ -46,7 +68,13 @@ class Chess(Game):
 
         assert len(planes) == self.PLANES_NUM
         assert len(probs) == self.MOVE_NUM
-        return DataEntry(planes=planes, probs=probs, winner=winner)
+        return DataEntry(planes=planes, probs=probs, winner=winner, moves_left=moves_left)
+
+    def move_to_nn_idx(self, m: Move) -> int:
+        if self.POLICY_MAP == "flat-v1":
+            return int(MOVE_TO_NN_INDEX[chess_move_to_idx(m)])
+        else:
+            return alphazero_move_to_nn_idx(m)
 
     def _get_input_shape(self):
         return (1, self.PLANES_NUM, self.BOARD_SIZE, self.BOARD_SIZE)
@@ -59,6 +87,8 @@ class Chess(Game):
             value_head_conv_output_channels_num=cfg["value_head_conv_output_channels_num"],
             policy_head_conv_output_channels_num=cfg["policy_head_conv_output_channels_num"],
             moves_num=self.MOVE_NUM,
+            wdl=cfg.get("wdl", False),
+            moves_left=cfg.get("moves_left", False),
         )
 
     def create_model(self, net_type: str, cfg: dict) -> nn.Module:
@@ -245,3 +275,49 @@ def _create_move_to_nn_index() -> np.ndarray:
 
 
 MOVE_TO_NN_INDEX = _create_move_to_nn_index()
+
+
+# The AlphaZero policy, 73 planes of 8x8 indexed by plane * 64 + source square. Must match the Rust engine.
+# The first 56 planes are queen-like moves by 8 directions and 7 distances, the next 8 planes are knight moves and the
+# last 9 planes are underpromotions to a knight, bishop or rook, capturing left, moving forward or capturing right.
+# Promotions to a queen are queen-like moves.
+ALPHAZERO_PLANES_NUM = 73
+_ALPHAZERO_DIRECTIONS = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)]
+_ALPHAZERO_KNIGHT_MOVES = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)]
+_ALPHAZERO_UNDERPROMOTIONS = [KNIGHT, BISHOP, ROOK]
+
+
+def alphazero_move_to_nn_idx(m: Move) -> int:
+    df = square_file(m.to_square) - square_file(m.from_square)
+    dr = square_rank(m.to_square) - square_rank(m.from_square)
+    if m.promotion is not None and m.promotion != QUEEN:
+        plane = 64 + (df + 1) * 3 + _ALPHAZERO_UNDERPROMOTIONS.index(m.promotion)
+    elif (df, dr) in _ALPHAZERO_KNIGHT_MOVES:
+        plane = 56 + _ALPHAZERO_KNIGHT_MOVES.index((df, dr))
+    else:
+        step = ((df > 0) - (df < 0), (dr > 0) - (dr < 0))
+        plane = _ALPHAZERO_DIRECTIONS.index(step) * 7 + max(abs(df), abs(dr)) - 1
+    return plane * 64 + m.from_square
+
+
+def _create_alphazero_nn_index_to_move() -> list[Optional[Move]]:
+    # Queen-like moves are without a promotion piece, including promotions to a queen
+    nnidx2move: list[Optional[Move]] = [None] * (ALPHAZERO_PLANES_NUM * 64)
+    for src in SQUARES:
+        for dst in SQUARES:
+            df = square_file(dst) - square_file(src)
+            dr = square_rank(dst) - square_rank(src)
+            if src != dst and (df == 0 or dr == 0 or abs(df) == abs(dr) or (df, dr) in _ALPHAZERO_KNIGHT_MOVES):
+                m = Move(src, dst)
+                nnidx2move[alphazero_move_to_nn_idx(m)] = m
+        if square_rank(src) == 6:
+            for df in [-1, 0, 1]:
+                if not 0 <= square_file(src) + df < 8:
+                    continue
+                for piece in _ALPHAZERO_UNDERPROMOTIONS:
+                    m = Move(src, square(square_file(src) + df, 7), piece)
+                    nnidx2move[alphazero_move_to_nn_idx(m)] = m
+    return nnidx2move
+
+
+ALPHAZERO_NN_INDEX_TO_MOVE = _create_alphazero_nn_index_to_move()
@@ -9,6 +9,10 @@ from pydantic.dataclasses import Field, dataclass
 class ModelConfig:
     type: str
     base: Path | str = "[none]"
+    # The encoder of positions to the model input planes, the game default encoder if not set
+    encoder: Optional[str] = None
+    # The map of moves to the model policy output, the game default policy map if not set
+    policy_map: Optional[str] = None
 
 
 @dataclass(config={"extra": "forbid"}, kw_only=True)
@@ -19,6 +23,8 @@ class MctsConfig:
     prior_noise_alpha: float
     prior_noise_epsilon: float
     cache_size: int = 0
+    # A directory of tablebases (Syzygy for chess), used by the search and to adjudicate self-play games
+    tablebase_path: Optional[str] = None
 
 
 @dataclass(config={"extra": "forbid"}, kw_only=True)
@@ -35,11 +41,24 @@ class TorchPyConfig:
 @dataclass(config={"extra": "forbid"}, kw_only=True)
 class OnnxTractConfig:
     engine: Literal["onnx-tract"] = "onnx-tract"
+    optimize: bool = True
 
 
 @dataclass(config={"extra": "forbid"}, kw_only=True)
 class OnnxOrtConfig:
     engine: Literal["onnx-ort"] = "onnx-ort"
+    # By order of preference, the CPU provider is always used as a fallback
+    execution_providers: list[Literal["tensorrt", "cuda", "coreml", "cpu"]] = Field(
+        default_factory=lambda: ["tensorrt", "cuda", "coreml"]
+    )
+    intra_threads: Optional[int] = None
+    inter_threads: Optional[int] = None
+    parallel_execution: bool = False
+    optimization_level: Literal["disable", "level1", "level2", "level3"] = "level3"
+    memory_pattern: bool = True
+    cpu_arena_allocator: bool = True
+    # If set, ORT profiling is enabled and written to a file with this prefix
+    profiling: Optional[str] = None
 
 
 InferenceConfig = ExecutorchConfig | TorchPyConfig | OnnxTractConfig | OnnxOrtConfig
@@ -49,6 +68,45 @@ InferenceConfig = ExecutorchConfig | TorchPyConfig | OnnxTractConfig | OnnxOrtCo
 class EngineModelConfig:
     batch_size: int
     inference: InferenceConfig = Field(discriminator="engine", default=None)
+    # The encoder id, set by the training process from the model config
+    encoder: Optional[str] = None
+    # The policy map id, set by the training process from the model config
+    policy_map: Optional[str] = None
+    # If set, the self-play engine reloads the model when its file is modified, polling at this interval
+    watch_interval_ms: Optional[int] = None
+    # Maximum time a position waits for its batch to fill, defaults to 20ms
+    batch_deadline_ms: Optional[int] = None
+    # Run each model on a dedicated inference thread instead of on the search threads
+    inference_server: bool = False
+    # Softmax temperature of the policy, defaults to 1. A temperature greater than 1 flattens the moves probabilities
+    policy_temperature: Optional[float] = None
+    # Minimum probability of each legal move, defaults to 0
+    min_prior: Optional[float] = None
+    # Choose the batch size and deadline automatically, using batch_size and batch_deadline_ms as upper bounds.
+    # Not supported with inference_server.
+    auto_batch: bool = False
+    # What to do when the network outputs non-finite values, defaults to "uniform"
+    non_finite_policy: Optional[Literal["retry", "uniform", "fail"]] = None
+    # If set, the inputs that produced non-finite outputs are appended to this file, as JSON lines
+    non_finite_log: Optional[str] = None
+
+    def __post_init__(self):
+        if self.auto_batch and self.inference_server:
+            raise ValueError("auto_batch is not supported with inference_server")
+
+
+@dataclass(config={"extra": "forbid"}, kw_only=True)
+class BookConfig:
+    # A Polyglot book file
+    path: str
+    # Book moves are played only in the first plies of the game
+    max_depth: int = 20
+    weighting: Literal["best", "weighted", "uniform"] = "weighted"
+
+
+@dataclass(config={"extra": "forbid"}, kw_only=True)
+class BookStartPositions:
+    book: BookConfig
 
 
 @dataclass(config={"extra": "forbid"}, kw_only=True)
@@ -56,6 +114,9 @@ class EngineConfig:
     mcts: MctsConfig
     model: EngineModelConfig
     threads: int
+    # Game specific start positions of the self-play games, "chess960" for random Chess960 positions or
+    # {"book": {...}} for random openings of a Polyglot book
+    start_positions: Literal["chess960"] | BookStartPositions | None = None
 
     def copy_with_overrides(self, overrides: dict[str, Any]) -> "EngineConfig":
         data = dataclasses.asdict(self)
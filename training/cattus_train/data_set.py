@@ -22,7 +22,7 @@ class DataSet(IterableDataset):
         self._cfg = cfg
         self._device: torch.device = device
 
-    def __iter__(self) -> Iterator[tuple[Tensor, tuple[Tensor, Tensor]]]:
+    def __iter__(self) -> Iterator[tuple[Tensor, tuple[Tensor, Tensor, Tensor]]]:
         for filename in self._data_entries_filenames_gen():
             try:
                 packed_entry = self._game.load_data_entry(filename)
@@ -42,7 +42,8 @@ class DataSet(IterableDataset):
             planes = to_tensor(entry.planes).float().to(self._device)
             probs = to_tensor(entry.probs).float().to(self._device)
             winner = torch.tensor(entry.winner, dtype=torch.float32, device=self._device)
-            yield planes, (probs, winner)
+            moves_left = torch.tensor(entry.moves_left, dtype=torch.float32, device=self._device)
+            yield planes, (probs, winner, moves_left)
 
     def _data_entries_filenames_gen(self) -> Iterator[Path]:
         filenames = [p for p in self._train_data_dir.rglob("*.traindata")]
@@ -70,7 +71,9 @@ class DataSet(IterableDataset):
         planes = [np.frombuffer(plane, dtype=np.uint8) for plane in packed_entry.planes]
         planes = np.array([np.unpackbits(plane, count=plane_size, bitorder="little") for plane in planes])
         planes = planes.reshape((game.PLANES_NUM, game.BOARD_SIZE, game.BOARD_SIZE))
-        return DataEntry(planes=planes, probs=packed_entry.probs, winner=packed_entry.winner)
+        return DataEntry(
+            planes=planes, probs=packed_entry.probs, winner=packed_entry.winner, moves_left=packed_entry.moves_left
+        )
 
     def transform(self, entry: DataEntry):
         match self._game:
@@ -136,23 +139,25 @@ class DataSet(IterableDataset):
         def probs_as_list(probs) -> list[tuple[chess.Move, float]]:
             if isinstance(probs, list):
                 return probs
-            from cattus_train.chess import NN_INDEX_TO_MOVE
-
             assert isinstance(probs, np.ndarray)
-            return list(zip(NN_INDEX_TO_MOVE, probs))
+            # Copies of the legal moves, as the moves are modified by the transforms
+            return [
+                (chess.Move(m.from_square, m.to_square, m.promotion), p)
+                for m, p in zip(self._game.NN_INDEX_TO_MOVE, probs)
+                if m is not None and p >= 0
+            ]
 
         def probs_as_array(probs) -> np.ndarray:
             if isinstance(probs, np.ndarray):
                 return probs
-            from cattus_train.chess import MOVE_TO_NN_INDEX, chess_move_to_idx
-
             assert isinstance(probs, list)
-            arr = np.zeros(len(probs), dtype=np.float32)
+            # Use -1 for illegal moves
+            arr = np.full(self._game.MOVE_NUM, -1.0, dtype=np.float32)
             for move, p in probs:
-                arr[MOVE_TO_NN_INDEX[chess_move_to_idx(move)]] = p
+                arr[self._game.move_to_nn_idx(move)] = p
             return arr
 
-        ### Planes
+        ### Planes of the base-v1 encoder, see Chess for the indices of other encoders
         # 0 - white pawns
         # 1 - white knights
         # 2 - white bishops
@@ -171,8 +176,8 @@ class DataSet(IterableDataset):
         # 15 - black can castle queenside
         # 16 - en passant square
         # 17 - all ones
-        has_castle_rights = planes[12, 0, 0] or planes[13, 0, 0] or planes[14, 0, 0] or planes[15, 0, 0]
-        has_pawns = planes[0].any() or planes[6].any()
+        has_castle_rights = any(planes[p, 0, 0] for p in self._game.CASTLING_PLANES)
+        has_pawns = any(planes[p].any() for p in self._game.PAWNS_PLANES)
 
         # Use all combination of the basic transforms:
         # - original
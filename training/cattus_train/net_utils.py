@@ -51,6 +51,8 @@ class ConvNetV1(nn.Module):
         value_head_conv_output_channels_num: int,
         policy_head_conv_output_channels_num: int,
         moves_num: int,
+        wdl: bool = False,
+        moves_left: bool = False,
     ):
         super().__init__()
         B, C, H, W = input_shape
@@ -64,14 +66,14 @@ class ConvNetV1(nn.Module):
             *[ResidualBlock(residual_filter_num) for _ in range(residual_block_num)]
         )  # B, RC, H, W
 
-        # Value head
+        # Value head, either a scalar in range [-1,1] or win/draw/loss logits
+        value_output = [nn.Linear(128, 3)] if wdl else [nn.Linear(128, 1), nn.Tanh()]  # B, 3 or B, 1
         self._value_head = nn.Sequential(
             ConvBlock(residual_filter_num, 1, value_head_conv_output_channels_num),  # B, VHC, H, W
             nn.Flatten(),  # B, VHC * H * W
             nn.Linear(value_head_conv_output_channels_num * H * W, 128),  # B, 128
             nn.ReLU(),
-            nn.Linear(128, 1),  # B, 1
-            nn.Tanh(),
+            *value_output,
         )
 
         # Policy head
@@ -81,11 +83,27 @@ class ConvNetV1(nn.Module):
             nn.Linear(policy_head_conv_output_channels_num * H * W, moves_num),
         )
 
+        # Moves left head, the predicted number of plies until the end of the game
+        self._moves_left_head = (
+            nn.Sequential(
+                ConvBlock(residual_filter_num, 1, value_head_conv_output_channels_num),  # B, VHC, H, W
+                nn.Flatten(),  # B, VHC * H * W
+                nn.Linear(value_head_conv_output_channels_num * H * W, 128),  # B, 128
+                nn.ReLU(),
+                nn.Linear(128, 1),  # B, 1
+                nn.ReLU(),
+            )
+            if moves_left
+            else None
+        )
+
     def forward(self, input):
         flow = self._conv1(input)
         flow = self._residual_blocks(flow)
         value = self._value_head(flow)
         policy = self._policy_head(flow)
+        if self._moves_left_head is not None:
+            return policy, value, self._moves_left_head(flow)
         return policy, value
 
 
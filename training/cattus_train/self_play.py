@@ -144,7 +144,7 @@ def _export_model_impl(
                     model_path,
                     verbose=False,
                     input_names=["planes"],
-                    output_names=["policy", "value"],
+                    output_names=["policy", "value", "moves_left"][: len(model(sample_input))],
                 )
         case _:
             raise ValueError(f"Unsupported inference engine: {cfg}")
@@ -1,8 +1,9 @@
 from pathlib import Path
+from typing import Optional
 
 import numpy as np
 import torch.nn as nn
-from construct import Array, Float32l, Int8sl, Int64ul, Struct
+from construct import Array, Float32l, Int8sl, Int16ul, Int64ul, Struct
 
 from cattus_train import net_utils
 from cattus_train.trainable_game import DataEntry, DataEntryParseError, Game
@@ -15,14 +16,18 @@ class TtoNetType:
 
 class TicTacToe(Game):
     BOARD_SIZE = 3
-    PLANES_NUM = 3
     MOVE_NUM = BOARD_SIZE * BOARD_SIZE
+    ENCODERS = {"base-v1": 3}
 
-    ENTRY_FORMAT = Struct(
-        "planes" / Array(PLANES_NUM, Int64ul),
-        "probs" / Array(MOVE_NUM, Float32l),
-        "winner" / Int8sl,
-    )
+    def __init__(self, encoder: Optional[str] = None, policy_map: Optional[str] = None):
+        self._init_encoder(encoder)
+        self._init_policy_map(policy_map)
+        self.ENTRY_FORMAT = Struct(
+            "planes" / Array(self.PLANES_NUM, Int64ul),
+            "probs" / Array(self.MOVE_NUM, Float32l),
+            "winner" / Int8sl,
+            "moves_left" / Int16ul,
+        )
 
     def load_data_entry(self, path: Path) -> DataEntry:
         with open(path, "rb") as f:
@@ -35,10 +40,11 @@ class TicTacToe(Game):
         planes = np.array(entry.planes, dtype=np.uint64)
         probs = np.array(entry.probs, dtype=np.float32)
         winner = float(entry.winner)
+        moves_left = float(entry.moves_left)
 
         assert len(planes) == self.PLANES_NUM
         assert len(probs) == self.MOVE_NUM
-        return DataEntry(planes=planes, probs=probs, winner=winner)
+        return DataEntry(planes=planes, probs=probs, winner=winner, moves_left=moves_left)
 
     def _get_input_shape(self):
         return (1, self.PLANES_NUM, self.BOARD_SIZE, self.BOARD_SIZE)
@@ -51,6 +57,8 @@ class TicTacToe(Game):
             value_head_conv_output_channels_num=cfg["value_head_conv_output_channels_num"],
             policy_head_conv_output_channels_num=cfg["policy_head_conv_output_channels_num"],
             moves_num=self.MOVE_NUM,
+            wdl=cfg.get("wdl", False),
+            moves_left=cfg.get("moves_left", False),
         )
 
     def create_model(self, net_type: str, cfg: dict) -> nn.Module:
@@ -30,6 +30,7 @@ from cattus_train.trainable_game import Game
 CATTUS_TOP = Path(__file__).parent.parent.parent.resolve()
 CATTUS_TRAIN_TOP = CATTUS_TOP / "training"
 SELF_PLAY_CRATE_DIR = CATTUS_TRAIN_TOP / "self-play"
+MOVES_LEFT_LOSS_WEIGHT = 0.01
 
 
 class TrainProcess:
@@ -55,14 +56,18 @@ class TrainProcess:
 
         self._game: Game
         if cfg.game == "tictactoe":
-            self._game = TicTacToe()
+            self._game = TicTacToe(cfg.model.encoder, cfg.model.policy_map)
         elif re.match("hex[0-9]+", cfg.game):
             size = int(re.findall("hex([0-9]+)", cfg.game)[0])
-            self._game = Hex(size)
+            self._game = Hex(size, cfg.model.encoder, cfg.model.policy_map)
         elif cfg.game == "chess":
-            self._game = Chess()
+            self._game = Chess(cfg.model.encoder, cfg.model.policy_map)
         else:
             raise ValueError("Unknown game argument in config file.")
+        self._self_play_engine_cfg.model.encoder = self._game.ENCODER
+        self._model_compare_engine_cfg.model.encoder = self._game.ENCODER
+        self._self_play_engine_cfg.model.policy_map = self._game.POLICY_MAP
+        self._model_compare_engine_cfg.model.policy_map = self._game.POLICY_MAP
         self.temp_dir_ = tempfile.TemporaryDirectory()
         self.temp_dir = Path(self.temp_dir_.name)
         self._self_play_exec_path: Path = self.temp_dir / "bin" / "self_play"
@@ -216,11 +221,22 @@ class TrainProcess:
                     return F.cross_entropy(output, target)
 
                 def loss_fn(outputs, targets):
-                    policy_output, value_output = outputs
-                    policy_target, value_target = targets
+                    policy_output, value_output = outputs[:2]
+                    policy_target, value_target, moves_left_target = targets
                     policy_loss = loss_cross_entropy(policy_output, policy_target)
-                    value_loss = F.mse_loss(value_output.squeeze(), value_target)
-                    return policy_loss + value_loss
+                    if value_output.shape[1] == 3:
+                        # Win/draw/loss logits, the target winner 1/0/-1 is the class 0/1/2
+                        wdl_target = (1 - value_target.round()).long()
+                        value_loss = F.cross_entropy(value_output, wdl_target)
+                    else:
+                        value_loss = F.mse_loss(value_output.squeeze(), value_target)
+                    loss = policy_loss + value_loss
+                    if len(outputs) > 2:
+                        # The moves left are in the order of tens of plies, scale the loss to the other heads
+                        moves_left_output = outputs[2]
+                        moves_left_loss = F.huber_loss(moves_left_output.squeeze(1), moves_left_target, delta=10.0)
+                        loss = loss + MOVES_LEFT_LOSS_WEIGHT * moves_left_loss
+                    return loss
 
                 model.train()
                 model.to(self.cfg.training.device)
@@ -244,14 +260,18 @@ class TrainProcess:
                     return (predicted == target).float().mean()
 
                 def value_head_accuracy(output, target):
+                    if output.shape[1] == 3:
+                        # Expected score of the win/draw/loss probabilities
+                        wdl = F.softmax(output, dim=1)
+                        output = wdl[:, 0] - wdl[:, 2]
                     # Both the target and output should be in range [-1,1]
-                    return 1 - torch.abs(target - output).mean() / 2
+                    return 1 - torch.abs(target - output.squeeze()).mean() / 2
 
                 with torch.no_grad():
                     model.eval()
                     final_x, final_y = final_batch
                     final_x = final_x.to("cpu")
-                    final_y = (final_y[0].to("cpu"), final_y[1].to("cpu"))
+                    final_y = tuple(y.to("cpu") for y in final_y)
                     final_outputs = model(final_x)
                     losses[m_idx] = loss_fn(final_outputs, final_y).detach().item()
                     policy_accuracies[m_idx] = policy_head_accuracy(final_outputs[0], final_y[0]).detach().item()
@@ -1,6 +1,7 @@
 from abc import ABC, abstractmethod
 from dataclasses import dataclass
 from pathlib import Path
+from typing import Optional
 
 import numpy as np
 import torch.nn as nn
@@ -11,11 +12,34 @@ class DataEntry:
     planes: np.ndarray
     probs: np.ndarray
     winner: float
+    # The number of plies played from the entry position until the end of the game
+    moves_left: float
 
 
 class Game(ABC):
     PLANES_NUM: int
     BOARD_SIZE: int
+    # Encoder ids to their number of planes, the first is the default. Must match the encoders of the Rust engine.
+    ENCODERS: dict[str, int]
+    ENCODER: str
+    # Policy map ids, the first is the default. Must match the policy maps of the Rust engine.
+    POLICY_MAPS: list[str] = ["flat-v1"]
+    POLICY_MAP: str
+
+    def _init_encoder(self, encoder: Optional[str]):
+        if encoder is None:
+            encoder = next(iter(self.ENCODERS))
+        if encoder not in self.ENCODERS:
+            raise ValueError(f"Unknown encoder: {encoder}")
+        self.ENCODER = encoder
+        self.PLANES_NUM = self.ENCODERS[encoder]
+
+    def _init_policy_map(self, policy_map: Optional[str]):
+        if policy_map is None:
+            policy_map = self.POLICY_MAPS[0]
+        if policy_map not in self.POLICY_MAPS:
+            raise ValueError(f"Unknown policy map: {policy_map}")
+        self.POLICY_MAP = policy_map
 
     @abstractmethod
     def create_model(self, net_type: str, cfg: dict) -> nn.Module: ...
@@ -0,0 +1,250 @@
+use cattus::chess::ChessGame;
+use cattus::hex::HexGame;
+use cattus::mcts::value_func::ValueFunction;
+use cattus::net::encoder::{EncodedGame, Encoder};
+use cattus::net::model::{InferenceConfig, Model};
+use cattus::net::{flip_history_if_needed, planes_to_tensor, NNetwork, NNetworkParams};
+use cattus::ttt::TttGame;
+use cattus_self_play::test_util::random_histories;
+use clap::Parser;
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Benchmark the model implementations available in this build
+///
+/// For each implementation and batch size, measures the latency and throughput of a single `Model::run`, and the
+/// throughput of `NNetwork::evaluate` with multiple concurrent threads sharing the network batches.
+#[derive(Parser, Debug)]
+#[clap(about, long_about = None)]
+struct Args {
+    #[clap(long)]
+    game: String,
+    /// The model path without an extension. Each implementation loads the model file with its own extension,
+    /// '.onnx', '.pte' or '.jit', and implementations whose model file does not exist are skipped.
+    /// Models are usually exported with a fixed batch size, a '{batch_size}' in the path is replaced by the batch
+    /// size.
+    #[clap(long)]
+    model_path: String,
+    #[clap(long, value_delimiter = ',', default_value = "1,8,32,128")]
+    batch_sizes: Vec<usize>,
+    /// Number of concurrent threads calling `NNetwork::evaluate`, an empty list skips the evaluate benchmark
+    #[clap(long, value_delimiter = ',', default_value = "1,4,16,64")]
+    threads: Vec<usize>,
+    /// Run the model on a dedicated inference thread in the evaluate benchmark
+    #[clap(long)]
+    inference_server: bool,
+    /// Duration of each measurement
+    #[clap(long, default_value = "3000")]
+    duration_ms: u64,
+    /// The encoder id, the game default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
+    /// The policy map id, the game default policy map if not set
+    #[clap(long)]
+    policy_map: Option<String>,
+    /// Write the results as JSON to this file
+    #[clap(long)]
+    outfile: Option<PathBuf>,
+}
+
+#[derive(serde::Serialize)]
+struct RunResult {
+    engine: &'static str,
+    batch_size: usize,
+    runs: usize,
+    latency_mean_ms: f64,
+    latency_p50_ms: f64,
+    latency_p99_ms: f64,
+    positions_per_sec: f64,
+}
+
+#[derive(serde::Serialize)]
+struct EvaluateResult {
+    engine: &'static str,
+    batch_size: usize,
+    threads: usize,
+    evaluations: u64,
+    evaluations_per_sec: f64,
+}
+
+#[derive(Default, serde::Serialize)]
+struct BenchResults {
+    model_run: Vec<RunResult>,
+    evaluate: Vec<EvaluateResult>,
+}
+
+fn main() -> std::io::Result<()> {
+    cattus::util::init_globals();
+
+    let args = Args::parse();
+
+    let results = match args.game.as_str() {
+        "tictactoe" => bench::<TttGame>(&args),
+        "hex4" => bench::<HexGame<4>>(&args),
+        "hex5" => bench::<HexGame<5>>(&args),
+        "hex7" => bench::<HexGame<7>>(&args),
+        "hex9" => bench::<HexGame<9>>(&args),
+        "hex11" => bench::<HexGame<11>>(&args),
+        "chess" => bench::<ChessGame>(&args),
+        unknown_game => panic!("unknown game: {:?}", unknown_game),
+    };
+
+    if let Some(outfile) = &args.outfile {
+        let writer = std::fs::File::create(outfile)?;
+        serde_json::to_writer_pretty(writer, &results)?;
+    }
+    Ok(())
+}
+
+fn bench<Game: EncodedGame + 'static>(args: &Args) -> BenchResults {
+    let encoder = args
+        .encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder);
+    let policy_map = args
+        .policy_map
+        .as_deref()
+        .map_or_else(Game::default_policy_map, Game::policy_map);
+    let histories = Arc::new(random_histories::<Game>(16, 0));
+    let duration = Duration::from_millis(args.duration_ms);
+
+    let mut results = BenchResults::default();
+    for cfg in InferenceConfig::available() {
+        for &batch_size in &args.batch_sizes {
+            let model_path = args.model_path.replace("{batch_size}", &batch_size.to_string());
+            let model_path = Path::new(&model_path).with_extension(cfg.model_file_extension().unwrap());
+            if !model_path.exists() {
+                log::warn!("Skipping {}, {} does not exist", cfg.name(), model_path.display());
+                continue;
+            }
+
+            let run = bench_model_run::<Game>(&model_path, &cfg, &*encoder, &histories, batch_size, duration);
+            println!(
+                "{:<12} batch {:>4}: latency mean {:>8.3}ms p50 {:>8.3}ms p99 {:>8.3}ms, {:>10.1} positions/s",
+                run.engine,
+                run.batch_size,
+                run.latency_mean_ms,
+                run.latency_p50_ms,
+                run.latency_p99_ms,
+                run.positions_per_sec
+            );
+            results.model_run.push(run);
+
+            for &threads in &args.threads {
+                let mut params = NNetworkParams::new(batch_size);
+                params.inference_server = args.inference_server;
+                let network = NNetwork::new(
+                    &model_path,
+                    cfg.clone(),
+                    Arc::clone(&encoder),
+                    Arc::clone(&policy_map),
+                    params,
+                    None,
+                );
+                let evaluate = bench_evaluate(cfg.name(), batch_size, Arc::new(network), &histories, threads, duration);
+                println!(
+                    "{:<12} batch {:>4}: {:>3} threads, {:>10.1} evaluations/s",
+                    evaluate.engine, evaluate.batch_size, evaluate.threads, evaluate.evaluations_per_sec
+                );
+                results.evaluate.push(evaluate);
+            }
+        }
+    }
+    results
+}
+
+fn bench_model_run<Game: cattus::game::Game>(
+    model_path: &Path,
+    cfg: &InferenceConfig,
+    encoder: &dyn Encoder<Game>,
+    histories: &[Vec<Game::Position>],
+    batch_size: usize,
+    duration: Duration,
+) -> RunResult {
+    let mut model = Model::new(model_path, cfg.clone());
+    let samples = histories
+        .iter()
+        .take(batch_size)
+        .map(|history| {
+            let history = &history[history.len().saturating_sub(encoder.history_len())..];
+            encoder.encode_to_vec(&flip_history_if_needed(history).0)
+        })
+        .collect_vec();
+    let tensor = planes_to_tensor::<Game>(&samples, batch_size);
+    let inputs = [tensor.view().into_dyn()];
+
+    /* Warm up, the first runs are usually slower */
+    for _ in 0..3 {
+        model.run(&inputs);
+    }
+
+    let mut latencies = Vec::new();
+    let begin = Instant::now();
+    while begin.elapsed() < duration || latencies.is_empty() {
+        let run_begin = Instant::now();
+        model.run(&inputs);
+        latencies.push(run_begin.elapsed());
+    }
+    let total = begin.elapsed();
+
+    latencies.sort();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p).round() as usize];
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    RunResult {
+        engine: cfg.name(),
+        batch_size,
+        runs: latencies.len(),
+        latency_mean_ms: ms(latencies.iter().sum::<Duration>()) / latencies.len() as f64,
+        latency_p50_ms: ms(percentile(0.5)),
+        latency_p99_ms: ms(percentile(0.99)),
+        positions_per_sec: (latencies.len() * batch_size) as f64 / total.as_secs_f64(),
+    }
+}
+
+fn bench_evaluate<Game: cattus::game::Game + 'static>(
+    engine: &'static str,
+    batch_size: usize,
+    network: Arc<NNetwork<Game>>,
+    histories: &Arc<Vec<Vec<Game::Position>>>,
+    threads: usize,
+    duration: Duration,
+) -> EvaluateResult {
+    let evaluations = Arc::new(AtomicU64::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let begin = Instant::now();
+    let handles = (0..threads)
+        .map(|thread_idx| {
+            let network = Arc::clone(&network);
+            let histories = Arc::clone(histories);
+            let evaluations = Arc::clone(&evaluations);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                for history in histories.iter().cycle().skip(thread_idx) {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    network.evaluate(history);
+                    evaluations.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect_vec();
+    std::thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let total = begin.elapsed();
+
+    let evaluations = evaluations.load(Ordering::Relaxed);
+    EvaluateResult {
+        engine,
+        batch_size,
+        threads,
+        evaluations,
+        evaluations_per_sec: evaluations as f64 / total.as_secs_f64(),
+    }
+}
@@ -1,7 +1,44 @@
-use cattus::chess::ChessGame;
-use cattus_self_play::self_play_cmd::run_main;
+use cattus::chess::book::{BookConfig, PolyglotBook};
+use cattus::chess::syzygy::Syzygy;
+use cattus::chess::{ChessGame, ChessPosition};
+use cattus_self_play::self_play::StartPositions;
+use cattus_self_play::self_play_cmd::{run_main_with_options, GameOptions};
 use cattus_self_play::serialize::chess::ChessSerializer;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+
+/// The start positions of the games, "chess960" or {"book": {"path": ..., "max_depth": ..., "weighting": ...}}
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ChessStartPositions {
+    /// A random Chess960 start position
+    Chess960,
+    /// Random book moves from a Polyglot book
+    Book(BookConfig),
+}
 
 fn main() -> std::io::Result<()> {
-    run_main::<ChessGame>(Box::new(ChessSerializer))
+    let start_positions = |config: serde_json::Value| -> StartPositions<ChessGame> {
+        let config: ChessStartPositions = serde_json::from_value(config).expect("invalid start positions config");
+        /* The same start position is used for both games of a pair */
+        let hasher = RandomState::new();
+        match config {
+            ChessStartPositions::Chess960 => {
+                Arc::new(move |pair_idx| ChessPosition::new_chess960((hasher.hash_one(pair_idx) % 960) as u16))
+            }
+            ChessStartPositions::Book(config) => {
+                let book = PolyglotBook::open(&config.path).expect("failed to open book");
+                Arc::new(move |pair_idx| {
+                    book.random_opening(config.max_depth, config.weighting, hasher.hash_one(pair_idx))
+                })
+            }
+        }
+    };
+    run_main_with_options::<ChessGame>(
+        |encoder, policy_map| Box::new(ChessSerializer::new(encoder, policy_map)),
+        GameOptions {
+            start_positions,
+            tablebase: |path| Arc::new(Syzygy::new(path).expect("failed to read tablebase directory")),
+        },
+    )
 }
@@ -0,0 +1,228 @@
+use cattus::chess::{ChessGame, ChessPosition};
+use cattus::game::Position;
+use cattus::hex::HexGame;
+use cattus::net::encoder::{EncodedGame, Encoder, PolicyMap};
+use cattus::net::model::{InferenceConfig, Model};
+use cattus::net::{calc_moves_probs, flip_history_if_needed, planes_to_tensor};
+use cattus::ttt::TttGame;
+use cattus_self_play::test_util::{hex_position_from_str, random_histories, ttt_position_from_str};
+use clap::Parser;
+use itertools::Itertools;
+use ndarray::{ArrayD, Axis, Slice};
+use std::path::PathBuf;
+
+/// Run the same model with every model implementation available in this build, and compare their outputs
+#[derive(Parser, Debug)]
+#[clap(about, long_about = None)]
+struct Args {
+    #[clap(long)]
+    game: String,
+    /// The model path without an extension. Each implementation loads the model file with its own extension,
+    /// '.onnx', '.pte' or '.jit', and implementations whose model file does not exist are skipped.
+    #[clap(long)]
+    model_path: PathBuf,
+    #[clap(long)]
+    batch_size: usize,
+    /// A file with a position per line, in the format of the game. If not set, the positions of random games are
+    /// used.
+    #[clap(long)]
+    positions_file: Option<PathBuf>,
+    /// Number of random games used if no positions file is given
+    #[clap(long, default_value = "8")]
+    games: usize,
+    #[clap(long, default_value = "0")]
+    seed: u64,
+    /// The encoder id, the game default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
+    /// The policy map id, the game default policy map if not set
+    #[clap(long)]
+    policy_map: Option<String>,
+    /// Absolute tolerance, outputs `a` and `b` of two implementations match if `|a-b| <= atol + rtol * |b|`
+    #[clap(long, default_value = "1e-4")]
+    atol: f32,
+    /// Relative tolerance, see `atol`
+    #[clap(long, default_value = "1e-3")]
+    rtol: f32,
+}
+
+fn main() {
+    cattus::util::init_globals();
+
+    let args = Args::parse();
+
+    let passed = match args.game.as_str() {
+        "tictactoe" => check_parity::<TttGame>(&args, ttt_position_from_str),
+        "hex4" => check_parity::<HexGame<4>>(&args, hex_position_from_str),
+        "hex5" => check_parity::<HexGame<5>>(&args, hex_position_from_str),
+        "hex7" => check_parity::<HexGame<7>>(&args, hex_position_from_str),
+        "hex9" => check_parity::<HexGame<9>>(&args, hex_position_from_str),
+        "hex11" => check_parity::<HexGame<11>>(&args, hex_position_from_str),
+        "chess" => check_parity::<ChessGame>(&args, |fen| ChessPosition::from_fen(fen).unwrap()),
+        unknown_game => panic!("unknown game: {:?}", unknown_game),
+    };
+    if !passed {
+        std::process::exit(1);
+    }
+}
+
+fn check_parity<Game: EncodedGame>(args: &Args, parse_position: impl Fn(&str) -> Game::Position) -> bool {
+    let encoder = args
+        .encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder);
+    let policy_map = args
+        .policy_map
+        .as_deref()
+        .map_or_else(Game::default_policy_map, Game::policy_map);
+    let histories = match &args.positions_file {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| vec![parse_position(line)])
+            .collect_vec(),
+        None => random_histories::<Game>(args.games, args.seed),
+    };
+    log::info!("Comparing outputs on {} positions", histories.len());
+
+    let outputs = InferenceConfig::available()
+        .into_iter()
+        .filter_map(|cfg| {
+            let model_path = args.model_path.with_extension(cfg.model_file_extension().unwrap());
+            if !model_path.exists() {
+                log::warn!("Skipping {}, {} does not exist", cfg.name(), model_path.display());
+                return None;
+            }
+            let name = cfg.name();
+            let mut model = Model::new(&model_path, cfg);
+            let outputs = run_model::<Game>(&mut model, &*encoder, &histories, args.batch_size);
+            Some((name, named_outputs(outputs, &*policy_map, &histories)))
+        })
+        .collect_vec();
+    if outputs.len() < 2 {
+        log::error!("Less than two model implementations to compare");
+        return false;
+    }
+
+    let mut passed = true;
+    for ((name1, outputs1), (name2, outputs2)) in outputs.iter().tuple_combinations() {
+        assert_eq!(outputs1.len(), outputs2.len(), "{} and {} outputs num", name1, name2);
+        for ((output_name, output1), (_, output2)) in outputs1.iter().zip(outputs2) {
+            assert_eq!(output1.shape(), output2.shape());
+            let (max_abs, max_rel) = max_diff(output1, output2);
+            let ok = is_close(output1, output2, args.atol, args.rtol);
+            passed &= ok;
+            println!(
+                "{:>10} vs {:<10} {:<12} max abs diff {:.3e}, max rel diff {:.3e} {}",
+                name1,
+                name2,
+                output_name,
+                max_abs,
+                max_rel,
+                if ok { "OK" } else { "FAILED" }
+            );
+        }
+    }
+    passed
+}
+
+/// Name the raw outputs of a model, and add the policy probabilities of the legal moves
+///
+/// The raw policy logits of illegal moves are never used, and a softmax may amplify small differences of the logits,
+/// so the probabilities that the search actually uses are compared as well.
+fn named_outputs<Game: cattus::game::Game>(
+    outputs: Vec<ArrayD<f32>>,
+    policy_map: &dyn PolicyMap<Game>,
+    histories: &[Vec<Game::Position>],
+) -> Vec<(&'static str, ArrayD<f32>)> {
+    let policy = outputs[0].view().into_dimensionality::<ndarray::Ix2>().unwrap();
+    let probs = histories
+        .iter()
+        .zip(policy.rows())
+        .flat_map(|(history, scores)| {
+            let (history, _is_flipped) = flip_history_if_needed(history);
+            let moves = history.last().unwrap().legal_moves().collect_vec();
+            calc_moves_probs(moves, scores.as_slice().unwrap(), policy_map, 1.0)
+                .into_iter()
+                .map(|(_m, p)| p)
+        })
+        .collect_vec();
+
+    let names = ["policy", "value", "moves_left"];
+    let mut named = outputs
+        .into_iter()
+        .enumerate()
+        .map(|(idx, output)| (*names.get(idx).unwrap_or(&"?"), output))
+        .collect_vec();
+    named.push((
+        "policy_probs",
+        ArrayD::from_shape_vec(vec![probs.len()], probs).unwrap(),
+    ));
+    named
+}
+
+/// Run the model on all the histories, and return the outputs concatenated along the batch dimension
+fn run_model<Game: cattus::game::Game>(
+    model: &mut Model,
+    encoder: &dyn Encoder<Game>,
+    histories: &[Vec<Game::Position>],
+    batch_size: usize,
+) -> Vec<ArrayD<f32>> {
+    let outputs = histories
+        .chunks(batch_size)
+        .map(|chunk| {
+            let samples = chunk
+                .iter()
+                .map(|history| {
+                    let history = &history[history.len().saturating_sub(encoder.history_len())..];
+                    let (history, _is_flipped) = flip_history_if_needed(history);
+                    encoder.encode_to_vec(&history)
+                })
+                .collect_vec();
+            let tensor = planes_to_tensor::<Game>(&samples, batch_size);
+            model
+                .run(&[tensor.view().into_dyn()])
+                .into_iter()
+                .map(|output| output.slice_axis(Axis(0), Slice::from(0..chunk.len())).to_owned())
+                .collect_vec()
+        })
+        .collect_vec();
+    (0..outputs[0].len())
+        .map(|output_idx| {
+            let outputs = outputs.iter().map(|outputs| outputs[output_idx].view()).collect_vec();
+            ndarray::concatenate(Axis(0), &outputs).unwrap()
+        })
+        .collect()
+}
+
+/// Check that every entry of the first output is close to the second, `|a-b| <= atol + rtol * |b|`
+fn is_close(output1: &ArrayD<f32>, output2: &ArrayD<f32>, atol: f32, rtol: f32) -> bool {
+    output1.iter().zip(output2.iter()).all(|(&a, &b)| {
+        /* Also equal infinities */
+        a == b || (a.is_nan() && b.is_nan()) || (a - b).abs() <= atol + rtol * b.abs()
+    })
+}
+
+/// The maximum absolute and relative differences between two outputs
+fn max_diff(output1: &ArrayD<f32>, output2: &ArrayD<f32>) -> (f32, f32) {
+    output1
+        .iter()
+        .zip(output2.iter())
+        .map(|(&x1, &x2)| {
+            if x1 == x2 || (x1.is_nan() && x2.is_nan()) {
+                /* Also equal infinities */
+                return (0.0, 0.0);
+            }
+            if x1.is_nan() || x2.is_nan() {
+                return (f32::INFINITY, f32::INFINITY);
+            }
+            let abs = (x1 - x2).abs();
+            let rel = abs / x1.abs().max(x2.abs()).max(f32::EPSILON);
+            (abs, rel)
+        })
+        .fold((0.0, 0.0), |(max_abs, max_rel), (abs, rel)| {
+            (f32::max(max_abs, abs), f32::max(max_rel, rel))
+        })
+}
@@ -1,13 +1,15 @@
 use cattus::chess::{ChessGame, ChessPosition};
 use cattus::hex::HexGame;
+use cattus::net::encoder::{EncodedGame, Encoder};
 use cattus::net::planes_to_tensor;
 use cattus::ttt::TttGame;
-use cattus::{chess, hex, ttt};
 use cattus_self_play::test_util::{hex_position_from_str, ttt_position_from_str};
 use clap::Parser;
 use ndarray::{Array3, Array4, Axis};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[clap(about, long_about = None)]
@@ -18,6 +20,9 @@ struct Args {
     position: String,
     #[clap(long)]
     outfile: PathBuf,
+    /// The encoder id, the game default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
@@ -44,21 +49,27 @@ fn main() -> std::io::Result<()> {
 
 fn create_tensor_tictactoe(args: &Args) -> Array4<f32> {
     let pos = ttt_position_from_str(&args.position);
-    let planes = ttt::net::position_to_planes(&pos);
+    let planes = encoder::<TttGame>(args).encode_to_vec(slice::from_ref(&pos));
     planes_to_tensor::<TttGame>(&[planes], 1)
 }
 fn create_tensor_hex<const BOARD_SIZE: usize>(args: &Args) -> Array4<f32> {
     let pos = hex_position_from_str(&args.position);
-    let planes = hex::net::position_to_planes(&pos);
+    let planes = encoder::<HexGame<BOARD_SIZE>>(args).encode_to_vec(slice::from_ref(&pos));
     planes_to_tensor::<HexGame<BOARD_SIZE>>(&[planes], 1)
 }
 
 fn create_tensor_chess(args: &Args) -> Array4<f32> {
-    let pos = ChessPosition::from_fen(&args.position);
-    let planes = chess::net::position_to_planes(&pos);
+    let pos = ChessPosition::from_fen(&args.position).unwrap();
+    let planes = encoder::<ChessGame>(args).encode_to_vec(slice::from_ref(&pos));
     planes_to_tensor::<ChessGame>(&[planes], 1)
 }
 
+fn encoder<Game: EncodedGame>(args: &Args) -> Arc<dyn Encoder<Game>> {
+    args.encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder)
+}
+
 fn tensor_to_json(tensor: Array3<f32>, filename: &Path) -> std::io::Result<()> {
     #[derive(serde::Serialize)]
     struct JsonTensor {
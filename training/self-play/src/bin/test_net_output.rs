@@ -1,15 +1,17 @@
 use cattus::chess::{ChessGame, ChessPosition};
 use cattus::hex::HexGame;
+use cattus::net::encoder::{EncodedGame, Encoder};
 use cattus::net::model::{InferenceConfig, Model};
 use cattus::net::planes_to_tensor;
 use cattus::ttt::TttGame;
-use cattus::{chess, hex, ttt};
 use cattus_self_play::test_util::{hex_position_from_str, ttt_position_from_str};
 use clap::Parser;
 use itertools::Itertools;
 use ndarray::{Array2, ArrayD, Axis};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[clap(about, long_about = None)]
@@ -26,6 +28,9 @@ struct Args {
     outfile: PathBuf,
     #[clap(long, default_value = "1")]
     repeat: u32,
+    /// The encoder id, the game default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
@@ -48,12 +53,13 @@ fn main() -> std::io::Result<()> {
 
 fn run_net_tictactoe(args: &Args) -> Vec<ArrayD<f32>> {
     let pos = ttt_position_from_str(&args.position);
+    let encoder = encoder::<TttGame>(args);
     let mut model = Model::new(&args.model_path, InferenceConfig::default());
     let outputs = (0..args.repeat)
         .map(|_| {
-            let samples = ttt::net::position_to_planes(&pos);
+            let samples = encoder.encode_to_vec(slice::from_ref(&pos));
             let tensor = planes_to_tensor::<TttGame>(&[samples], args.batch_size);
-            model.run(vec![tensor.into_dyn()])
+            model.run(&[tensor.view().into_dyn()])
         })
         .collect_vec();
     (0..outputs[0].len())
@@ -66,12 +72,13 @@ fn run_net_tictactoe(args: &Args) -> Vec<ArrayD<f32>> {
 
 fn run_net_hex<const BOARD_SIZE: usize>(args: &Args) -> Vec<ArrayD<f32>> {
     let pos = hex_position_from_str(&args.position);
+    let encoder = encoder::<HexGame<BOARD_SIZE>>(args);
     let mut model = Model::new(&args.model_path, InferenceConfig::default());
     let outputs = (0..args.repeat)
         .map(|_| {
-            let samples = hex::net::position_to_planes(&pos);
+            let samples = encoder.encode_to_vec(slice::from_ref(&pos));
             let tensor = planes_to_tensor::<HexGame<BOARD_SIZE>>(&[samples], args.batch_size);
-            model.run(vec![tensor.into_dyn()])
+            model.run(&[tensor.view().into_dyn()])
         })
         .collect_vec();
     (0..outputs[0].len())
@@ -83,14 +90,15 @@ fn run_net_hex<const BOARD_SIZE: usize>(args: &Args) -> Vec<ArrayD<f32>> {
 }
 
 fn run_net_chess(args: &Args) -> Vec<ArrayD<f32>> {
-    let pos = ChessPosition::from_fen(&args.position);
+    let pos = ChessPosition::from_fen(&args.position).unwrap();
+    let encoder = encoder::<ChessGame>(args);
     let mut model = Model::new(&args.model_path, InferenceConfig::default());
 
     let outputs = (0..args.repeat)
         .map(|_| {
-            let samples = chess::net::position_to_planes(&pos);
+            let samples = encoder.encode_to_vec(slice::from_ref(&pos));
             let tensor = planes_to_tensor::<ChessGame>(&[samples], args.batch_size);
-            model.run(vec![tensor.into_dyn()])
+            model.run(&[tensor.view().into_dyn()])
         })
         .collect_vec();
     (0..outputs[0].len())
@@ -101,6 +109,12 @@ fn run_net_chess(args: &Args) -> Vec<ArrayD<f32>> {
         .collect()
 }
 
+fn encoder<Game: EncodedGame>(args: &Args) -> Arc<dyn Encoder<Game>> {
+    args.encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder)
+}
+
 fn outputs_to_json(mut outputs: Vec<ArrayD<f32>>, filename: &Path) -> std::io::Result<()> {
     assert_eq!(outputs.len(), 2);
     let probs: Array2<f32> = outputs.remove(0).into_dimensionality().unwrap();
@@ -1,8 +1,11 @@
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use cattus::chess::ChessPosition;
+use cattus::chess::{ChessGame, ChessPosition};
 use cattus::game::{GameColor, Position};
 use cattus::hex::HexGame;
+use cattus::net::encoder::{EncodedGame, Encoder, PolicyMap};
+use cattus::ttt::TttGame;
 use cattus_self_play::self_play::DataEntry;
 use cattus_self_play::serialize::chess::ChessSerializer;
 use cattus_self_play::serialize::hex::HexSerializer;
@@ -21,6 +24,12 @@ struct Args {
     position: String,
     #[clap(long)]
     outfile: PathBuf,
+    /// The encoder id, the game default encoder if not set
+    #[clap(long)]
+    encoder: Option<String>,
+    /// The policy map id, the game default policy map if not set
+    #[clap(long)]
+    policy_map: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
@@ -41,22 +50,34 @@ fn main() -> std::io::Result<()> {
 
 fn test_tictactoe(args: Args) -> std::io::Result<()> {
     let pos = ttt_position_from_str(&args.position);
-    let serializer = TttSerializer;
+    let serializer = TttSerializer::new(encoder::<TttGame>(&args));
     serialize_position(pos, &serializer, &args.outfile)
 }
 
 fn test_hex<const BOARD_SIZE: usize>(args: Args) -> std::io::Result<()> {
     let pos = hex_position_from_str(&args.position);
-    let serializer = HexSerializer;
+    let serializer = HexSerializer::new(encoder::<HexGame<BOARD_SIZE>>(&args));
     serialize_position::<HexGame<BOARD_SIZE>>(pos, &serializer, &args.outfile)
 }
 
 fn test_chess(args: Args) -> std::io::Result<()> {
-    let pos = ChessPosition::from_fen(&args.position);
-    let serializer = ChessSerializer;
+    let pos = ChessPosition::from_fen(&args.position).unwrap();
+    let serializer = ChessSerializer::new(encoder::<ChessGame>(&args), policy_map::<ChessGame>(&args));
     serialize_position(pos, &serializer, &args.outfile)
 }
 
+fn encoder<Game: EncodedGame>(args: &Args) -> Arc<dyn Encoder<Game>> {
+    args.encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder)
+}
+
+fn policy_map<Game: EncodedGame>(args: &Args) -> Arc<dyn PolicyMap<Game>> {
+    args.policy_map
+        .as_deref()
+        .map_or_else(Game::default_policy_map, Game::policy_map)
+}
+
 fn serialize_position<Game: cattus::game::Game>(
     pos: Game::Position,
     serializer: &impl DataSerializer<Game>,
@@ -75,5 +96,13 @@ fn serialize_position<Game: cattus::game::Game>(
         2 => None,
         _ => panic!("cant happen"),
     };
-    serializer.serialize_data_entry(DataEntry { pos, probs, winner }, filename)
+    serializer.serialize_data_entry(
+        DataEntry {
+            pos_history: vec![pos],
+            probs,
+            winner,
+            moves_left: moves_num as u32,
+        },
+        filename,
+    )
 }
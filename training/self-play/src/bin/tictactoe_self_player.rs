@@ -1,6 +1,7 @@
+use cattus::ttt::TttGame;
 use cattus_self_play::self_play_cmd::run_main;
 use cattus_self_play::serialize::ttt::TttSerializer;
 
 fn main() -> std::io::Result<()> {
-    run_main(Box::new(TttSerializer))
+    run_main::<TttGame>(|encoder, _policy_map| Box::new(TttSerializer::new(encoder)))
 }
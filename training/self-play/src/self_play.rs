@@ -7,33 +7,45 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 
 use cattus::game::{GameColor, GameStatus, Move, Position};
+use cattus::mcts::tablebase::Tablebase;
 use cattus::mcts::{MctsParams, MctsPlayer};
 use cattus::net;
 
 use crate::serialize::DataSerializer;
 
 pub struct DataEntry<Game: cattus::game::Game> {
-    pub pos: Game::Position,
+    /// The last positions of the game up to and including the entry position, oldest first
+    pub pos_history: Vec<Game::Position>,
     pub probs: Vec<(Game::Move, f32)>,
     pub winner: Option<GameColor>,
+    /// The number of plies played from the entry position until the end of the game
+    pub moves_left: u32,
 }
 
 impl<Game: cattus::game::Game> Clone for DataEntry<Game> {
     fn clone(&self) -> Self {
         DataEntry {
-            pos: self.pos.clone(),
+            pos_history: self.pos_history.clone(),
             probs: self.probs.clone(),
             winner: self.winner,
+            moves_left: self.moves_left,
         }
     }
 }
 
+impl<Game: cattus::game::Game> DataEntry<Game> {
+    pub fn pos(&self) -> &Game::Position {
+        self.pos_history.last().unwrap()
+    }
+}
+
 pub struct SerializerBase;
 impl SerializerBase {
     pub fn write_entry<Game: cattus::game::Game>(
         planes: Vec<u64>,
         probs: Vec<(Game::Move, f32)>,
         winner: i8,
+        moves_left: u16,
         filename: &Path,
     ) -> std::io::Result<()> {
         /* Use -1 for illegal moves */
@@ -47,13 +59,15 @@ impl SerializerBase {
         let u64bytes = u64::BITS as usize / 8;
         let f32bytes = /* f32::BITS */ 32 / 8;
         let i8bytes = i8::BITS as usize / 8;
-        let size = planes.len() * u64bytes + probs_vec.len() * f32bytes + i8bytes;
+        let u16bytes = u16::BITS as usize / 8;
+        let size = planes.len() * u64bytes + probs_vec.len() * f32bytes + i8bytes + u16bytes;
         let mut bytes = Vec::with_capacity(size);
 
         /* Serialized in little indian format, should deserialized the same */
         bytes.extend(planes.into_iter().flat_map(|p| p.to_le_bytes()));
         bytes.extend(probs_vec.into_iter().flat_map(|p| p.to_le_bytes()));
         bytes.extend(winner.to_le_bytes());
+        bytes.extend(moves_left.to_le_bytes());
         assert!(bytes.len() == size);
 
         /* Write to file */
@@ -61,6 +75,9 @@ impl SerializerBase {
     }
 }
 
+/// Generate the start position of a game by the game index
+pub type StartPositions<Game> = Arc<dyn Fn(usize) -> <Game as cattus::game::Game>::Position + Send + Sync>;
+
 #[derive(Copy, Clone)]
 pub struct GamesResults {
     pub w1: u32,
@@ -73,6 +90,8 @@ pub struct SelfPlayRunner<Game: cattus::game::Game> {
     player2_params: MctsParams<Game>,
     serializer: Arc<dyn DataSerializer<Game>>,
     thread_num: usize,
+    start_positions: Option<StartPositions<Game>>,
+    tablebase: Option<Arc<dyn Tablebase<Game>>>,
 }
 
 impl<Game: cattus::game::Game + 'static> SelfPlayRunner<Game> {
@@ -88,9 +107,25 @@ impl<Game: cattus::game::Game + 'static> SelfPlayRunner<Game> {
             player2_params,
             serializer,
             thread_num: thread_num as usize,
+            start_positions: None,
+            tablebase: None,
         }
     }
 
+    /// Start the games from generated positions instead of the game start position
+    ///
+    /// Both games of a pair, in which the players switch colors, are called with the same index.
+    pub fn with_start_positions(mut self, start_positions: StartPositions<Game>) -> Self {
+        self.start_positions = Some(start_positions);
+        self
+    }
+
+    /// End the games as soon as their outcome is known by the tablebase
+    pub fn with_tablebase(mut self, tablebase: Arc<dyn Tablebase<Game>>) -> Self {
+        self.tablebase = Some(tablebase);
+        self
+    }
+
     pub fn generate_data(
         &self,
         games_num: usize,
@@ -114,6 +149,8 @@ impl<Game: cattus::game::Game + 'static> SelfPlayRunner<Game> {
                 self.player1_params.clone(),
                 self.player2_params.clone(),
                 self.serializer.clone(),
+                self.start_positions.clone(),
+                self.tablebase.clone(),
                 output_dir1.to_path_buf(),
                 output_dir2.to_path_buf(),
                 result.clone(),
@@ -145,6 +182,8 @@ struct SelfPlayWorker<Game: cattus::game::Game> {
     player1_params: MctsParams<Game>,
     player2_params: MctsParams<Game>,
     serializer: Arc<dyn DataSerializer<Game>>,
+    start_positions: Option<StartPositions<Game>>,
+    tablebase: Option<Arc<dyn Tablebase<Game>>>,
     output_dir1: PathBuf,
     output_dir2: PathBuf,
     results: Arc<Mutex<GamesResults>>,
@@ -158,6 +197,8 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
         player1_params: MctsParams<Game>,
         player2_params: MctsParams<Game>,
         serializer: Arc<dyn DataSerializer<Game>>,
+        start_positions: Option<StartPositions<Game>>,
+        tablebase: Option<Arc<dyn Tablebase<Game>>>,
         output_dir1: PathBuf,
         output_dir2: PathBuf,
         results: Arc<Mutex<GamesResults>>,
@@ -168,6 +209,8 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
             player1_params,
             player2_params,
             serializer,
+            start_positions,
+            tablebase,
             output_dir1,
             output_dir2,
             results,
@@ -186,14 +229,25 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
                 break;
             }
 
-            let mut game = Game::new();
-            let mut pos_probs_pairs = Vec::new();
+            let mut game = match &self.start_positions {
+                Some(start_positions) => Game::from_position(start_positions(game_idx / 2)),
+                None => Game::new(),
+            };
+            let mut moves_probs = Vec::new();
             let players_switch = game_idx % 2 == 1;
 
             let winner = loop {
                 if let GameStatus::Finished(winner) = game.status() {
                     break winner;
                 }
+                /* Adjudicate positions with a known outcome */
+                if let Some(winner) = self
+                    .tablebase
+                    .as_ref()
+                    .and_then(|tablebase| tablebase.probe(game.position()))
+                {
+                    break winner;
+                }
 
                 let mut player = game.position().turn();
                 if players_switch {
@@ -211,15 +265,19 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
                     .unwrap();
 
                 /* Store probabilities */
-                pos_probs_pairs.push((game.position().clone(), moves));
+                moves_probs.push(moves);
 
                 /* Advance game position */
                 game.play_single_turn(next_move);
             };
 
-            /* Save all data entries */
-            for (pos_idx, (pos, probs)) in pos_probs_pairs.into_iter().enumerate() {
-                self.write_data_entry(game_idx, pos_idx, pos, probs, winner)?;
+            /* Save all data entries, each with the positions history used by the network */
+            let history_len = self.player1_params.value_func.history_len();
+            let game_len = moves_probs.len();
+            for (pos_idx, probs) in moves_probs.into_iter().enumerate() {
+                let pos_history = &game.pos_history()[(pos_idx + 1).saturating_sub(history_len)..=pos_idx];
+                let moves_left = (game_len - pos_idx) as u32;
+                self.write_data_entry(game_idx, pos_idx, pos_history, probs, winner, moves_left)?;
             }
 
             /* Update winning counters */
@@ -245,21 +303,23 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_data_entry(
         &self,
         game_idx: usize,
         pos_idx: usize,
-        pos: Game::Position,
+        pos_history: &[Game::Position],
         probs: Vec<(Game::Move, f32)>,
         winner: Option<GameColor>,
+        moves_left: u32,
     ) -> std::io::Result<()> {
-        let output_dir = match pos.turn() {
+        let output_dir = match pos_history.last().unwrap().turn() {
             GameColor::Player1 => [&self.output_dir1, &self.output_dir2],
             GameColor::Player2 => [&self.output_dir2, &self.output_dir1],
         }[game_idx % 2];
 
         let winner = GameColor::to_signed_one(winner) as f32;
-        let (pos, is_flipped) = net::flip_pos_if_needed(pos);
+        let (pos_history, is_flipped) = net::flip_history_if_needed(pos_history);
         let (probs, winner) = net::flip_score_if_needed((probs, winner), is_flipped);
         let winner = match winner as i32 {
             1 => Some(GameColor::Player1),
@@ -269,7 +329,12 @@ impl<Game: cattus::game::Game> SelfPlayWorker<Game> {
         };
 
         self.serializer.serialize_data_entry(
-            DataEntry { pos, probs, winner },
+            DataEntry {
+                pos_history,
+                probs,
+                winner,
+                moves_left,
+            },
             &output_dir.join(format!("{game_idx:#08}_{pos_idx:#03}.traindata",)),
         )
     }
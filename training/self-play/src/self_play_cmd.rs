@@ -1,15 +1,18 @@
 use cattus::mcts::cache::ValueFuncCache;
+use cattus::mcts::tablebase::Tablebase;
 use cattus::mcts::value_func::ValueFunction;
-use cattus::mcts::{MctsParams, TemperaturePolicy};
+use cattus::mcts::{MctsParams, MovesLeftParams, TemperaturePolicy};
+use cattus::net::encoder::{EncodedGame, Encoder, PolicyMap};
 use cattus::net::model::InferenceConfig;
-use cattus::net::NNetwork;
+use cattus::net::{NNetwork, NNetworkParams, NonFinitePolicy};
 use cattus::util;
 use clap::Parser;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::self_play::SelfPlayRunner;
+use crate::self_play::{SelfPlayRunner, StartPositions};
 use crate::serialize::DataSerializer;
 
 #[derive(Parser, Debug)]
@@ -36,11 +39,45 @@ struct Config {
     model: ModelConfig,
     mcts: MctsConfig,
     threads: u32,
+    /// Game specific start positions of the games, the game start position if not set
+    #[serde(default)]
+    start_positions: Option<serde_json::Value>,
 }
 #[derive(serde::Deserialize)]
 struct ModelConfig {
     inference: InferenceConfig,
+    /// The id of the encoder the models were trained with, the game default encoder if not set
+    #[serde(default)]
+    encoder: Option<String>,
+    /// The id of the policy map the models were trained with, the game default policy map if not set
+    #[serde(default)]
+    policy_map: Option<String>,
     batch_size: usize,
+    /// If set, the model files are polled at this interval and the models are reloaded when modified
+    #[serde(default)]
+    watch_interval_ms: Option<u64>,
+    /// Maximum time a position waits for its batch to fill, defaults to 20ms
+    #[serde(default)]
+    batch_deadline_ms: Option<u64>,
+    /// Run each model on a dedicated inference thread instead of on the search threads
+    #[serde(default)]
+    inference_server: bool,
+    /// Softmax temperature of the policy, defaults to 1
+    #[serde(default)]
+    policy_temperature: Option<f32>,
+    /// Minimum probability of each legal move, defaults to 0
+    #[serde(default)]
+    min_prior: Option<f32>,
+    /// Choose the batch size and deadline automatically, using the configured ones as upper bounds. Not supported
+    /// with the inference server.
+    #[serde(default)]
+    auto_batch: bool,
+    /// What to do when the network outputs non-finite values, "retry", "uniform" or "fail", defaults to "uniform"
+    #[serde(default)]
+    non_finite_policy: Option<NonFinitePolicy>,
+    /// If set, the inputs that produced non-finite outputs are appended to this file
+    #[serde(default)]
+    non_finite_log: Option<PathBuf>,
 }
 #[derive(serde::Deserialize)]
 struct MctsConfig {
@@ -50,12 +87,43 @@ struct MctsConfig {
     prior_noise_alpha: f32,
     prior_noise_epsilon: f32,
     cache_size: usize,
+    /// A directory of game specific tablebases, used by the search and to adjudicate the games
+    #[serde(default)]
+    tablebase_path: Option<PathBuf>,
 }
 
-pub fn run_main<Game>(serializer: Box<dyn DataSerializer<Game>>) -> std::io::Result<()>
+/// Game specific options of the config file
+pub struct GameOptions<Game: cattus::game::Game> {
+    /// Create start positions by their config
+    pub start_positions: fn(serde_json::Value) -> StartPositions<Game>,
+    /// Open the tablebases of a directory
+    pub tablebase: fn(&Path) -> Arc<dyn Tablebase<Game>>,
+}
+impl<Game: cattus::game::Game> Default for GameOptions<Game> {
+    fn default() -> Self {
+        Self {
+            start_positions: |config| panic!("unknown start positions: {config}"),
+            tablebase: |_path| panic!("tablebases are not supported"),
+        }
+    }
+}
+
+pub fn run_main<Game>(
+    create_serializer: impl FnOnce(Arc<dyn Encoder<Game>>, Arc<dyn PolicyMap<Game>>) -> Box<dyn DataSerializer<Game>>,
+) -> std::io::Result<()>
+where
+    Game: EncodedGame + 'static,
+{
+    run_main_with_options(create_serializer, GameOptions::default())
+}
+
+/// Same as `run_main`, with the game specific options of the config file
+pub fn run_main_with_options<Game>(
+    create_serializer: impl FnOnce(Arc<dyn Encoder<Game>>, Arc<dyn PolicyMap<Game>>) -> Box<dyn DataSerializer<Game>>,
+    options: GameOptions<Game>,
+) -> std::io::Result<()>
 where
-    Game: cattus::game::Game + 'static,
-    NNetwork<Game>: ValueFunction<Game>,
+    Game: EncodedGame + 'static,
 {
     util::init_globals();
     let args = SelfPlayArgs::parse();
@@ -75,38 +143,82 @@ where
     let last_temperature = config.mcts.temperature_policy.last().unwrap().1;
     let temperature = TemperaturePolicy::scheduled(scheduled_temperatures.to_vec(), last_temperature);
 
-    let player1_net: Arc<dyn ValueFunction<Game>> = Arc::new(NNetwork::new(
-        &args.model1_path,
-        config.model.inference,
-        config.model.batch_size,
-        Some(Arc::new(ValueFuncCache::new(config.mcts.cache_size))),
-    ));
+    let encoder = config
+        .model
+        .encoder
+        .as_deref()
+        .map_or_else(Game::default_encoder, Game::encoder);
+    let policy_map = config
+        .model
+        .policy_map
+        .as_deref()
+        .map_or_else(Game::default_policy_map, Game::policy_map);
+
+    let mut net_params = NNetworkParams::new(config.model.batch_size);
+    if let Some(deadline) = config.model.batch_deadline_ms {
+        net_params.batch_deadline = Duration::from_millis(deadline);
+    }
+    net_params.inference_server = config.model.inference_server;
+    if let Some(temperature) = config.model.policy_temperature {
+        net_params.policy_temperature = temperature;
+    }
+    if let Some(min_prior) = config.model.min_prior {
+        net_params.min_prior = min_prior;
+    }
+    net_params.auto_batch = config.model.auto_batch;
+    if let Some(policy) = config.model.non_finite_policy {
+        net_params.non_finite_policy = policy;
+    }
+    net_params.non_finite_log = config.model.non_finite_log.clone();
+    let create_net = |model_path: &PathBuf| -> Arc<dyn ValueFunction<Game>> {
+        let net = Arc::new(NNetwork::new(
+            model_path,
+            config.model.inference.clone(),
+            Arc::clone(&encoder),
+            Arc::clone(&policy_map),
+            net_params.clone(),
+            Some(Arc::new(ValueFuncCache::new(config.mcts.cache_size))),
+        ));
+        if let Some(interval) = config.model.watch_interval_ms {
+            net.watch_model(Duration::from_millis(interval));
+        }
+        net
+    };
+
+    let tablebase = config.mcts.tablebase_path.as_deref().map(options.tablebase);
+
+    let player1_net = create_net(&args.model1_path);
     let player1_params = MctsParams {
         sim_num: config.mcts.sim_num,
         explore_factor: config.mcts.explore_factor,
         temperature: temperature.clone(),
         prior_noise_alpha: config.mcts.prior_noise_alpha,
         prior_noise_epsilon: config.mcts.prior_noise_epsilon,
+        contempt: 0.0,
+        moves_left: MovesLeftParams::default(),
         value_func: player1_net,
+        tablebase: tablebase.clone(),
     };
 
     let player2_params = if args.model1_path == args.model2_path {
         player1_params.clone()
     } else {
-        let player2_net: Arc<dyn ValueFunction<Game>> = Arc::new(NNetwork::new(
-            &args.model2_path,
-            config.model.inference,
-            config.model.batch_size,
-            Some(Arc::new(ValueFuncCache::new(config.mcts.cache_size))),
-        ));
+        let player2_net = create_net(&args.model2_path);
         MctsParams {
             value_func: player2_net,
             ..player1_params.clone()
         }
     };
 
-    let result = SelfPlayRunner::new(player1_params, player2_params, Arc::from(serializer), config.threads)
-        .generate_data(args.games_num as usize, &args.out_dir1, &args.out_dir2)?;
+    let serializer = create_serializer(encoder, policy_map);
+    let mut runner = SelfPlayRunner::new(player1_params, player2_params, Arc::from(serializer), config.threads);
+    if let Some(start_positions) = config.start_positions {
+        runner = runner.with_start_positions((options.start_positions)(start_positions));
+    }
+    if let Some(tablebase) = tablebase {
+        runner = runner.with_tablebase(tablebase);
+    }
+    let result = runner.generate_data(args.games_num as usize, &args.out_dir1, &args.out_dir2)?;
 
     if let Some(summary_file) = args.summary_file {
         let mut metrics = HashMap::new();
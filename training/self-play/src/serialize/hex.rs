@@ -1,20 +1,32 @@
 use std::path::Path;
+use std::sync::Arc;
 
 use crate::self_play::{DataEntry, SerializerBase};
 use crate::serialize::DataSerializer;
 use cattus::game::{GameColor, Position};
 use cattus::hex::HexGame;
+use cattus::net::encoder::Encoder;
 use itertools::Itertools;
 
-pub struct HexSerializer;
-impl<const BOARD_SIZE: usize> DataSerializer<HexGame<BOARD_SIZE>> for HexSerializer {
+pub struct HexSerializer<const BOARD_SIZE: usize> {
+    encoder: Arc<dyn Encoder<HexGame<BOARD_SIZE>>>,
+}
+impl<const BOARD_SIZE: usize> HexSerializer<BOARD_SIZE> {
+    pub fn new(encoder: Arc<dyn Encoder<HexGame<BOARD_SIZE>>>) -> Self {
+        Self { encoder }
+    }
+}
+impl<const BOARD_SIZE: usize> DataSerializer<HexGame<BOARD_SIZE>> for HexSerializer<BOARD_SIZE> {
     fn serialize_data_entry(&self, entry: DataEntry<HexGame<BOARD_SIZE>>, filename: &Path) -> std::io::Result<()> {
         /* Always serialize as turn=1 */
         let winner = GameColor::to_signed_one(entry.winner) as i8;
-        assert!(entry.pos.turn() == GameColor::Player1);
+        let moves_left = entry.moves_left.min(u16::MAX as u32) as u16;
+        assert!(entry.pos().turn() == GameColor::Player1);
 
         #[allow(clippy::identity_op)]
-        let planes = cattus::hex::net::position_to_planes(&entry.pos)
+        let planes = self
+            .encoder
+            .encode_to_vec(&entry.pos_history)
             .into_iter()
             .flat_map(|p| {
                 [
@@ -25,6 +37,6 @@ impl<const BOARD_SIZE: usize> DataSerializer<HexGame<BOARD_SIZE>> for HexSeriali
             })
             .collect_vec();
 
-        SerializerBase::write_entry::<HexGame<BOARD_SIZE>>(planes, entry.probs, winner, filename)
+        SerializerBase::write_entry::<HexGame<BOARD_SIZE>>(planes, entry.probs, winner, moves_left, filename)
     }
 }
@@ -1,24 +1,36 @@
 use std::path::Path;
+use std::sync::Arc;
 
 use itertools::Itertools;
 
 use crate::self_play::{DataEntry, SerializerBase};
 use crate::serialize::DataSerializer;
 use cattus::game::{GameColor, Position};
+use cattus::net::encoder::Encoder;
 use cattus::ttt::TttGame;
 
-pub struct TttSerializer;
+pub struct TttSerializer {
+    encoder: Arc<dyn Encoder<TttGame>>,
+}
+impl TttSerializer {
+    pub fn new(encoder: Arc<dyn Encoder<TttGame>>) -> Self {
+        Self { encoder }
+    }
+}
 impl DataSerializer<TttGame> for TttSerializer {
     fn serialize_data_entry(&self, entry: DataEntry<TttGame>, filename: &Path) -> std::io::Result<()> {
         /* Always serialize as turn=1 */
         let winner = GameColor::to_signed_one(entry.winner) as i8;
-        assert!(entry.pos.turn() == GameColor::Player1);
+        let moves_left = entry.moves_left.min(u16::MAX as u32) as u16;
+        assert!(entry.pos().turn() == GameColor::Player1);
 
-        let planes = cattus::ttt::net::position_to_planes(&entry.pos)
+        let planes = self
+            .encoder
+            .encode_to_vec(&entry.pos_history)
             .iter()
             .map(|p| p.get_raw() as u64)
             .collect_vec();
 
-        SerializerBase::write_entry::<TttGame>(planes, entry.probs, winner, filename)
+        SerializerBase::write_entry::<TttGame>(planes, entry.probs, winner, moves_left, filename)
     }
 }
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
-use cattus::game::{Bitboard, Game as _, GameColor, Position};
+use cattus::game::player::PlayerRand;
+use cattus::game::{Bitboard, Game, GameColor, Position};
 use cattus::hex::{HexBitboard, HexPosition};
 use cattus::ttt::{TttGame, TttPosition};
 
@@ -64,3 +65,21 @@ pub fn hex_position_from_str<const BOARD_SIZE: usize>(s: &str) -> HexPosition<BO
 
     HexPosition::new_from_board(board_red, board_blue, turn.unwrap())
 }
+
+/// The positions of random games, each with the history that leads to it. The games are deterministic for a seed.
+pub fn random_histories<G: Game>(games: usize, seed: u64) -> Vec<Vec<G::Position>> {
+    let mut histories = Vec::new();
+    for game_idx in 0..games as u64 {
+        let mut game = G::new();
+        let mut player1 = PlayerRand::from_seed(seed.wrapping_add(2 * game_idx));
+        let mut player2 = PlayerRand::from_seed(seed.wrapping_add(2 * game_idx + 1));
+        game.play_until_over(&mut player1, &mut player2);
+        let pos_history = game.pos_history();
+        for len in 1..=pos_history.len() {
+            if pos_history[len - 1].status().is_ongoing() {
+                histories.push(pos_history[..len].to_vec());
+            }
+        }
+    }
+    histories
+}
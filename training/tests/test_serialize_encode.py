@@ -4,9 +4,10 @@ import subprocess
 import tempfile
 from pathlib import Path
 
+import chess
 import numpy as np
 
-from cattus_train.chess import Chess
+from cattus_train.chess import Chess, alphazero_move_to_nn_idx
 from cattus_train.data_set import DataSet
 from cattus_train.hex import Hex
 from cattus_train.tictactoe import TicTacToe
@@ -92,6 +93,57 @@ def test_chess_serialize_encode():
     )
 
 
+def test_chess_policy_maps():
+    # The same indices are checked by the engine
+    for m, idx in [
+        ("e2e4", 12 + 64),
+        ("g1f3", 6 + 63 * 64),
+        ("b1c3", 1 + 56 * 64),
+        ("h1a1", 7 + 6 * 7 * 64 + 6 * 64),
+        ("a1h8", 13 * 64),
+        ("e7e8q", 52),
+        ("d7c8n", 51 + 64 * 64),
+        ("d7d8b", 51 + 68 * 64),
+        ("d7e8r", 51 + 72 * 64),
+    ]:
+        assert alphazero_move_to_nn_idx(chess.Move.from_uci(m)) == idx, m
+
+    positions = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1",
+    ]
+    for policy_map in Chess.POLICY_MAPS:
+        game = Chess(policy_map=policy_map)
+        for position in positions:
+            with tempfile.TemporaryDirectory() as tmp_dir:
+                serialize_file = Path(tmp_dir) / "serialize_res.json"
+                subprocess.check_call(
+                    [
+                        "cargo",
+                        "run",
+                        "--profile=dev",
+                        "-q",
+                        "--features=onnx-ort",
+                        "--bin=test_serialize",
+                        "--",
+                        "--game=chess",
+                        f"--position={position}",
+                        f"--outfile={serialize_file}",
+                        f"--policy-map={policy_map}",
+                    ],
+                    cwd=SELF_PLAY_TOP,
+                )
+                entry = game.load_data_entry(serialize_file)
+
+            # The serialized moves are exactly the legal moves
+            legal_indices = {game.move_to_nn_idx(m) for m in chess.Board(position).legal_moves}
+            serialized_indices = set(np.where(entry.probs >= 0)[0].tolist())
+            if legal_indices != serialized_indices:
+                raise ValueError(f"Policy indices mismatch (Policy map: {policy_map}, Position: {position})")
+            assert all(game.NN_INDEX_TO_MOVE[idx] is not None for idx in legal_indices)
+
+
 def _test_serialize_encode(game_name: str, game: Game, positions):
     for position in positions:
         with tempfile.TemporaryDirectory() as tmp_dir:
@@ -155,4 +207,5 @@ if __name__ == "__main__":
     test_ttt_serialize_encode()
     test_hex_serialize_encode()
     test_chess_serialize_encode()
+    test_chess_policy_maps()
     logging.info("test passed")